@@ -59,6 +59,10 @@ struct Args {
     decoder_path: Option<OsString>,
     #[clap(long, default_value = "rgb(255,255,255)")]
     bg_color: OsString,
+    /// Reads the packed colors of PCD files written by earlier versions of vvtk,
+    /// which have red and blue swapped otherwise
+    #[clap(long)]
+    legacy_rgba: bool,
 }
 
 #[derive(clap::ValueEnum, Clone, Copy)]
@@ -69,7 +73,7 @@ enum DecoderType {
 
 fn main() {
     let args: Args = Args::parse();
-    let adaptive_reader = AdaptiveReader::new(&args.src);
+    let adaptive_reader = AdaptiveReader::new(&args.src, args.legacy_rgba);
 
     let camera = Camera::new(
         (args.camera_x, args.camera_y, args.camera_z),
@@ -0,0 +1,127 @@
+use kiddo::distance::squared_euclidean;
+use rayon::prelude::*;
+
+use crate::metrics::{build_tree, is_finite};
+
+/// How outliers are told apart from the surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutlierFilter {
+    /// Removes the points whose mean distance to their `k` nearest neighbors is more than
+    /// `std_ratio` standard deviations above the mean over the whole frame
+    Statistical { k: usize, std_ratio: f32 },
+    /// Removes the points with fewer than `min_neighbors` other points within `radius`
+    Radius { radius: f32, min_neighbors: usize },
+}
+
+/// Whether each point is kept by the filter, in the order of `positions`.
+/// Points with a NaN or infinite coordinate are removed.
+pub fn inliers(positions: &[[f32; 3]], filter: OutlierFilter) -> Vec<bool> {
+    if positions.is_empty() {
+        return vec![];
+    }
+    let tree = build_tree(positions.iter().copied());
+    match filter {
+        OutlierFilter::Statistical { k, std_ratio } => {
+            // !! the nearest point is the point itself
+            let mean_distances = positions
+                .par_iter()
+                .map(|p| {
+                    if !is_finite(p) {
+                        return None;
+                    }
+                    let neighbors = tree.nearest(p, k + 1, &squared_euclidean).ok()?;
+                    let distances = neighbors.iter().skip(1).map(|(d, _)| d.sqrt());
+                    let n = neighbors.len().saturating_sub(1).max(1);
+                    Some(distances.sum::<f32>() / n as f32)
+                })
+                .collect::<Vec<_>>();
+            let finite = mean_distances.iter().flatten();
+            let n = finite.clone().count().max(1) as f32;
+            let mean = finite.clone().sum::<f32>() / n;
+            let variance = finite.map(|d| (d - mean) * (d - mean)).sum::<f32>() / n;
+            let threshold = mean + std_ratio * variance.sqrt();
+            mean_distances
+                .iter()
+                .map(|d| matches!(d, Some(d) if *d <= threshold))
+                .collect()
+        }
+        OutlierFilter::Radius {
+            radius,
+            min_neighbors,
+        } => positions
+            .par_iter()
+            .map(|p| {
+                if !is_finite(p) {
+                    return false;
+                }
+                let within = tree.within(p, radius * radius, &squared_euclidean);
+                matches!(within, Ok(within) if within.len().saturating_sub(1) >= min_neighbors)
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A 5x5 grid with a spacing of 1 and a point floating far above it
+    fn grid_with_speckle() -> Vec<[f32; 3]> {
+        let mut positions = vec![];
+        for i in 0..5 {
+            for j in 0..5 {
+                positions.push([i as f32, j as f32, 0.0]);
+            }
+        }
+        positions.push([2.0, 2.0, 10.0]);
+        positions
+    }
+
+    #[test]
+    fn test_statistical() {
+        let kept = inliers(
+            &grid_with_speckle(),
+            OutlierFilter::Statistical {
+                k: 4,
+                std_ratio: 2.0,
+            },
+        );
+        assert_eq!(kept.iter().filter(|&&k| k).count(), 25);
+        assert!(!kept[25]);
+    }
+
+    #[test]
+    fn test_radius() {
+        let filter = |min_neighbors| OutlierFilter::Radius {
+            radius: 1.0,
+            min_neighbors,
+        };
+        let kept = inliers(&grid_with_speckle(), filter(2));
+        assert_eq!(kept.iter().filter(|&&k| k).count(), 25);
+        assert!(!kept[25]);
+
+        // !! corners only have two neighbors at a distance of 1
+        let kept = inliers(&grid_with_speckle(), filter(3));
+        assert_eq!(kept.iter().filter(|&&k| k).count(), 21);
+    }
+
+    #[test]
+    fn test_non_finite_points_are_removed() {
+        let mut positions = grid_with_speckle();
+        positions.push([f32::NAN, 0.0, 0.0]);
+        positions.push([0.0, f32::INFINITY, 0.0]);
+        let statistical = OutlierFilter::Statistical {
+            k: 4,
+            std_ratio: 2.0,
+        };
+        let radius = OutlierFilter::Radius {
+            radius: 1.0,
+            min_neighbors: 2,
+        };
+        for filter in [statistical, radius] {
+            let kept = inliers(&positions, filter);
+            assert_eq!(kept.iter().filter(|&&k| k).count(), 25);
+            assert!(!kept[26] && !kept[27]);
+        }
+    }
+}
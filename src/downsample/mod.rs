@@ -1 +1,3 @@
 pub mod octree;
+pub mod sample;
+pub mod voxel;
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use rayon::prelude::*;
+
+use crate::metrics::is_finite;
+
+/// How the points to keep are chosen
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Uniformly at random
+    #[default]
+    Random,
+    /// One point per cell of a grid fitted to the count, dense areas lose more points
+    Voxel,
+    /// Each point is the farthest from the ones already picked, slow for large counts
+    Farthest,
+}
+
+/// SplitMix64, small and with a fixed output for a given seed whatever the dependency versions
+pub struct SeededRng(u64);
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`, `n` must not be 0
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() as u128 * n as u128) >> 64) as usize
+    }
+}
+
+/// Indices of `count` positions chosen with `strategy`, in increasing order.
+/// Every index is returned if there are no more than `count` positions.
+pub fn sample(positions: &[[f32; 3]], count: usize, strategy: Strategy, seed: u64) -> Vec<usize> {
+    if count >= positions.len() {
+        return (0..positions.len()).collect();
+    }
+    let mut rng = SeededRng::new(seed);
+    let mut indices = match strategy {
+        Strategy::Random => random(positions.len(), count, &mut rng),
+        Strategy::Voxel => voxel(positions, count, &mut rng),
+        Strategy::Farthest => farthest(positions, count, &mut rng),
+    };
+    indices.sort_unstable();
+    indices
+}
+
+/// Partial Fisher-Yates shuffle
+fn random(n: usize, count: usize, rng: &mut SeededRng) -> Vec<usize> {
+    let mut indices = (0..n).collect::<Vec<_>>();
+    for i in 0..count {
+        let j = i + rng.below(n - i);
+        indices.swap(i, j);
+    }
+    indices.truncate(count);
+    indices
+}
+
+/// One index per occupied cell of a grid of side `size`, the first point seen in each cell
+fn one_per_cell(positions: &[[f32; 3]], min: [f32; 3], size: f32) -> Vec<usize> {
+    let mut cells: HashMap<[i64; 3], usize> = HashMap::new();
+    for (idx, p) in positions.iter().enumerate() {
+        let cell = [0, 1, 2].map(|i| ((p[i] - min[i]) / size).floor() as i64);
+        cells.entry(cell).or_insert(idx);
+    }
+    cells.into_values().collect()
+}
+
+fn voxel(positions: &[[f32; 3]], count: usize, rng: &mut SeededRng) -> Vec<usize> {
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for p in positions {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    }
+    let longest = (0..3).map(|i| max[i] - min[i]).fold(0.0, f32::max);
+    if longest <= 0.0 {
+        return random(positions.len(), count, rng);
+    }
+
+    // !! the number of occupied cells grows as the cells shrink, look for the largest cells
+    // giving at least `count` of them, then drop the extra cells at random
+    let (mut large, mut small) = (longest, longest / (count as f32).cbrt() / 64.0);
+    let mut best = one_per_cell(positions, min, small);
+    best.sort_unstable();
+    if best.len() < count {
+        // !! duplicate or nearly duplicate positions, not enough distinct cells,
+        // the missing points are taken at random among the others
+        let mut is_picked = vec![false; positions.len()];
+        best.iter().for_each(|&i| is_picked[i] = true);
+        let rest = (0..positions.len())
+            .filter(|&i| !is_picked[i])
+            .collect::<Vec<_>>();
+        let missing = random(rest.len(), count - best.len(), rng);
+        best.extend(missing.into_iter().map(|i| rest[i]));
+        return best;
+    }
+    for _ in 0..32 {
+        let size = (large + small) / 2.0;
+        let cells = one_per_cell(positions, min, size);
+        if cells.len() >= count {
+            small = size;
+            best = cells;
+        } else {
+            large = size;
+        }
+    }
+    // !! `HashMap` iteration order is not reproducible
+    best.sort_unstable();
+    random(best.len(), count, rng)
+        .into_iter()
+        .map(|i| best[i])
+        .collect()
+}
+
+/// Positions with a NaN or infinite coordinate are never picked,
+/// fewer than `count` indices are returned when there are not enough finite positions
+fn farthest(positions: &[[f32; 3]], count: usize, rng: &mut SeededRng) -> Vec<usize> {
+    let squared_distance =
+        |a: &[f32; 3], b: &[f32; 3]| (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum::<f32>();
+    // !! the positions already picked and the non-finite ones are at -inf, out of the candidates
+    let mut distances = positions
+        .iter()
+        .map(|p| {
+            if is_finite(p) {
+                f32::INFINITY
+            } else {
+                f32::NEG_INFINITY
+            }
+        })
+        .collect::<Vec<_>>();
+    let candidates = distances.iter().filter(|d| **d == f32::INFINITY).count();
+    let count = count.min(candidates);
+    let mut picked = Vec::with_capacity(count);
+    if count == 0 {
+        return picked;
+    }
+    let mut next = distances
+        .iter()
+        .enumerate()
+        .filter(|(_, d)| **d == f32::INFINITY)
+        .nth(rng.below(candidates))
+        .expect("the first point is one of the candidates")
+        .0;
+    while picked.len() < count {
+        picked.push(next);
+        distances[next] = f32::NEG_INFINITY;
+        let last = positions[next];
+        distances
+            .par_iter_mut()
+            .zip(positions.par_iter())
+            .for_each(|(d, p)| *d = d.min(squared_distance(p, &last)));
+        // !! ties go to the lowest index so that the result does not depend on the threads
+        next = distances
+            .iter()
+            .enumerate()
+            .fold((0, f32::NEG_INFINITY), |(best, best_d), (i, &d)| {
+                if d > best_d {
+                    (i, d)
+                } else {
+                    (best, best_d)
+                }
+            })
+            .0;
+    }
+    picked
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn line(n: usize) -> Vec<[f32; 3]> {
+        (0..n).map(|i| [i as f32, 0.0, 0.0]).collect()
+    }
+
+    #[test]
+    fn test_exact_count_and_seed() {
+        let positions = line(1000);
+        for strategy in [Strategy::Random, Strategy::Voxel, Strategy::Farthest] {
+            let sampled = sample(&positions, 100, strategy, 7);
+            assert_eq!(sampled.len(), 100, "{:?}", strategy);
+            assert!(sampled.windows(2).all(|w| w[0] < w[1]), "{:?}", strategy);
+            assert_eq!(
+                sampled,
+                sample(&positions, 100, strategy, 7),
+                "{:?}",
+                strategy
+            );
+        }
+        assert_ne!(
+            sample(&positions, 100, Strategy::Random, 7),
+            sample(&positions, 100, Strategy::Random, 8)
+        );
+        assert_eq!(sample(&positions, 2000, Strategy::Random, 7).len(), 1000);
+    }
+
+    #[test]
+    fn test_farthest_spreads_points() {
+        let positions = [0.0, 100.0, 49.0, 50.0, 51.0].map(|x| [x, 0.0, 0.0]);
+        // !! whatever the first point, the two ends of the line are picked
+        for seed in 0..10 {
+            let sampled = sample(&positions, 3, Strategy::Farthest, seed);
+            assert!(sampled.contains(&0) && sampled.contains(&1));
+        }
+    }
+
+    #[test]
+    fn test_farthest_with_duplicates_and_nan() {
+        let mut positions = vec![[0.0, 0.0, 0.0]; 6];
+        positions.push([f32::NAN, 0.0, 0.0]);
+        positions.push([1.0, f32::INFINITY, 0.0]);
+        positions.push([1.0, 0.0, 0.0]);
+        for seed in 0..10 {
+            let sampled = sample(&positions, 5, Strategy::Farthest, seed);
+            assert_eq!(sampled.len(), 5);
+            assert!(sampled.windows(2).all(|w| w[0] < w[1]), "{:?}", sampled);
+            assert!(!sampled.contains(&6) && !sampled.contains(&7));
+        }
+        // !! only 7 finite positions
+        assert_eq!(sample(&positions, 8, Strategy::Farthest, 1).len(), 7);
+    }
+
+    #[test]
+    fn test_voxel_with_duplicates() {
+        let mut positions = vec![[0.0, 0.0, 0.0]; 10];
+        positions.extend(line(5));
+        assert_eq!(sample(&positions, 12, Strategy::Voxel, 1).len(), 12);
+    }
+}
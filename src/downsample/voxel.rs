@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use crate::formats::{
+    pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud,
+};
+
+/// How the colors of the points falling in the same voxel are combined
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorReduction {
+    /// Average of every channel, rounded
+    #[default]
+    Mean,
+    /// Median of every channel, taken separately
+    Median,
+    /// Color of the first point in the voxel
+    First,
+}
+
+/// Size and placement of the voxels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Grid {
+    /// `2^bits` voxels along the longest side of the bounding box of the frame,
+    /// points are moved to integer coordinates from 0 to `2^bits - 1`
+    Bits(u32),
+    /// Same as `Bits` with a given bounding box, as `[min, max]`, so that every frame of a
+    /// sequence is quantized on the same grid. Points outside the box are outside `0..2^bits`.
+    BitsInBox(u32, [[f32; 3]; 2]),
+    /// Voxels of the given size aligned on the origin, points are moved to the center of their voxel
+    Size(f32),
+}
+
+/// Maps positions to voxel indices and voxel indices back to positions
+struct Quantizer {
+    origin: [f32; 3],
+    size: f32,
+    /// the voxel `[i, j, k]` is written at `[i, j, k] * output_size`
+    output_size: f32,
+}
+
+impl Quantizer {
+    fn new(grid: Grid, positions: &[[f32; 3]]) -> Self {
+        match grid {
+            Grid::Bits(bits) => {
+                Self::new(Grid::BitsInBox(bits, bounding_box(positions)), positions)
+            }
+            Grid::BitsInBox(bits, [min, max]) => {
+                let longest = (0..3).map(|i| max[i] - min[i]).fold(0.0, f32::max);
+                let steps = ((1u64 << bits) - 1) as f32;
+                // !! a frame with a single position is put at the origin
+                let size = if longest > 0.0 { longest / steps } else { 1.0 };
+                Self {
+                    origin: min,
+                    size,
+                    output_size: 1.0,
+                }
+            }
+            Grid::Size(size) => Self {
+                origin: [0.0; 3],
+                size,
+                output_size: size,
+            },
+        }
+    }
+
+    fn voxel(&self, p: &[f32; 3]) -> [i64; 3] {
+        [0, 1, 2].map(|i| ((p[i] - self.origin[i]) / self.size).round() as i64)
+    }
+
+    fn position(&self, voxel: &[i64; 3]) -> [f32; 3] {
+        voxel.map(|v| v as f32 * self.output_size)
+    }
+}
+
+/// `[min, max]` of the positions
+pub fn bounding_box(positions: &[[f32; 3]]) -> [[f32; 3]; 2] {
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for p in positions {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    }
+    [min, max]
+}
+
+/// Indices of the points in every occupied voxel, in the order the voxels are first seen
+fn group_by_voxel(quantizer: &Quantizer, positions: &[[f32; 3]]) -> Vec<([i64; 3], Vec<usize>)> {
+    let mut slots: HashMap<[i64; 3], usize> = HashMap::new();
+    let mut groups: Vec<([i64; 3], Vec<usize>)> = vec![];
+    for (idx, p) in positions.iter().enumerate() {
+        let voxel = quantizer.voxel(p);
+        let slot = *slots.entry(voxel).or_insert_with(|| {
+            groups.push((voxel, vec![]));
+            groups.len() - 1
+        });
+        groups[slot].1.push(idx);
+    }
+    groups
+}
+
+fn reduce_channel(values: &mut [u8], reduction: ColorReduction) -> u8 {
+    match reduction {
+        ColorReduction::Mean => {
+            let sum: u64 = values.iter().map(|&v| v as u64).sum();
+            ((sum as f64 / values.len() as f64).round()) as u8
+        }
+        ColorReduction::Median => {
+            values.sort_unstable();
+            values[values.len() / 2]
+        }
+        ColorReduction::First => values[0],
+    }
+}
+
+fn reduce_color(colors: &[[u8; 4]], reduction: ColorReduction) -> [u8; 4] {
+    let mut channel = Vec::with_capacity(colors.len());
+    [0, 1, 2, 3].map(|c| {
+        channel.clear();
+        channel.extend(colors.iter().map(|color| color[c]));
+        reduce_channel(&mut channel, reduction)
+    })
+}
+
+/// Quantizes the positions to the grid and merges the points falling in the same voxel
+pub fn voxelize(
+    pc: &PointCloud<PointXyzRgba>,
+    grid: Grid,
+    colors: ColorReduction,
+) -> PointCloud<PointXyzRgba> {
+    let positions = pc
+        .points
+        .iter()
+        .map(|p| [p.x, p.y, p.z])
+        .collect::<Vec<_>>();
+    let quantizer = Quantizer::new(grid, &positions);
+    let points = group_by_voxel(&quantizer, &positions)
+        .into_iter()
+        .map(|(voxel, indices)| {
+            let [x, y, z] = quantizer.position(&voxel);
+            let group = indices
+                .iter()
+                .map(|&i| {
+                    let p = &pc.points[i];
+                    [p.r, p.g, p.b, p.a]
+                })
+                .collect::<Vec<_>>();
+            let [r, g, b, a] = reduce_color(&group, colors);
+            PointXyzRgba {
+                x,
+                y,
+                z,
+                r,
+                g,
+                b,
+                a,
+            }
+        })
+        .collect::<Vec<_>>();
+    PointCloud {
+        number_of_points: points.len(),
+        points,
+    }
+}
+
+/// Same as `voxelize`, the normals of the merged points are averaged and normalized
+pub fn voxelize_with_normals(
+    pc: &PointCloud<PointXyzRgbaNormal>,
+    grid: Grid,
+    colors: ColorReduction,
+) -> PointCloud<PointXyzRgbaNormal> {
+    let positions = pc
+        .points
+        .iter()
+        .map(|p| [p.x, p.y, p.z])
+        .collect::<Vec<_>>();
+    let quantizer = Quantizer::new(grid, &positions);
+    let points = group_by_voxel(&quantizer, &positions)
+        .into_iter()
+        .map(|(voxel, indices)| {
+            let [x, y, z] = quantizer.position(&voxel);
+            let group = indices
+                .iter()
+                .map(|&i| {
+                    let p = &pc.points[i];
+                    [p.r, p.g, p.b, p.a]
+                })
+                .collect::<Vec<_>>();
+            let [r, g, b, a] = reduce_color(&group, colors);
+
+            let mut normal = [0.0f32; 3];
+            for &i in &indices {
+                let p = &pc.points[i];
+                normal[0] += p.nx;
+                normal[1] += p.ny;
+                normal[2] += p.nz;
+            }
+            let norm = normal.iter().map(|n| n * n).sum::<f32>().sqrt();
+            // !! opposite normals cancel out, the first one is kept instead
+            let [nx, ny, nz] = if norm > f32::EPSILON {
+                normal.map(|n| n / norm)
+            } else {
+                let first = &pc.points[indices[0]];
+                [first.nx, first.ny, first.nz]
+            };
+            PointXyzRgbaNormal {
+                x,
+                y,
+                z,
+                r,
+                g,
+                b,
+                a,
+                nx,
+                ny,
+                nz,
+            }
+        })
+        .collect::<Vec<_>>();
+    PointCloud {
+        number_of_points: points.len(),
+        points,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn point(x: f32, y: f32, z: f32, r: u8) -> PointXyzRgba {
+        PointXyzRgba {
+            x,
+            y,
+            z,
+            r,
+            g: 0,
+            b: 0,
+            a: 255,
+        }
+    }
+
+    fn cloud<T>(points: Vec<T>) -> PointCloud<T> {
+        PointCloud {
+            number_of_points: points.len(),
+            points,
+        }
+    }
+
+    #[test]
+    fn test_voxel_size() {
+        let pc = cloud(vec![
+            point(0.1, 0.0, 0.0, 10),
+            point(-0.2, 0.1, 0.0, 20),
+            point(0.0, 0.0, 0.0, 90),
+            point(1.1, 0.0, 0.0, 40),
+        ]);
+        let voxelized = voxelize(&pc, Grid::Size(1.0), ColorReduction::Mean);
+        assert_eq!(voxelized.number_of_points, 2);
+        assert_eq!(voxelized.points[0], point(0.0, 0.0, 0.0, 40));
+        assert_eq!(voxelized.points[1], point(1.0, 0.0, 0.0, 40));
+
+        let voxelized = voxelize(&pc, Grid::Size(1.0), ColorReduction::Median);
+        assert_eq!(voxelized.points[0].r, 20);
+        let voxelized = voxelize(&pc, Grid::Size(1.0), ColorReduction::First);
+        assert_eq!(voxelized.points[0].r, 10);
+    }
+
+    #[test]
+    fn test_bits() {
+        let pc = cloud(vec![
+            point(100.0, 100.0, 100.0, 0),
+            point(100.4, 100.0, 100.0, 0),
+            point(300.0, 150.0, 100.0, 0),
+        ]);
+        // !! 3 voxels along x for 200 units, one every 100
+        let voxelized = voxelize(&pc, Grid::Bits(2), ColorReduction::Mean);
+        assert_eq!(voxelized.number_of_points, 2);
+        assert_eq!(voxelized.points[0], point(0.0, 0.0, 0.0, 0));
+        assert_eq!(voxelized.points[1], point(3.0, 1.0, 0.0, 0));
+
+        // !! the same points on the grid of a box twice as large
+        let grid = Grid::BitsInBox(2, [[100.0; 3], [500.0, 100.0, 100.0]]);
+        let voxelized = voxelize(&pc, grid, ColorReduction::Mean);
+        assert_eq!(voxelized.points[0], point(0.0, 0.0, 0.0, 0));
+        assert_eq!(voxelized.points[1], point(2.0, 0.0, 0.0, 0));
+    }
+
+    #[test]
+    fn test_normals_are_averaged() {
+        let with_normal = |x: f32, nx: f32, ny: f32| PointXyzRgbaNormal {
+            x,
+            y: 0.0,
+            z: 0.0,
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+            nx,
+            ny,
+            nz: 0.0,
+        };
+        let pc = cloud(vec![with_normal(0.0, 1.0, 0.0), with_normal(0.1, 0.0, 1.0)]);
+        let voxelized = voxelize_with_normals(&pc, Grid::Size(1.0), ColorReduction::Mean);
+        assert_eq!(voxelized.number_of_points, 1);
+        let p = voxelized.points[0];
+        assert!((p.nx - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!((p.ny - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+    }
+}
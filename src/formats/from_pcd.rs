@@ -0,0 +1,341 @@
+use crate::pcd::{PCDFieldDataType, PCDHeader, PCDReadError, PointCloudData};
+
+use super::{pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud};
+
+/// Position of a field in the bytes of a point, only its first value is read
+struct Field {
+    offset: usize,
+    data_type: PCDFieldDataType,
+}
+
+impl Field {
+    /// The first field of the header with one of the names
+    fn find(header: &PCDHeader, names: &[&str]) -> Option<Self> {
+        let mut offset = 0;
+        for field in header.fields() {
+            if names.contains(&field.name()) {
+                return Some(Self {
+                    offset,
+                    data_type: field.data_type(),
+                });
+            }
+            offset += field.size() as usize * field.count() as usize;
+        }
+        None
+    }
+
+    fn require(header: &PCDHeader, names: &[&str]) -> Result<Self, PCDReadError> {
+        Self::find(header, names).ok_or_else(|| PCDReadError::MissingField {
+            field: names.join(" or "),
+            available: header
+                .fields()
+                .iter()
+                .map(|f| f.name().to_string())
+                .collect(),
+        })
+    }
+
+    fn bytes<const N: usize>(&self, point: &[u8]) -> [u8; N] {
+        point[self.offset..self.offset + N]
+            .try_into()
+            .expect("Fields should lie within the point")
+    }
+
+    /// The value converted to f32, whatever its type
+    fn read_f32(&self, point: &[u8]) -> f32 {
+        use PCDFieldDataType::*;
+
+        match self.data_type {
+            U8 => point[self.offset] as f32,
+            I8 => point[self.offset] as i8 as f32,
+            U16 => u16::from_ne_bytes(self.bytes(point)) as f32,
+            I16 => i16::from_ne_bytes(self.bytes(point)) as f32,
+            U32 => u32::from_ne_bytes(self.bytes(point)) as f32,
+            I32 => i32::from_ne_bytes(self.bytes(point)) as f32,
+            F32 => f32::from_ne_bytes(self.bytes(point)),
+            F64 => f64::from_ne_bytes(self.bytes(point)) as f32,
+        }
+    }
+
+    /// The value clamped to a color channel
+    fn read_u8(&self, point: &[u8]) -> u8 {
+        match self.data_type {
+            PCDFieldDataType::U8 => point[self.offset],
+            _ => self.read_f32(point).round().clamp(0.0, 255.0) as u8,
+        }
+    }
+
+    /// The 32 bits of a packed color, PCL stores them in a float or an unsigned integer
+    fn read_bits(&self, point: &[u8]) -> u32 {
+        u32::from_ne_bytes(self.bytes(point))
+    }
+}
+
+/// Where the color of a point is stored
+enum Color {
+    /// `rgb` or `rgba` packed as 0xAARRGGBB, the alpha of `rgb` is ignored
+    Packed { field: Field, alpha: bool },
+    /// One field per channel
+    Channels {
+        r: Field,
+        g: Field,
+        b: Field,
+        a: Option<Field>,
+    },
+    /// No color fields, the points are white
+    None,
+}
+
+impl Color {
+    fn find(header: &PCDHeader) -> Result<Self, PCDReadError> {
+        for (name, alpha) in [("rgba", true), ("rgb", false)] {
+            if let Some(field) = Field::find(header, &[name]) {
+                return match field.data_type {
+                    PCDFieldDataType::F32 | PCDFieldDataType::U32 | PCDFieldDataType::I32 => {
+                        Ok(Color::Packed { field, alpha })
+                    }
+                    data_type => Err(PCDReadError::InvalidField {
+                        field: name.to_string(),
+                        error_msg: format!(
+                            "Packed colors should be 4 byte floats or integers, got {:?}",
+                            data_type
+                        ),
+                    }),
+                };
+            }
+        }
+        let channel = |names: &[&str]| Field::find(header, names);
+        match (
+            channel(&["r", "red"]),
+            channel(&["g", "green"]),
+            channel(&["b", "blue"]),
+        ) {
+            (Some(r), Some(g), Some(b)) => Ok(Color::Channels {
+                r,
+                g,
+                b,
+                a: channel(&["a", "alpha"]),
+            }),
+            _ => Ok(Color::None),
+        }
+    }
+
+    fn read(&self, point: &[u8]) -> [u8; 4] {
+        match self {
+            Color::Packed { field, alpha } => {
+                let [b, g, r, a] = field.read_bits(point).to_le_bytes();
+                [r, g, b, if *alpha { a } else { 255 }]
+            }
+            Color::Channels { r, g, b, a } => [
+                r.read_u8(point),
+                g.read_u8(point),
+                b.read_u8(point),
+                a.as_ref().map_or(255, |a| a.read_u8(point)),
+            ],
+            Color::None => [255; 4],
+        }
+    }
+}
+
+/// Bytes of every point, with the fields holding positions and colors
+fn points_of(
+    pcd: &PointCloudData,
+) -> Result<(impl Iterator<Item = &[u8]> + '_, [Field; 3], Color), PCDReadError> {
+    let header = pcd.header();
+    let position = [
+        Field::require(header, &["x"])?,
+        Field::require(header, &["y"])?,
+        Field::require(header, &["z"])?,
+    ];
+    let color = Color::find(header)?;
+    Ok((pcd.data().chunks_exact(point_size(pcd)), position, color))
+}
+
+fn point_size(pcd: &PointCloudData) -> usize {
+    // !! the length of the data is checked against the header by `PointCloudData::new`
+    (pcd.data().len() / (pcd.header().points() as usize).max(1)).max(1)
+}
+
+/// Rewrites the packed colors of PCD files written by vvtk before they followed PCL, which hold
+/// the bytes r, g, b, a in memory order and so read with red and blue swapped.
+/// Data without a packed color is returned as it is.
+pub fn from_legacy_rgba(mut pcd: PointCloudData) -> PointCloudData {
+    let field = match Color::find(pcd.header()) {
+        Ok(Color::Packed { field, .. }) => field,
+        _ => return pcd,
+    };
+    let point_size = point_size(&pcd);
+    for point in pcd.data.chunks_exact_mut(point_size) {
+        let [r, g, b, a] = field.bytes(point);
+        let packed = u32::from_be_bytes([a, r, g, b]).to_ne_bytes();
+        point[field.offset..field.offset + 4].copy_from_slice(&packed);
+    }
+    pcd
+}
+
+impl TryFrom<PointCloudData> for PointCloud<PointXyzRgba> {
+    type Error = PCDReadError;
+
+    /// Looks up the fields by name. Positions can be of any numeric type, colors are packed
+    /// in `rgba` or `rgb` as PCL does, or stored in `r g b` or `red green blue` fields.
+    /// Points without colors are white.
+    fn try_from(pcd: PointCloudData) -> Result<Self, Self::Error> {
+        let (points, [x, y, z], color) = points_of(&pcd)?;
+        let points = points
+            .map(|point| {
+                let [r, g, b, a] = color.read(point);
+                PointXyzRgba {
+                    x: x.read_f32(point),
+                    y: y.read_f32(point),
+                    z: z.read_f32(point),
+                    r,
+                    g,
+                    b,
+                    a,
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(Self {
+            number_of_points: points.len(),
+            points,
+        })
+    }
+}
+
+impl TryFrom<PointCloudData> for PointCloud<PointXyzRgbaNormal> {
+    type Error = PCDReadError;
+
+    /// Same as for [PointXyzRgba], with normals in `normal_x normal_y normal_z` as PCL writes them
+    /// or in `nx ny nz`
+    fn try_from(pcd: PointCloudData) -> Result<Self, Self::Error> {
+        let header = pcd.header();
+        let nx = Field::require(header, &["normal_x", "nx"])?;
+        let ny = Field::require(header, &["normal_y", "ny"])?;
+        let nz = Field::require(header, &["normal_z", "nz"])?;
+        let (points, [x, y, z], color) = points_of(&pcd)?;
+        let points = points
+            .map(|point| {
+                let [r, g, b, a] = color.read(point);
+                PointXyzRgbaNormal {
+                    x: x.read_f32(point),
+                    y: y.read_f32(point),
+                    z: z.read_f32(point),
+                    r,
+                    g,
+                    b,
+                    a,
+                    nx: nx.read_f32(point),
+                    ny: ny.read_f32(point),
+                    nz: nz.read_f32(point),
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(Self {
+            number_of_points: points.len(),
+            points,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcd::read_pcd;
+
+    #[test]
+    fn test_pcl_fields() {
+        // !! fields in PCL order, with double coordinates and a packed float color
+        let mut bytes = b"VERSION .7\n\
+            FIELDS normal_x normal_y normal_z rgb x y z\n\
+            SIZE 4 4 4 4 8 8 8\n\
+            TYPE F F F F F F F\n\
+            COUNT 1 1 1 1 1 1 1\n\
+            WIDTH 1\n\
+            HEIGHT 1\n\
+            VIEWPOINT 0 0 0 1 0 0 0\n\
+            POINTS 1\n\
+            DATA binary\n"
+            .to_vec();
+        for v in [0.0f32, 0.0, 1.0, f32::from_bits(0x00ff8040)] {
+            bytes.extend_from_slice(&v.to_ne_bytes());
+        }
+        for v in [1.5f64, -2.0, 3.0] {
+            bytes.extend_from_slice(&v.to_ne_bytes());
+        }
+
+        let pc = PointCloud::<PointXyzRgbaNormal>::try_from(read_pcd(&bytes[..]).unwrap()).unwrap();
+        let p = &pc.points[0];
+        assert_eq!([p.x, p.y, p.z], [1.5, -2.0, 3.0]);
+        assert_eq!([p.r, p.g, p.b, p.a], [255, 128, 64, 255]);
+        assert_eq!([p.nx, p.ny, p.nz], [0.0, 0.0, 1.0]);
+
+        let pc = PointCloud::<PointXyzRgba>::try_from(read_pcd(&bytes[..]).unwrap()).unwrap();
+        assert_eq!(pc.number_of_points, 1);
+        assert_eq!([pc.points[0].r, pc.points[0].b], [255, 64]);
+    }
+
+    #[test]
+    fn test_channels_and_missing_fields() {
+        let ascii = b"VERSION .7\n\
+            FIELDS x y z red green blue\n\
+            SIZE 4 4 4 1 1 1\n\
+            TYPE F F F U U U\n\
+            COUNT 1 1 1 1 1 1\n\
+            WIDTH 1\n\
+            HEIGHT 1\n\
+            VIEWPOINT 0 0 0 1 0 0 0\n\
+            POINTS 1\n\
+            DATA ascii\n\
+            1 2 3 10 20 30\n";
+        let pc = PointCloud::<PointXyzRgba>::try_from(read_pcd(&ascii[..]).unwrap()).unwrap();
+        let p = &pc.points[0];
+        assert_eq!([p.r, p.g, p.b, p.a], [10, 20, 30, 255]);
+
+        let err = PointCloud::<PointXyzRgbaNormal>::try_from(read_pcd(&ascii[..]).unwrap());
+        assert!(matches!(
+            err,
+            Err(PCDReadError::MissingField { ref field, .. }) if field == "normal_x or nx"
+        ));
+    }
+
+    #[test]
+    fn test_legacy_rgba() {
+        // !! earlier versions wrote the bytes of the color as they are in `PointXyzRgba`
+        let mut bytes = b"VERSION .7\n\
+            FIELDS x y z rgba\n\
+            SIZE 4 4 4 4\n\
+            TYPE F F F U\n\
+            COUNT 1 1 1 1\n\
+            WIDTH 1\n\
+            HEIGHT 1\n\
+            VIEWPOINT 0 0 0 1 0 0 0\n\
+            POINTS 1\n\
+            DATA binary\n"
+            .to_vec();
+        for v in [1.0f32, 2.0, 3.0] {
+            bytes.extend_from_slice(&v.to_ne_bytes());
+        }
+        bytes.extend_from_slice(&[10, 20, 30, 40]);
+
+        let pcd = from_legacy_rgba(read_pcd(&bytes[..]).unwrap());
+        let pc = PointCloud::<PointXyzRgba>::try_from(pcd).unwrap();
+        let p = &pc.points[0];
+        assert_eq!([p.x, p.y, p.z], [1.0, 2.0, 3.0]);
+        assert_eq!([p.r, p.g, p.b, p.a], [10, 20, 30, 40]);
+
+        let ascii = b"VERSION .7\n\
+            FIELDS x y z r g b\n\
+            SIZE 4 4 4 1 1 1\n\
+            TYPE F F F U U U\n\
+            COUNT 1 1 1 1 1 1\n\
+            WIDTH 1\n\
+            HEIGHT 1\n\
+            VIEWPOINT 0 0 0 1 0 0 0\n\
+            POINTS 1\n\
+            DATA ascii\n\
+            1 2 3 10 20 30\n";
+        let pcd = from_legacy_rgba(read_pcd(&ascii[..]).unwrap());
+        let pc = PointCloud::<PointXyzRgba>::try_from(pcd).unwrap();
+        assert_eq!([pc.points[0].r, pc.points[0].b], [10, 30]);
+    }
+}
@@ -1,11 +1,13 @@
 use serde::Serialize;
 use std::fmt::Debug;
 
-use crate::pcd::PointCloudData;
+use crate::las::LasData;
 use crate::velodyne::{VelodynPoint, VelodyneBinData};
 
 use self::pointxyzrgba::PointXyzRgba;
 
+mod from_pcd;
+pub use self::from_pcd::from_legacy_rgba;
 pub mod pointxyzrgba;
 pub mod pointxyzrgbanormal;
 
@@ -51,23 +53,6 @@ impl Debug for PointCloud<pointxyzrgbanormal::PointXyzRgbaNormal> {
     }
 }
 
-impl<T> From<PointCloudData> for PointCloud<T> {
-    fn from(pcd: PointCloudData) -> Self {
-        let number_of_points = pcd.header.points() as usize;
-
-        let mut v_clone = std::mem::ManuallyDrop::new(pcd.data);
-        let points = unsafe {
-            let factor = v_clone.len() / number_of_points;
-            let capacity = v_clone.capacity() / factor;
-            Vec::from_raw_parts(v_clone.as_mut_ptr() as *mut T, number_of_points, capacity)
-        };
-        Self {
-            number_of_points,
-            points,
-        }
-    }
-}
-
 impl From<tmc2rs::codec::PointSet3> for PointCloud<PointXyzRgba> {
     fn from(point_set: tmc2rs::codec::PointSet3) -> Self {
         let number_of_points = point_set.len();
@@ -105,6 +90,59 @@ impl From<VelodyneBinData> for PointCloud<pointxyzrgba::PointXyzRgba> {
     }
 }
 
+impl From<LasData> for PointCloud<PointXyzRgba> {
+    /// Positions have the scale and offset of the header applied.
+    /// Colors are scaled from 16 to 8 bits, unless the file stores 8 bit values in them as some
+    /// writers do. Points without colors are gray, as bright as their intensity.
+    fn from(las: LasData) -> Self {
+        from_las(las, [0.0; 3])
+    }
+}
+
+impl PointCloud<PointXyzRgba> {
+    /// Same as `from`, with positions relative to the offset of the header: an f32 only keeps
+    /// about 7 digits, less than a centimeter of coordinates in the millions such as UTM ones.
+    /// [crate::las::write_las_with_offset] puts the offset back
+    pub fn from_las_relative_to_offset(las: LasData) -> Self {
+        let offset = las.header.offset;
+        from_las(las, offset)
+    }
+}
+
+fn from_las(las: LasData, origin: [f64; 3]) -> PointCloud<PointXyzRgba> {
+    let channels = las
+        .points
+        .iter()
+        .flat_map(|p| p.color.unwrap_or([p.intensity; 3]))
+        .max()
+        .unwrap_or(0);
+    let shift = if channels > 255 { 8 } else { 0 };
+    let points = las
+        .points
+        .into_iter()
+        .map(|p| {
+            let [r, g, b] = p
+                .color
+                .unwrap_or([p.intensity; 3])
+                .map(|c| (c >> shift) as u8);
+            // !! subtracted in f64, an f32 only has 24 bits for the whole coordinate
+            PointXyzRgba {
+                x: (p.x - origin[0]) as f32,
+                y: (p.y - origin[1]) as f32,
+                z: (p.z - origin[2]) as f32,
+                r,
+                g,
+                b,
+                a: 255,
+            }
+        })
+        .collect::<Vec<_>>();
+    PointCloud {
+        number_of_points: points.len(),
+        points,
+    }
+}
+
 impl From<VelodynPoint> for pointxyzrgba::PointXyzRgba {
     fn from(value: VelodynPoint) -> Self {
         Self {
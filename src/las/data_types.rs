@@ -0,0 +1,81 @@
+use std::fmt::{Debug, Formatter};
+
+/// Header information for the LAS file, only the parts needed to read the points
+#[derive(Debug, Clone, PartialEq)]
+pub struct LasHeader {
+    pub version_major: u8,
+    pub version_minor: u8,
+    /// Point data record format, from 0 to 3 or from 6 to 8
+    pub point_format: u8,
+    /// Size in bytes of a point record, can be larger than the format for extra bytes
+    pub point_record_length: u16,
+    pub offset_to_point_data: u32,
+    pub number_of_points: u64,
+    pub scale: [f64; 3],
+    pub offset: [f64; 3],
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl LasHeader {
+    /// Size in bytes of a record of the format, without extra bytes
+    pub fn format_length(point_format: u8) -> Option<u16> {
+        match point_format {
+            0 => Some(20),
+            1 => Some(28),
+            2 => Some(26),
+            3 => Some(34),
+            6 => Some(30),
+            7 => Some(36),
+            8 => Some(38),
+            _ => None,
+        }
+    }
+
+    /// Offset of the red, green and blue channels in a record of the format
+    pub fn color_offset(point_format: u8) -> Option<usize> {
+        match point_format {
+            2 => Some(20),
+            3 => Some(28),
+            7 | 8 => Some(30),
+            _ => None,
+        }
+    }
+}
+
+/// A point with the scale and offset of the header applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LasPoint {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub intensity: u16,
+    /// 16 bit red, green and blue, for the formats with colors
+    pub color: Option<[u16; 3]>,
+}
+
+/// This struct represents a single .las file
+pub struct LasData {
+    pub(crate) header: LasHeader,
+    pub(crate) points: Vec<LasPoint>,
+}
+
+impl LasData {
+    pub fn new(header: LasHeader, points: Vec<LasPoint>) -> Self {
+        Self { header, points }
+    }
+
+    pub fn header(&self) -> &LasHeader {
+        &self.header
+    }
+
+    pub fn points(&self) -> &[LasPoint] {
+        &self.points
+    }
+}
+
+impl Debug for LasData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LasData: {:?}", self.header)
+    }
+}
@@ -0,0 +1,26 @@
+//! LiDAR point cloud files (LAS)
+//!
+//! This module reads versions 1.2 to 1.4 of the [LAS format](https://www.asprs.org/divisions-committees/lidar-division/laser-las-file-format-exchange-activities)
+//! with point data record formats 0 to 3 and 6 to 8, and writes LAS 1.2 with format 2.
+//! Compressed LAZ files are not supported.
+//!
+//! # Examples
+//!
+//! ## Reading from a file
+//! ```no_run
+//! use vivotk::las::{read_las_file, LasReadError};
+//!
+//! fn main() -> Result<(), LasReadError> {
+//!     let las = read_las_file("example.las")?;
+//!     println!("{}", las.points().len());
+//!     Ok(())
+//! }
+//! ```
+
+mod data_types;
+mod reader;
+mod writer;
+
+pub use data_types::*;
+pub use reader::{read_las, read_las_file, read_las_header, LasReadError};
+pub use writer::{write_las, write_las_file, write_las_file_with_offset, write_las_with_offset};
@@ -0,0 +1,257 @@
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+use std::path::Path;
+use thiserror::Error;
+
+use crate::las::data_types::{LasData, LasHeader, LasPoint};
+
+type Result<T> = std::result::Result<T, LasReadError>;
+
+/// Size of the header of LAS 1.2, the later versions only add to it
+const MIN_HEADER_SIZE: usize = 227;
+/// Size of the header of LAS 1.4, which holds the 64 bit number of points
+const HEADER_SIZE_1_4: usize = 375;
+
+#[derive(Error, Debug)]
+pub enum LasReadError {
+    /// For ease of conversion from IO errors to LasReadError.
+    /// Note that error can still be due to a truncated file.
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+
+    /// Represents an error with the header of the file.
+    #[error("Invalid header: {0}")]
+    InvalidHeader(String),
+
+    /// The point data record format is not one of 0 to 3 or 6 to 8.
+    #[error("Unsupported point data record format {0}, supported formats are 0 to 3 and 6 to 8")]
+    UnsupportedFormat(u8),
+}
+
+/// Reads the LAS file from the reader
+pub fn read_las<R: Read>(r: R) -> Result<LasData> {
+    let mut reader = BufReader::new(r);
+    let header = parse_header(&mut reader)?;
+    let points = parse_points(&mut reader, &header)?;
+    Ok(LasData { header, points })
+}
+
+/// Reads the LAS file directly from a file given the path
+pub fn read_las_file<P: AsRef<Path>>(p: P) -> Result<LasData> {
+    let file = File::open(p)?;
+    read_las(file)
+}
+
+/// Reads only the header of the LAS file
+pub fn read_las_header<P: AsRef<Path>>(p: P) -> Result<LasHeader> {
+    let file = File::open(p)?;
+    parse_header(&mut BufReader::new(file))
+}
+
+fn read_f64s<R: Read>(r: &mut R) -> Result<[f64; 3]> {
+    Ok([
+        r.read_f64::<LittleEndian>()?,
+        r.read_f64::<LittleEndian>()?,
+        r.read_f64::<LittleEndian>()?,
+    ])
+}
+
+/// Reads the header and skips the variable length records up to the point data
+fn parse_header<R: Read>(reader: &mut R) -> Result<LasHeader> {
+    let mut bytes = vec![0u8; MIN_HEADER_SIZE];
+    reader.read_exact(&mut bytes)?;
+    if &bytes[0..4] != b"LASF" {
+        return Err(LasReadError::InvalidHeader(
+            "File signature should be LASF".to_string(),
+        ));
+    }
+
+    let mut r = Cursor::new(&bytes);
+    r.set_position(24);
+    let version_major = r.read_u8()?;
+    let version_minor = r.read_u8()?;
+    r.set_position(94);
+    let header_size = r.read_u16::<LittleEndian>()? as usize;
+    let offset_to_point_data = r.read_u32::<LittleEndian>()?;
+    let _number_of_vlrs = r.read_u32::<LittleEndian>()?;
+    let point_format = r.read_u8()?;
+    let point_record_length = r.read_u16::<LittleEndian>()?;
+    let legacy_number_of_points = r.read_u32::<LittleEndian>()?;
+    r.set_position(131);
+    let scale = read_f64s(&mut r)?;
+    let offset = read_f64s(&mut r)?;
+    let [max_x, min_x, max_y, min_y, max_z, min_z] = [
+        r.read_f64::<LittleEndian>()?,
+        r.read_f64::<LittleEndian>()?,
+        r.read_f64::<LittleEndian>()?,
+        r.read_f64::<LittleEndian>()?,
+        r.read_f64::<LittleEndian>()?,
+        r.read_f64::<LittleEndian>()?,
+    ];
+
+    if header_size < MIN_HEADER_SIZE || (offset_to_point_data as usize) < header_size {
+        return Err(LasReadError::InvalidHeader(format!(
+            "Header size {} and offset to point data {} are inconsistent",
+            header_size, offset_to_point_data
+        )));
+    }
+    // !! the rest of the header of LAS 1.3 and 1.4
+    let mut rest = vec![0u8; header_size - MIN_HEADER_SIZE];
+    reader.read_exact(&mut rest)?;
+    bytes.extend_from_slice(&rest);
+
+    let number_of_points = if version_minor >= 4 && header_size >= HEADER_SIZE_1_4 {
+        let mut r = Cursor::new(&bytes);
+        r.set_position(247);
+        r.read_u64::<LittleEndian>()?
+    } else {
+        legacy_number_of_points as u64
+    };
+
+    // !! the two high bits of the format are set by LAZ compression
+    if point_format & 0xc0 != 0 {
+        return Err(LasReadError::InvalidHeader(
+            "Compressed LAZ files are not supported".to_string(),
+        ));
+    }
+    let format_length = LasHeader::format_length(point_format)
+        .ok_or(LasReadError::UnsupportedFormat(point_format))?;
+    if point_record_length < format_length {
+        return Err(LasReadError::InvalidHeader(format!(
+            "Point record length {} is smaller than the {} bytes of format {}",
+            point_record_length, format_length, point_format
+        )));
+    }
+    if scale.iter().any(|s| *s == 0.0 || !s.is_finite()) {
+        return Err(LasReadError::InvalidHeader(format!(
+            "Invalid scale {:?}",
+            scale
+        )));
+    }
+
+    // !! skips the variable length records
+    let skipped = offset_to_point_data as u64 - header_size as u64;
+    std::io::copy(&mut reader.take(skipped), &mut std::io::sink())?;
+
+    Ok(LasHeader {
+        version_major,
+        version_minor,
+        point_format,
+        point_record_length,
+        offset_to_point_data,
+        number_of_points,
+        scale,
+        offset,
+        min: [min_x, min_y, min_z],
+        max: [max_x, max_y, max_z],
+    })
+}
+
+fn parse_points<R: Read>(reader: &mut R, header: &LasHeader) -> Result<Vec<LasPoint>> {
+    let color_offset = LasHeader::color_offset(header.point_format);
+    let mut record = vec![0u8; header.point_record_length as usize];
+    let mut points = Vec::with_capacity(header.number_of_points.min(1 << 24) as usize);
+    for _ in 0..header.number_of_points {
+        reader.read_exact(&mut record)?;
+        let mut r = Cursor::new(&record);
+        let x = r.read_i32::<LittleEndian>()?;
+        let y = r.read_i32::<LittleEndian>()?;
+        let z = r.read_i32::<LittleEndian>()?;
+        let intensity = r.read_u16::<LittleEndian>()?;
+        let color = match color_offset {
+            Some(at) => {
+                r.set_position(at as u64);
+                Some([
+                    r.read_u16::<LittleEndian>()?,
+                    r.read_u16::<LittleEndian>()?,
+                    r.read_u16::<LittleEndian>()?,
+                ])
+            }
+            None => None,
+        };
+        points.push(LasPoint {
+            x: x as f64 * header.scale[0] + header.offset[0],
+            y: y as f64 * header.scale[1] + header.offset[1],
+            z: z as f64 * header.scale[2] + header.offset[2],
+            intensity,
+            color,
+        });
+    }
+    Ok(points)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use byteorder::WriteBytesExt;
+
+    /// A LAS 1.4 header with one variable length record of 10 bytes before the points
+    fn header_1_4(point_format: u8, point_record_length: u16, points: u64) -> Vec<u8> {
+        let mut bytes = vec![0u8; HEADER_SIZE_1_4];
+        bytes[0..4].copy_from_slice(b"LASF");
+        bytes[24] = 1;
+        bytes[25] = 4;
+        bytes[94..96].copy_from_slice(&(HEADER_SIZE_1_4 as u16).to_le_bytes());
+        bytes[96..100].copy_from_slice(&(HEADER_SIZE_1_4 as u32 + 10).to_le_bytes());
+        bytes[100..104].copy_from_slice(&1u32.to_le_bytes());
+        bytes[104] = point_format;
+        bytes[105..107].copy_from_slice(&point_record_length.to_le_bytes());
+        for (i, v) in [0.01f64, 0.01, 0.1, 100.0, 200.0, 0.0].iter().enumerate() {
+            bytes[131 + 8 * i..139 + 8 * i].copy_from_slice(&v.to_le_bytes());
+        }
+        bytes[247..255].copy_from_slice(&points.to_le_bytes());
+        bytes.extend_from_slice(&[0; 10]);
+        bytes
+    }
+
+    #[test]
+    fn test_read_format_8() {
+        let mut bytes = header_1_4(8, 40, 2);
+        for (x, intensity, red) in [(150, 7u16, 65535u16), (-50, 300, 256)] {
+            bytes.write_i32::<LittleEndian>(x).unwrap();
+            bytes.write_i32::<LittleEndian>(0).unwrap();
+            bytes.write_i32::<LittleEndian>(5).unwrap();
+            bytes.write_u16::<LittleEndian>(intensity).unwrap();
+            bytes.extend_from_slice(&[0; 16]);
+            for channel in [red, 0, 512, 1000] {
+                bytes.write_u16::<LittleEndian>(channel).unwrap();
+            }
+            // !! extra bytes after the record of format 8
+            bytes.extend_from_slice(&[0; 2]);
+        }
+
+        let las = read_las(&bytes[..]).unwrap();
+        assert_eq!(las.header().version_minor, 4);
+        assert_eq!(las.header().number_of_points, 2);
+        let p = las.points()[0];
+        assert!((p.x - 101.5).abs() < 1e-9);
+        assert!((p.y - 200.0).abs() < 1e-9);
+        assert!((p.z - 0.5).abs() < 1e-9);
+        assert_eq!(p.intensity, 7);
+        assert_eq!(p.color, Some([65535, 0, 512]));
+        assert!((las.points()[1].x - 99.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let mut bytes = header_1_4(4, 57, 0);
+        assert!(matches!(
+            read_las(&bytes[..]),
+            Err(LasReadError::UnsupportedFormat(4))
+        ));
+
+        bytes[104] = 6;
+        bytes[105..107].copy_from_slice(&20u16.to_le_bytes());
+        assert!(matches!(
+            read_las(&bytes[..]),
+            Err(LasReadError::InvalidHeader(_))
+        ));
+
+        bytes[0] = b'X';
+        assert!(matches!(
+            read_las(&bytes[..]),
+            Err(LasReadError::InvalidHeader(_))
+        ));
+    }
+}
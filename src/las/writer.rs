@@ -0,0 +1,247 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::formats::{pointxyzrgba::PointXyzRgba, PointCloud};
+use crate::metrics::is_finite;
+
+type IOResult = Result<(), std::io::Error>;
+
+const HEADER_SIZE: u16 = 227;
+/// Format 2 holds the position, intensity and 16 bit colors
+const POINT_FORMAT: u8 = 2;
+const POINT_RECORD_LENGTH: u16 = 26;
+
+/// Writes the point cloud into the file as LAS 1.2 with point format 2
+pub fn write_las_file<P: AsRef<Path>>(pc: &PointCloud<PointXyzRgba>, p: P) -> IOResult {
+    write_las_file_with_offset(pc, [0.0; 3], p)
+}
+
+/// Same as [write_las_file] for positions relative to `offset`, see [write_las_with_offset]
+pub fn write_las_file_with_offset<P: AsRef<Path>>(
+    pc: &PointCloud<PointXyzRgba>,
+    offset: [f64; 3],
+    p: P,
+) -> IOResult {
+    let file = File::create(p)?;
+    let mut writer = BufWriter::new(file);
+    write_las_with_offset(pc, offset, &mut writer)?;
+    writer.flush()
+}
+
+/// Writes the point cloud into the provided writer as LAS 1.2 with point format 2
+pub fn write_las<W: Write>(pc: &PointCloud<PointXyzRgba>, writer: &mut W) -> IOResult {
+    write_las_with_offset(pc, [0.0; 3], writer)
+}
+
+/// Writes positions relative to `offset`, such as those read from a LAS file with that offset
+/// in its header, so that they are written back where they were.
+/// Points with a NaN or infinite coordinate cannot be stored and are left out
+pub fn write_las_with_offset<W: Write>(
+    pc: &PointCloud<PointXyzRgba>,
+    offset: [f64; 3],
+    writer: &mut W,
+) -> IOResult {
+    let points = pc
+        .points
+        .iter()
+        .filter(|p| is_finite(&[p.x, p.y, p.z]))
+        .collect::<Vec<_>>();
+    let number_of_points = u32::try_from(points.len()).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "LAS 1.2 holds at most 2^32 - 1 points",
+        )
+    })?;
+
+    let mut min = [f64::MAX; 3];
+    let mut max = [f64::MIN; 3];
+    for p in &points {
+        for (i, v) in [p.x, p.y, p.z].into_iter().enumerate() {
+            min[i] = min[i].min(v as f64);
+            max[i] = max[i].max(v as f64);
+        }
+    }
+    if points.is_empty() {
+        min = [0.0; 3];
+        max = [0.0; 3];
+    }
+    // !! positions are stored as integers from the offset, the scale keeps them in an i32
+    let scale = [0, 1, 2].map(|i| {
+        let mut scale = 1e-4;
+        while min[i].abs().max(max[i].abs()) / scale > i32::MAX as f64 {
+            scale *= 10.0;
+        }
+        scale
+    });
+    let min = [0, 1, 2].map(|i| min[i] + offset[i]);
+    let max = [0, 1, 2].map(|i| max[i] + offset[i]);
+
+    write_header(writer, number_of_points, scale, offset, min, max)?;
+    for p in points {
+        for (i, v) in [p.x, p.y, p.z].into_iter().enumerate() {
+            writer.write_i32::<LittleEndian>((v as f64 / scale[i]).round() as i32)?;
+        }
+        // !! intensity, then a single return, classification, scan angle, user data and source
+        writer.write_u16::<LittleEndian>(0)?;
+        writer.write_all(&[0b0000_1001, 0, 0, 0])?;
+        writer.write_u16::<LittleEndian>(0)?;
+        // !! 8 bit colors are scaled to the 16 bits of LAS
+        for c in [p.r, p.g, p.b] {
+            writer.write_u16::<LittleEndian>(c as u16 * 257)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_header<W: Write>(
+    writer: &mut W,
+    number_of_points: u32,
+    scale: [f64; 3],
+    offset: [f64; 3],
+    min: [f64; 3],
+    max: [f64; 3],
+) -> IOResult {
+    writer.write_all(b"LASF")?;
+    // !! file source id, global encoding and project id
+    writer.write_all(&[0; 20])?;
+    writer.write_all(&[1, 2])?;
+    let mut system_identifier = [0u8; 32];
+    system_identifier[..5].copy_from_slice(b"OTHER");
+    writer.write_all(&system_identifier)?;
+    let mut generating_software = [0u8; 32];
+    generating_software[..4].copy_from_slice(b"vvtk");
+    writer.write_all(&generating_software)?;
+    // !! creation day of year and year, unknown
+    writer.write_u16::<LittleEndian>(0)?;
+    writer.write_u16::<LittleEndian>(0)?;
+    writer.write_u16::<LittleEndian>(HEADER_SIZE)?;
+    writer.write_u32::<LittleEndian>(HEADER_SIZE as u32)?;
+    // !! no variable length records
+    writer.write_u32::<LittleEndian>(0)?;
+    writer.write_u8(POINT_FORMAT)?;
+    writer.write_u16::<LittleEndian>(POINT_RECORD_LENGTH)?;
+    writer.write_u32::<LittleEndian>(number_of_points)?;
+    // !! every point is a first return
+    writer.write_u32::<LittleEndian>(number_of_points)?;
+    writer.write_all(&[0; 16])?;
+    for v in scale.into_iter().chain(offset) {
+        writer.write_f64::<LittleEndian>(v)?;
+    }
+    for i in 0..3 {
+        writer.write_f64::<LittleEndian>(max[i])?;
+        writer.write_f64::<LittleEndian>(min[i])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::las::read_las;
+
+    #[test]
+    fn test_round_trip() {
+        let points = vec![
+            PointXyzRgba {
+                x: 171.0,
+                y: -63.25,
+                z: 0.5,
+                r: 183,
+                g: 165,
+                b: 155,
+                a: 255,
+            },
+            PointXyzRgba {
+                x: 1.0e6,
+                y: 2.0,
+                z: 3.0,
+                r: 0,
+                g: 255,
+                b: 1,
+                a: 255,
+            },
+        ];
+        let pc = PointCloud {
+            number_of_points: points.len(),
+            points,
+        };
+        let mut bytes = vec![];
+        write_las(&pc, &mut bytes).unwrap();
+        assert_eq!(
+            bytes.len(),
+            HEADER_SIZE as usize + 2 * POINT_RECORD_LENGTH as usize
+        );
+
+        let read: PointCloud<PointXyzRgba> = read_las(&bytes[..]).unwrap().into();
+        assert_eq!(read.number_of_points, 2);
+        for (a, b) in read.points.iter().zip(&pc.points) {
+            assert!((a.x - b.x).abs() < 1e-3);
+            assert!((a.y - b.y).abs() < 1e-3);
+            assert!((a.z - b.z).abs() < 1e-3);
+            assert_eq!([a.r, a.g, a.b, a.a], [b.r, b.g, b.b, b.a]);
+        }
+    }
+
+    #[test]
+    fn test_non_finite_points_are_left_out() {
+        let point = |x: f32| PointXyzRgba {
+            x,
+            y: 1.0,
+            z: 2.0,
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let points = vec![point(f32::INFINITY), point(3.0), point(f32::NAN)];
+        let pc = PointCloud {
+            number_of_points: points.len(),
+            points,
+        };
+        let mut bytes = vec![];
+        write_las(&pc, &mut bytes).unwrap();
+        let las = read_las(&bytes[..]).unwrap();
+        assert!(las.header().scale.iter().all(|s| s.is_finite()));
+        assert_eq!(las.header().max, [3.0, 1.0, 2.0]);
+        assert_eq!(las.points().len(), 1);
+        assert!((las.points()[0].x - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_large_coordinates() {
+        // !! UTM coordinates, an f32 of 4.5e6 is only precise to 0.5
+        let offset = [4.5e6, 5.2e6, 30.0];
+        let pc = PointCloud {
+            number_of_points: 1,
+            points: vec![PointXyzRgba {
+                x: 0.01,
+                y: -0.02,
+                z: 0.5,
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            }],
+        };
+        let mut bytes = vec![];
+        write_las_with_offset(&pc, offset, &mut bytes).unwrap();
+
+        let read: PointCloud<PointXyzRgba> = read_las(&bytes[..]).unwrap().into();
+        assert!((read.points[0].x - 4.5e6).abs() < 1.0);
+
+        let las = read_las(&bytes[..]).unwrap();
+        assert_eq!(las.header().offset, offset);
+        let p = las.points()[0];
+        assert!((p.x - 4_500_000.01).abs() < 1e-6);
+        assert!((p.y - 5_199_999.98).abs() < 1e-6);
+        assert!((p.z - 30.5).abs() < 1e-6);
+
+        let read = PointCloud::from_las_relative_to_offset(las);
+        let p = read.points[0];
+        assert!((p.x - 0.01).abs() < 1e-6);
+        assert!((p.y + 0.02).abs() < 1e-6);
+        assert!((p.z - 0.5).abs() < 1e-6);
+    }
+}
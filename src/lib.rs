@@ -5,18 +5,22 @@ pub mod abr;
 pub mod codec;
 #[cfg(feature = "dash")]
 pub mod dash;
+pub mod denoise;
 pub mod downsample;
 pub mod estimatethroughput;
 pub mod formats;
+pub mod las;
 pub mod metrics;
 pub mod pcd;
 pub mod pipeline;
 pub mod ply;
+pub mod registration;
 pub mod render;
 pub mod upsample;
 pub mod utils;
 pub mod velodyne;
 pub mod vvplay_async_prefetch;
+pub mod xyz;
 
 use formats::{pointxyzrgba::PointXyzRgba, PointCloud};
 
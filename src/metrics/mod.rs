@@ -4,6 +4,7 @@ mod cd_psnr;
 mod hd;
 mod lc_psnr;
 mod psnr;
+mod stats;
 mod vqoe;
 
 use std::{
@@ -21,6 +22,7 @@ use self::cd_psnr::CdPsnr;
 use self::hd::Hd;
 use self::lc_psnr::LcPsnr;
 use self::psnr::Psnr;
+pub use self::stats::calculate_stats;
 use self::vqoe::VQoE;
 
 #[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
@@ -100,23 +102,30 @@ impl Metrics {
     }
 }
 
+/// KdTree over the positions, the data of each point is its index.
+/// Positions with a NaN or infinite coordinate cannot be searched and are left out.
+pub(crate) fn build_tree(positions: impl Iterator<Item = [f32; 3]>) -> KdTree<f32, usize, 3> {
+    let mut tree = KdTree::new();
+    for (i, position) in positions.enumerate() {
+        if is_finite(&position) {
+            tree.add(&position, i).expect("Failed to add to tree");
+        }
+    }
+    tree
+}
+
+/// Whether the position can be added to or searched in a KdTree
+pub(crate) fn is_finite(position: &[f32; 3]) -> bool {
+    position.iter().all(|v| v.is_finite())
+}
+
 pub fn calculate_metrics(
     original: &PointCloud<PointXyzRgba>,
     reconstructed: &PointCloud<PointXyzRgba>,
     metrics: &Vec<SupoportedMetrics>,
 ) -> Metrics {
-    let mut original_tree = KdTree::new();
-    for (i, pt) in original.points.iter().enumerate() {
-        original_tree
-            .add(&[pt.x, pt.y, pt.z], i)
-            .expect("Failed to add to original tree");
-    }
-    let mut reconstructed_tree = KdTree::new();
-    for (i, pt) in reconstructed.points.iter().enumerate() {
-        reconstructed_tree
-            .add(&[pt.x, pt.y, pt.z], i)
-            .expect("Failed to add to original tree");
-    }
+    let original_tree = build_tree(original.points.iter().map(|pt| [pt.x, pt.y, pt.z]));
+    let reconstructed_tree = build_tree(reconstructed.points.iter().map(|pt| [pt.x, pt.y, pt.z]));
 
     let mut metrics_report = Metrics::new();
 
@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+
+use kiddo::distance::squared_euclidean;
+use rayon::prelude::*;
+
+use super::{build_tree, is_finite, Metrics};
+
+/// Statistics of a single frame, vectors are written as space separated values:
+/// `points`, `bbox_min`, `bbox_max`, `centroid`, `spacing` (mean distance from each distinct
+/// position to the nearest other one), `duplicates` (points at the same position as an earlier one)
+/// and for every color channel `{r,g,b}_mean`, `{r,g,b}_std` and `{r,g,b}_histogram` with `bins` counts.
+/// `non_finite` counts the points with a NaN or infinite coordinate, which are left out of the
+/// statistics of the positions; these are missing when no position is finite.
+pub fn calculate_stats(positions: &[[f32; 3]], colors: &[[u8; 3]], bins: usize) -> Metrics {
+    let mut metrics = Metrics::new();
+    metrics.insert("points".to_string(), positions.len().to_string());
+    if positions.is_empty() {
+        return metrics;
+    }
+    let n = positions.len() as f64;
+
+    let finite = positions
+        .iter()
+        .filter(|p| is_finite(p))
+        .copied()
+        .collect::<Vec<_>>();
+    metrics.insert(
+        "non_finite".to_string(),
+        (positions.len() - finite.len()).to_string(),
+    );
+    if !finite.is_empty() {
+        insert_position_stats(&mut metrics, &finite);
+    }
+
+    for (c, channel) in ["r", "g", "b"].iter().enumerate() {
+        let mean = colors.iter().map(|color| color[c] as f64).sum::<f64>() / n;
+        let variance = colors
+            .iter()
+            .map(|color| (color[c] as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        let mut histogram = vec![0usize; bins];
+        for color in colors {
+            histogram[color[c] as usize * bins / 256] += 1;
+        }
+        metrics.insert(format!("{}_mean", channel), format!("{:.5}", mean));
+        metrics.insert(
+            format!("{}_std", channel),
+            format!("{:.5}", variance.sqrt()),
+        );
+        metrics.insert(format!("{}_histogram", channel), join(histogram.iter()));
+    }
+    metrics
+}
+
+/// `bbox_min`, `bbox_max`, `centroid`, `duplicates` and `spacing` of finite `positions`
+fn insert_position_stats(metrics: &mut Metrics, positions: &[[f32; 3]]) {
+    let n = positions.len() as f64;
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    let mut sum = [0.0f64; 3];
+    for p in positions {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+            sum[i] += p[i] as f64;
+        }
+    }
+    metrics.insert("bbox_min".to_string(), join(min.iter()));
+    metrics.insert("bbox_max".to_string(), join(max.iter()));
+    metrics.insert(
+        "centroid".to_string(),
+        join(sum.iter().map(|s| format!("{:.5}", s / n))),
+    );
+
+    // !! -0.0 and 0.0 have different bits but are the same position
+    let mut seen = HashSet::new();
+    let distinct = positions
+        .iter()
+        .filter(|p| seen.insert(p.map(|c| if c == 0.0 { 0 } else { c.to_bits() })))
+        .copied()
+        .collect::<Vec<_>>();
+    metrics.insert(
+        "duplicates".to_string(),
+        (positions.len() - distinct.len()).to_string(),
+    );
+    let spacing = if distinct.len() < 2 {
+        0.0
+    } else {
+        let tree = build_tree(distinct.iter().copied());
+        distinct
+            .par_iter()
+            .map(|p| {
+                // !! the nearest position is the position itself
+                let nearest = tree
+                    .nearest(p, 2, &squared_euclidean)
+                    .expect("Positions should be finite");
+                (nearest[1].0 as f64).sqrt()
+            })
+            .sum::<f64>()
+            / distinct.len() as f64
+    };
+    metrics.insert("spacing".to_string(), format!("{:.5}", spacing));
+}
+
+/// Space separated, the values of a metric cannot contain commas
+fn join<T: ToString>(values: impl Iterator<Item = T>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get(metrics: &Metrics, key: &str) -> String {
+        metrics
+            .metrics()
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .unwrap_or_else(|| panic!("missing {}", key))
+            .1
+    }
+
+    #[test]
+    fn test_stats() {
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [3.0, 2.0, 0.0],
+        ];
+        let colors = [[0, 0, 0], [255, 0, 0], [255, 0, 0], [128, 0, 0]];
+        let stats = calculate_stats(&positions, &colors, 4);
+        assert_eq!(get(&stats, "points"), "4");
+        assert_eq!(get(&stats, "bbox_min"), "0 0 0");
+        assert_eq!(get(&stats, "bbox_max"), "3 2 0");
+        assert_eq!(get(&stats, "centroid"), "1.25000 0.50000 0.00000");
+        assert_eq!(get(&stats, "duplicates"), "1");
+        // !! 1 for the first two distinct positions, sqrt(8) for the last one
+        let spacing = (2.0 + 8f64.sqrt()) / 3.0;
+        assert_eq!(get(&stats, "spacing"), format!("{:.5}", spacing));
+        assert_eq!(get(&stats, "r_mean"), "159.50000");
+        assert_eq!(get(&stats, "r_histogram"), "1 0 1 2");
+        assert_eq!(get(&stats, "g_histogram"), "4 0 0 0");
+        assert_eq!(get(&stats, "g_std"), "0.00000");
+    }
+
+    #[test]
+    fn test_non_finite_positions() {
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [f32::NAN, 0.0, 0.0],
+            [2.0, 0.0, f32::INFINITY],
+            [1.0, 0.0, 0.0],
+        ];
+        let colors = [[0, 0, 0], [8, 0, 0], [16, 0, 0], [24, 0, 0]];
+        let stats = calculate_stats(&positions, &colors, 4);
+        assert_eq!(get(&stats, "points"), "4");
+        assert_eq!(get(&stats, "non_finite"), "2");
+        assert_eq!(get(&stats, "bbox_max"), "1 0 0");
+        assert_eq!(get(&stats, "centroid"), "0.50000 0.00000 0.00000");
+        assert_eq!(get(&stats, "spacing"), "1.00000");
+        assert_eq!(get(&stats, "r_mean"), "12.00000");
+
+        let stats = calculate_stats(&[[f32::NAN; 3]], &[[0, 0, 0]], 4);
+        assert_eq!(get(&stats, "non_finite"), "1");
+        assert!(stats.metrics().iter().all(|(k, _)| k != "spacing"));
+    }
+
+    #[test]
+    fn test_empty_frame() {
+        let stats = calculate_stats(&[], &[], 16);
+        assert_eq!(
+            stats.metrics(),
+            vec![("points".to_string(), "0".to_string())]
+        );
+    }
+}
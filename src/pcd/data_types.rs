@@ -429,7 +429,7 @@ impl ToString for PCDDataType {
         match self {
             Self::Ascii => "ascii",
             Self::Binary => "binary",
-            Self::CompressedBinary => "compressed_binary",
+            Self::CompressedBinary => "binary_compressed",
         }
         .to_string()
     }
@@ -442,7 +442,8 @@ impl FromStr for PCDDataType {
         match s {
             "ascii" => Ok(Self::Ascii),
             "binary" => Ok(Self::Binary),
-            "compressed_binary" => Ok(Self::CompressedBinary),
+            // !! PCL writes binary_compressed, compressed_binary is kept for the command line
+            "binary_compressed" | "compressed_binary" => Ok(Self::CompressedBinary),
             _ => Err(format!("Unknown data type: {s}")),
         }
     }
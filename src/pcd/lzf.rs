@@ -0,0 +1,178 @@
+//! LZF, the compression used by the `binary_compressed` data of PCL.
+//!
+//! A compressed stream is a sequence of chunks starting with a control byte `c`:
+//! - `c < 32`: `c + 1` literal bytes follow
+//! - otherwise a back reference of `(c >> 5) + 2` bytes, or `next byte + 9` bytes if `c >> 5` is 7,
+//!   copied from `((c & 0x1f) << 8) + next byte + 1` bytes back in the output
+
+const HASH_LOG: u32 = 14;
+const MAX_LITERAL: usize = 32;
+const MAX_OFFSET: usize = 1 << 13;
+const MAX_REFERENCE: usize = (1 << 8) + (1 << 3);
+
+fn hash(bytes: &[u8]) -> usize {
+    let v = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+    (v.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+}
+
+/// Compresses `input`, the output can be slightly larger than the input for random data
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 16);
+    // !! positions + 1 of the last 3 bytes with each hash, 0 for none
+    let mut table = vec![0usize; 1 << HASH_LOG];
+    // !! the control byte of the literal run being written
+    let mut literal_at = out.len();
+    out.push(0);
+    let mut literals = 0;
+    let mut ip = 0;
+
+    while ip < input.len() {
+        if ip + 2 < input.len() {
+            let h = hash(&input[ip..]);
+            let candidate = table[h];
+            table[h] = ip + 1;
+            if candidate > 0 {
+                let r = candidate - 1;
+                let offset = ip - r - 1;
+                if offset < MAX_OFFSET && input[r..r + 3] == input[ip..ip + 3] {
+                    let max_len = MAX_REFERENCE.min(input.len() - ip);
+                    let mut len = 3;
+                    while len < max_len && input[r + len] == input[ip + len] {
+                        len += 1;
+                    }
+
+                    if literals > 0 {
+                        out[literal_at] = (literals - 1) as u8;
+                    } else {
+                        out.pop();
+                    }
+                    let encoded = len - 2;
+                    if encoded < 7 {
+                        out.push(((encoded << 5) + (offset >> 8)) as u8);
+                    } else {
+                        out.push(((7 << 5) + (offset >> 8)) as u8);
+                        out.push((encoded - 7) as u8);
+                    }
+                    out.push(offset as u8);
+
+                    // !! the positions inside the match are hashed too, long runs compress better
+                    for p in (ip + 1)..(ip + len).min(input.len() - 2) {
+                        table[hash(&input[p..])] = p + 1;
+                    }
+                    ip += len;
+                    literal_at = out.len();
+                    out.push(0);
+                    literals = 0;
+                    continue;
+                }
+            }
+        }
+
+        out.push(input[ip]);
+        literals += 1;
+        ip += 1;
+        if literals == MAX_LITERAL {
+            out[literal_at] = (MAX_LITERAL - 1) as u8;
+            literal_at = out.len();
+            out.push(0);
+            literals = 0;
+        }
+    }
+
+    if literals > 0 {
+        out[literal_at] = (literals - 1) as u8;
+    } else {
+        out.pop();
+    }
+    out
+}
+
+/// Decompresses `input`, which must expand to exactly `expected_len` bytes
+pub fn decompress(input: &[u8], expected_len: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(expected_len);
+    let mut ip = 0;
+    let truncated = || "LZF data is truncated".to_string();
+
+    while ip < input.len() {
+        let control = input[ip] as usize;
+        ip += 1;
+        if control < 32 {
+            let len = control + 1;
+            let literal = input.get(ip..ip + len).ok_or_else(truncated)?;
+            out.extend_from_slice(literal);
+            ip += len;
+        } else {
+            let mut len = control >> 5;
+            if len == 7 {
+                len += *input.get(ip).ok_or_else(truncated)? as usize;
+                ip += 1;
+            }
+            len += 2;
+            let low = *input.get(ip).ok_or_else(truncated)? as usize;
+            ip += 1;
+            let offset = ((control & 0x1f) << 8) + low + 1;
+            if offset > out.len() {
+                return Err(format!(
+                    "LZF back reference of {} bytes with only {} bytes decompressed",
+                    offset,
+                    out.len()
+                ));
+            }
+            // !! the reference can overlap the bytes being written, copy byte by byte
+            let start = out.len() - offset;
+            for i in 0..len {
+                out.push(out[start + i]);
+            }
+        }
+        if out.len() > expected_len {
+            break;
+        }
+    }
+
+    if out.len() != expected_len {
+        return Err(format!(
+            "LZF data decompressed to {} bytes, expected {}",
+            out.len(),
+            expected_len
+        ));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut inputs: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![7],
+            b"abcabcabcabcabcabcabcabcabcabcabcabcabc".to_vec(),
+            vec![0; 10_000],
+        ];
+        // !! pseudo-random bytes, mostly literals
+        let mut state = 1u32;
+        inputs.push(
+            (0..5000)
+                .map(|_| {
+                    state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                    (state >> 16) as u8
+                })
+                .collect(),
+        );
+        for input in inputs {
+            let compressed = compress(&input);
+            assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+        }
+        assert!(compress(&[0; 10_000]).len() < 200);
+    }
+
+    #[test]
+    fn test_decompress_known() {
+        // !! "aaaaa": a literal "a" then 4 bytes from 1 byte back
+        assert_eq!(decompress(&[0, b'a', 2 << 5, 0], 5).unwrap(), b"aaaaa");
+        assert!(decompress(&[0, b'a', 2 << 5, 1], 5).is_err());
+        assert!(decompress(&[3, b'a'], 4).is_err());
+    }
+}
@@ -26,11 +26,14 @@
 //!     write_pcd_file(&file_pcd, PCDDataType::Ascii, "new.pcd");
 //!
 //!     write_pcd_file(&file_pcd, PCDDataType::Binary, "new_binary.pcd");
+//!
+//!     write_pcd_file(&file_pcd, PCDDataType::CompressedBinary, "new_compressed.pcd");
 //!     Ok(())
 //! }
 //! ```
 
 mod data_types;
+mod lzf;
 mod reader;
 mod writer;
 
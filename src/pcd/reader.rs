@@ -1,6 +1,7 @@
 use crate::pcd::data_types::{
     PCDDataType, PCDField, PCDFieldDataType, PCDHeader, PCDVersion, PointCloudData,
 };
+use crate::pcd::lzf;
 use std::convert::TryInto;
 use std::fmt::Debug;
 
@@ -62,6 +63,22 @@ pub enum PCDReadError {
     /// Represents an error with the data of the file.
     #[error("Invalid data: {0}")]
     InvalidData(String),
+    /// A field needed by the point type is not in the file.
+    #[error("Missing field {field}, the file has fields {available:?}")]
+    MissingField {
+        /// The names the field is looked up by
+        field: String,
+        /// The fields of the file
+        available: Vec<String>,
+    },
+    /// A field of the file cannot be converted to the point type.
+    #[error("Invalid field {field:?}. {error_msg}")]
+    InvalidField {
+        /// The name of the field
+        field: String,
+        /// A custom error messaging describing the error
+        error_msg: String,
+    },
 }
 
 struct Parser<R: BufRead> {
@@ -205,9 +222,7 @@ impl<R: BufRead> Parser<R> {
         match data_type {
             PCDDataType::Ascii => self.parse_ascii_data(header),
             PCDDataType::Binary => self.parse_binary_data(header),
-            PCDDataType::CompressedBinary => {
-                Err(self.header_err("DATA", "Compressed binary type not supported".to_string()))
-            }
+            PCDDataType::CompressedBinary => self.parse_compressed_binary_data(header),
         }
     }
 
@@ -295,6 +310,53 @@ impl<R: BufRead> Parser<R> {
         PointCloudData::new(header, buffer).map_err(PCDReadError::InvalidData)
     }
 
+    /// LZF compressed, after the compressed and the uncompressed sizes.
+    /// The uncompressed data holds every field of all the points before the next field.
+    fn parse_compressed_binary_data(mut self, header: PCDHeader) -> Result<PointCloudData> {
+        use byteorder::{NativeEndian, ReadBytesExt};
+
+        let mut buffer = vec![];
+        self.reader
+            .read_to_end(&mut buffer)
+            .map_err(PCDReadError::IOError)?;
+        let mut sizes = &buffer[..];
+        let compressed_size = sizes.read_u32::<NativeEndian>()? as usize;
+        let uncompressed_size = sizes.read_u32::<NativeEndian>()? as usize;
+        if uncompressed_size as u64 != header.buffer_size() {
+            return Err(PCDReadError::InvalidData(format!(
+                "Expected {} bytes of uncompressed data, the file has {}",
+                header.buffer_size(),
+                uncompressed_size
+            )));
+        }
+        let compressed = buffer.get(8..8 + compressed_size).ok_or_else(|| {
+            PCDReadError::InvalidData(format!(
+                "Expected {} bytes of compressed data, the file has {}",
+                compressed_size,
+                buffer.len() - 8
+            ))
+        })?;
+        let fields =
+            lzf::decompress(compressed, uncompressed_size).map_err(PCDReadError::InvalidData)?;
+
+        let points = header.points() as usize;
+        let point_size = uncompressed_size / points.max(1);
+        let mut data = vec![0; uncompressed_size];
+        let mut field_start = 0;
+        let mut offset = 0;
+        for field in header.fields() {
+            let field_size = field.size() as usize * field.count() as usize;
+            for i in 0..points {
+                let from = field_start + i * field_size;
+                let to = i * point_size + offset;
+                data[to..to + field_size].copy_from_slice(&fields[from..from + field_size]);
+            }
+            field_start += field_size * points;
+            offset += field_size;
+        }
+        PointCloudData::new(header, data).map_err(PCDReadError::InvalidData)
+    }
+
     fn strip_line_prefix(&mut self, prefix: &str) -> Result<&str> {
         self.line
             .trim()
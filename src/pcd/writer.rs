@@ -1,11 +1,12 @@
 use crate::formats::{
     pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud,
 };
+use crate::pcd::lzf;
 use crate::pcd::{
     PCDDataType, PCDField, PCDFieldDataType, PCDFieldSize, PCDFieldType, PCDHeader, PCDVersion,
     PointCloudData,
 };
-use byteorder::{NativeEndian, ReadBytesExt};
+use byteorder::{NativeEndian, ReadBytesExt, WriteBytesExt};
 use std::fs::File;
 use std::io::{BufWriter, Cursor, Write};
 use std::path::Path;
@@ -125,7 +126,7 @@ impl<'a, W: Write> Writer<'a, W> {
         match self.data_type {
             PCDDataType::Ascii => self.write_ascii(),
             PCDDataType::Binary => self.write_binary(),
-            PCDDataType::CompressedBinary => panic!("Write compressed binary not supported"),
+            PCDDataType::CompressedBinary => self.write_compressed_binary(),
         }
     }
 
@@ -163,6 +164,32 @@ impl<'a, W: Write> Writer<'a, W> {
         self.writer.write_all(self.pcd.data())?;
         Ok(())
     }
+
+    /// Same layout as PCL, every field of all the points comes before the next field,
+    /// LZF compressed and preceded by the compressed and the uncompressed sizes
+    fn write_compressed_binary(&mut self) -> IOResult {
+        let header = self.pcd.header();
+        let data = self.pcd.data();
+        let points = header.points() as usize;
+        let point_size = data.len() / points.max(1);
+        let mut fields = Vec::with_capacity(data.len());
+        let mut offset = 0;
+        for field in header.fields() {
+            let field_size = field.size() as usize * field.count() as usize;
+            for i in 0..points {
+                let start = i * point_size + offset;
+                fields.extend_from_slice(&data[start..start + field_size]);
+            }
+            offset += field_size;
+        }
+
+        let compressed = lzf::compress(&fields);
+        self.writer
+            .write_u32::<NativeEndian>(compressed.len() as u32)?;
+        self.writer.write_u32::<NativeEndian>(fields.len() as u32)?;
+        self.writer.write_all(&compressed)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -274,6 +301,66 @@ mod tests {
         assert_eq!(new_pcd.header(), pcd.header());
         assert_eq!(new_pcd.data(), pcd.data());
     }
+
+    #[test]
+    fn test_write_compressed_binary() {
+        // !! 3 points of a float and two bytes, the bytes repeat so that LZF has something to do
+        let mut data = vec![];
+        for i in 0..3 {
+            data.write_f32::<NativeEndian>(i as f32).unwrap();
+            data.write_u8(7).unwrap();
+            data.write_u8(7).unwrap();
+        }
+        let pcd = PointCloudData::new(
+            PCDHeader::new(
+                PCDVersion::V0_7,
+                vec![
+                    PCDField::new("x".to_string(), PCDFieldSize::Four, PCDFieldType::Float, 1)
+                        .unwrap(),
+                    PCDField::new(
+                        "label".to_string(),
+                        PCDFieldSize::One,
+                        PCDFieldType::Unsigned,
+                        2,
+                    )
+                    .unwrap(),
+                ],
+                3,
+                1,
+                [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+                3,
+                "binary_compressed".parse().unwrap(),
+            )
+            .unwrap(),
+            data,
+        )
+        .unwrap();
+
+        let mut buf = BufWriter::new(Vec::new());
+        write_pcd(&pcd, PCDDataType::CompressedBinary, &mut buf).unwrap();
+        let vec = buf.into_inner().unwrap();
+        let marker = b"DATA binary_compressed\n";
+        let start = vec.windows(marker.len()).position(|w| w == marker).unwrap();
+        let body = &vec[start + marker.len()..];
+        // !! all the x come before all the labels
+        let uncompressed = super::lzf::decompress(&body[8..], 18).unwrap();
+        assert_eq!(&uncompressed[4..8], 1f32.to_ne_bytes());
+        assert_eq!(&uncompressed[12..], [7; 6]);
+
+        let new_pcd = read_pcd(BufReader::new(vec.as_bytes())).unwrap();
+        assert_eq!(new_pcd.header(), pcd.header());
+        assert_eq!(new_pcd.data(), pcd.data());
+    }
+}
+
+/// Color packed as PCL does, 0xAARRGGBB in an unsigned integer
+fn write_position_and_color(bytes: &mut Vec<u8>, x: f32, y: f32, z: f32, [r, g, b, a]: [u8; 4]) {
+    for v in [x, y, z] {
+        bytes.write_f32::<NativeEndian>(v).unwrap();
+    }
+    bytes
+        .write_u32::<NativeEndian>(u32::from_be_bytes([a, r, g, b]))
+        .unwrap();
 }
 
 pub fn create_pcd(point_cloud: &PointCloud<PointXyzRgba>) -> PointCloudData {
@@ -298,14 +385,10 @@ pub fn create_pcd(point_cloud: &PointCloud<PointXyzRgba>) -> PointCloudData {
         PCDDataType::Ascii, // this is a placeholder value, it will be overwritten accoradingly in write_pcd_file()
     )
     .unwrap();
-    let bytes = unsafe {
-        let mut points = std::mem::ManuallyDrop::new(point_cloud.points.clone());
-        Vec::from_raw_parts(
-            points.as_mut_ptr() as *mut u8,
-            point_cloud.number_of_points * std::mem::size_of::<PointXyzRgba>(),
-            points.capacity() * std::mem::size_of::<PointXyzRgba>(),
-        )
-    };
+    let mut bytes = Vec::with_capacity(point_cloud.points.len() * 16);
+    for p in &point_cloud.points {
+        write_position_and_color(&mut bytes, p.x, p.y, p.z, [p.r, p.g, p.b, p.a]);
+    }
     PointCloudData::new(header, bytes).unwrap()
 }
 
@@ -335,14 +418,13 @@ pub fn create_pcd_from_pc_normal(point_cloud: &PointCloud<PointXyzRgbaNormal>) -
     )
     .unwrap();
 
-    let bytes = unsafe {
-        let mut points = std::mem::ManuallyDrop::new(point_cloud.points.clone());
-        Vec::from_raw_parts(
-            points.as_mut_ptr() as *mut u8,
-            point_cloud.number_of_points * std::mem::size_of::<PointXyzRgbaNormal>(),
-            points.capacity() * std::mem::size_of::<PointXyzRgbaNormal>(),
-        )
-    };
+    let mut bytes = Vec::with_capacity(point_cloud.points.len() * 28);
+    for p in &point_cloud.points {
+        write_position_and_color(&mut bytes, p.x, p.y, p.z, [p.r, p.g, p.b, p.a]);
+        for n in [p.nx, p.ny, p.nz] {
+            bytes.write_f32::<NativeEndian>(n).unwrap();
+        }
+    }
 
     PointCloudData::new(header, bytes).unwrap()
 }
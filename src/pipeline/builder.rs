@@ -0,0 +1,615 @@
+//! Build and run a pipeline from Rust code instead of command line arguments.
+//!
+//! ```no_run
+//! use vivotk::pipeline::PipelineBuilder;
+//!
+//! let handle = PipelineBuilder::new()
+//!     .stage("read", ["./longdress/Ply"])
+//!     .output("raw")
+//!     .stage("downsample", ["-p", "5"])
+//!     .input("raw")
+//!     .output("ds")
+//!     .stage("write", ["--output-format", "pcd", "./longdress_ds"])
+//!     .input("ds")
+//!     .build()?
+//!     .run()?;
+//! handle.join()?;
+//! # Ok::<(), String>(())
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use crossbeam_channel::Receiver;
+use serde_json::json;
+
+use super::{
+    executor::{Executor, ExecutorBuilder, StageSpec},
+    explain, subcommand,
+    subcommands::Subcommand,
+    validate, ErrorPolicy, JoinPolicy, Progress, StageError, VVSubCommand,
+};
+
+struct PendingStage {
+    spec: StageSpec,
+    /// handler supplied by the library user, `None` for the built-in subcommands
+    handler: Option<Box<dyn Subcommand>>,
+}
+
+/// Describes the stages of a pipeline and the named streams connecting them.
+///
+/// `input` and `output` apply to the most recently added stage.
+#[derive(Default)]
+pub struct PipelineBuilder {
+    stages: Vec<PendingStage>,
+    errors: Vec<String>,
+    on_error: ErrorPolicy,
+}
+
+impl PipelineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds one of the `vv` subcommands, `args` are the same as on the command line
+    /// without the `+input=` and `+output=` tokens
+    pub fn stage<I, S>(mut self, name: &str, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut stage_args = vec![name.to_string()];
+        stage_args.extend(args.into_iter().map(Into::into));
+        self.stages.push(PendingStage {
+            spec: StageSpec::new(name, stage_args),
+            handler: None,
+        });
+        self
+    }
+
+    /// Adds a stage backed by a user defined handler. Custom stages may consume
+    /// any number of inputs, including none.
+    pub fn custom_stage<T>(mut self, name: &str, handler: T) -> Self
+    where
+        T: Subcommand + Send + 'static,
+    {
+        let mut spec = StageSpec::new(name, vec![name.to_string()]);
+        spec.custom = true;
+        self.stages.push(PendingStage {
+            spec,
+            handler: Some(Box::new(handler)),
+        });
+        self
+    }
+
+    pub fn input(mut self, stream: &str) -> Self {
+        match self.stages.last_mut() {
+            Some(stage) => stage.spec.input_stream_names.push(stream.to_string()),
+            None => self
+                .errors
+                .push(format!("input `{}` given before any stage", stream)),
+        }
+        self
+    }
+
+    pub fn output(mut self, stream: &str) -> Self {
+        match self.stages.last_mut() {
+            Some(stage) => stage.spec.output_name = stream.to_string(),
+            None => self
+                .errors
+                .push(format!("output `{}` given before any stage", stream)),
+        }
+        self
+    }
+
+    /// How the frames of the inputs of the most recently added stage are matched,
+    /// `JoinPolicy::Error` by default
+    pub fn join(mut self, policy: JoinPolicy) -> Self {
+        match self.stages.last_mut() {
+            Some(stage) => stage.spec.join = Some(policy),
+            None => self.errors.push("join given before any stage".to_string()),
+        }
+        self
+    }
+
+    /// Runs the most recently added stage on `n` workers, only for per-frame commands
+    pub fn jobs(mut self, n: usize) -> Self {
+        match self.stages.last_mut() {
+            Some(stage) if n > 0 => stage.spec.jobs = n,
+            Some(_) => self.errors.push("jobs should be at least 1".to_string()),
+            None => self.errors.push("jobs given before any stage".to_string()),
+        }
+        self
+    }
+
+    /// What to do once a stage fails on a frame, skips the frame by default
+    pub fn on_error(mut self, on_error: ErrorPolicy) -> Self {
+        self.on_error = on_error;
+        self
+    }
+
+    /// The stream graph in DOT format and a table of the stages, nothing is created or run
+    pub fn explain(&self) -> Result<String, String> {
+        if !self.errors.is_empty() {
+            return Err(self.errors.join("\n"));
+        }
+        let specs = self
+            .stages
+            .iter()
+            .map(|stage| stage.spec.clone())
+            .collect::<Vec<_>>();
+        explain::explain(&specs)
+    }
+
+    /// Checks the arguments of every stage and the stream graph, then creates the handlers
+    pub fn build(self) -> Result<PipelineGraph, String> {
+        let mut problems = self.errors;
+        if self.stages.is_empty() {
+            problems.push("Should have at least one stage".to_string());
+        }
+
+        // !! parse the arguments here so that a typo is reported instead of exiting the process
+        for (idx, stage) in self.stages.iter().enumerate() {
+            if stage.handler.is_some() || subcommand(&stage.spec.name).is_none() {
+                continue;
+            }
+            let argv = std::iter::once("vv").chain(stage.spec.args.iter().map(String::as_str));
+            if let Err(err) = VVSubCommand::try_parse_from(argv) {
+                problems.push(format!(
+                    "stage {} ({}): {}",
+                    idx,
+                    stage.spec.name,
+                    err.to_string().trim()
+                ));
+            }
+        }
+
+        let specs = self
+            .stages
+            .iter()
+            .map(|stage| stage.spec.clone())
+            .collect::<Vec<_>>();
+        if let Err(err) = validate::validate(&specs) {
+            problems.push(err);
+        }
+
+        if !problems.is_empty() {
+            return Err(problems.join("\n"));
+        }
+
+        let on_error = self.on_error;
+        let stages = self
+            .stages
+            .into_iter()
+            .map(|stage| {
+                let handlers = match stage.handler {
+                    Some(handler) => vec![handler],
+                    None => {
+                        let creator = subcommand(&stage.spec.name)
+                            .expect("validated stage should have a known command");
+                        (0..stage.spec.jobs)
+                            .map(|_| creator(stage.spec.args.clone()))
+                            .collect()
+                    }
+                };
+                (stage.spec, handlers)
+            })
+            .collect();
+        Ok(PipelineGraph::new(stages, on_error))
+    }
+}
+
+/// Names of the stream and of every stream it is computed from
+fn upstream(name: &str, producers: &HashMap<String, Vec<String>>) -> HashSet<String> {
+    let mut streams = HashSet::new();
+    let mut pending = vec![name.to_string()];
+    while let Some(name) = pending.pop() {
+        if let Some(inputs) = producers.get(&name) {
+            pending.extend(inputs.iter().filter(|i| !streams.contains(*i)).cloned());
+        }
+        streams.insert(name);
+    }
+    streams
+}
+
+/// Executors with their inputs connected, ready to be started
+pub struct PipelineGraph {
+    executors: Vec<Executor>,
+    progresses: Vec<Receiver<Progress>>,
+}
+
+impl PipelineGraph {
+    pub(crate) fn new(
+        stages: Vec<(StageSpec, Vec<Box<dyn Subcommand>>)>,
+        on_error: ErrorPolicy,
+    ) -> Self {
+        let mut executor_builder = ExecutorBuilder::new(on_error);
+        let (mut executors, progresses): (Vec<_>, Vec<_>) = stages
+            .into_iter()
+            .map(|(spec, handlers)| executor_builder.create(spec, handlers))
+            .unzip();
+
+        // !! set named input outputs
+        let all_input_names: Vec<Vec<String>> = executors.iter().map(|e| e.input_names()).collect();
+        let producers: HashMap<String, Vec<String>> = executors
+            .iter()
+            .map(|e| (e.output_name().to_string(), e.input_names()))
+            .collect();
+        for (idx, input_names) in all_input_names.iter().enumerate() {
+            let mut inputs = vec![];
+            for input_name in input_names {
+                for executor in &mut executors {
+                    if executor.output_name().eq(input_name) {
+                        inputs.push(executor.output());
+                    }
+                }
+            }
+            let upstreams = input_names
+                .iter()
+                .map(|name| upstream(name, &producers))
+                .collect::<Vec<_>>();
+            let shared_producer = upstreams
+                .iter()
+                .enumerate()
+                .any(|(i, a)| upstreams[i + 1..].iter().any(|b| !a.is_disjoint(b)));
+            executors[idx].set_inputs(inputs, shared_producer);
+        }
+
+        Self {
+            executors,
+            progresses,
+        }
+    }
+
+    /// Spawns one thread per stage
+    pub fn run(self) -> Result<PipelineHandle, String> {
+        let mut names = vec![];
+        let mut handles = vec![];
+        for exec in self.executors {
+            names.push(exec.name());
+            handles.push(exec.run());
+        }
+        let n = names.len();
+        Ok(PipelineHandle {
+            names,
+            progresses: self.progresses,
+            handles,
+            frames: vec![0; n],
+            completed: vec![false; n],
+            errors: vec![vec![]; n],
+            queued: vec![0; n],
+            blocked: vec![Duration::ZERO; n],
+            wall_times: vec![None; n],
+            started: Instant::now(),
+        })
+    }
+}
+
+/// Number of failed frames listed per stage in the summary
+const MAX_ERRORS_LISTED: usize = 10;
+
+/// A running pipeline
+pub struct PipelineHandle {
+    names: Vec<String>,
+    progresses: Vec<Receiver<Progress>>,
+    handles: Vec<JoinHandle<()>>,
+    frames: Vec<usize>,
+    completed: Vec<bool>,
+    errors: Vec<Vec<StageError>>,
+    queued: Vec<usize>,
+    blocked: Vec<Duration>,
+    wall_times: Vec<Option<Duration>>,
+    started: Instant,
+}
+
+impl PipelineHandle {
+    /// Names of the stages, in the order they were added
+    pub fn stage_names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Collects the progress sent by the stages so far, returns true once every stage has completed
+    pub fn poll(&mut self) -> bool {
+        for (idx, recv) in self.progresses.iter().enumerate() {
+            while let Ok(progress) = recv.try_recv() {
+                match progress {
+                    Progress::Incr => self.frames[idx] += 1,
+                    Progress::Completed => self.completed[idx] = true,
+                    Progress::Error(error) => self.errors[idx].push(error),
+                    Progress::Input { queued, blocked } => {
+                        self.queued[idx] = queued;
+                        self.blocked[idx] += blocked;
+                    }
+                    Progress::Finished(wall_time) => self.wall_times[idx] = Some(wall_time),
+                }
+            }
+        }
+        self.completed.iter().all(|&completed| completed)
+    }
+
+    /// Number of messages sent by each stage, in the same order as `stage_names`
+    pub fn frames(&self) -> &[usize] {
+        &self.frames
+    }
+
+    /// Failures reported by each stage, in the same order as `stage_names`
+    pub fn errors(&self) -> &[Vec<StageError>] {
+        &self.errors
+    }
+
+    /// Messages waiting in the inputs of each stage when it last took one
+    pub fn queued(&self) -> &[usize] {
+        &self.queued
+    }
+
+    /// Time since the pipeline started
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Wall time, frame counts and time spent waiting for input of every stage so far.
+    /// A stage that is busy most of its wall time while the others wait is the bottleneck.
+    pub fn report(&self) -> serde_json::Value {
+        let stages = self
+            .names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| {
+                let wall_time = self.wall_times[idx].unwrap_or_else(|| self.started.elapsed());
+                let busy = wall_time.saturating_sub(self.blocked[idx]);
+                json!({
+                    "index": idx,
+                    "name": name,
+                    "frames": self.frames[idx],
+                    "errors": self.errors[idx].len(),
+                    "completed": self.completed[idx],
+                    "wall_time_secs": wall_time.as_secs_f64(),
+                    "blocked_on_input_secs": self.blocked[idx].as_secs_f64(),
+                    "busy_secs": busy.as_secs_f64(),
+                    "frames_per_sec": self.frames[idx] as f64 / wall_time.as_secs_f64().max(f64::EPSILON),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "wall_time_secs": self.started.elapsed().as_secs_f64(),
+            "stages": stages,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handles.iter().all(|handle| handle.is_finished())
+    }
+
+    /// Waits for every stage to finish. Fails with a per-stage summary if any stage
+    /// reported an error or panicked.
+    pub fn join(mut self) -> Result<(), String> {
+        let mut panics = vec![None; self.handles.len()];
+        for (idx, handle) in std::mem::take(&mut self.handles).into_iter().enumerate() {
+            if let Err(panic) = handle.join() {
+                let cause = panic
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                panics[idx] = Some(cause);
+            }
+        }
+        self.poll();
+
+        let mut summary = vec![];
+        for (idx, name) in self.names.iter().enumerate() {
+            if let Some(cause) = &panics[idx] {
+                summary.push(format!("  stage {} ({}) panicked: {}", idx, name, cause));
+            }
+            let errors = &self.errors[idx];
+            if errors.is_empty() {
+                continue;
+            }
+            summary.push(format!(
+                "  stage {} ({}): {} error{}",
+                idx,
+                name,
+                errors.len(),
+                if errors.len() == 1 { "" } else { "s" }
+            ));
+            for error in errors.iter().take(MAX_ERRORS_LISTED) {
+                summary.push(format!("    {}", error));
+            }
+            if errors.len() > MAX_ERRORS_LISTED {
+                summary.push(format!(
+                    "    ... and {} more",
+                    errors.len() - MAX_ERRORS_LISTED
+                ));
+            }
+        }
+
+        if summary.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Pipeline finished with errors:\n{}",
+                summary.join("\n")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::PointCloud;
+    use crate::pipeline::{Channel, PipelineMessage};
+    use std::sync::{Arc, Mutex};
+
+    /// Sends `frames` point clouds, failing on the frames listed in `fail_on`
+    struct Source {
+        frames: u32,
+        fail_on: Vec<u32>,
+    }
+
+    impl Subcommand for Source {
+        fn handle(&mut self, _messages: Vec<PipelineMessage>, out: &Channel) -> Result<(), String> {
+            for i in 0..self.frames {
+                if out.is_aborted() {
+                    break;
+                }
+                if self.fail_on.contains(&i) {
+                    out.send_error(Some(i), "unreadable");
+                } else {
+                    let pc = PointCloud {
+                        number_of_points: 0,
+                        points: vec![],
+                    };
+                    out.send(PipelineMessage::IndexedPointCloud(pc, i));
+                }
+            }
+            out.send(PipelineMessage::End);
+            Ok(())
+        }
+    }
+
+    /// Records the indices it receives
+    struct Collect(Arc<Mutex<Vec<u32>>>);
+
+    impl Subcommand for Collect {
+        fn handle(&mut self, messages: Vec<PipelineMessage>, _out: &Channel) -> Result<(), String> {
+            for message in messages {
+                if let PipelineMessage::IndexedPointCloud(_, i) = message {
+                    self.0.lock().unwrap().push(i);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn run_with(source: Source, on_error: ErrorPolicy) -> (Vec<u32>, Result<(), String>) {
+        let collected = Arc::new(Mutex::new(vec![]));
+        let result = PipelineBuilder::new()
+            .on_error(on_error)
+            .custom_stage("source", source)
+            .output("a")
+            .custom_stage("collect", Collect(collected.clone()))
+            .input("a")
+            .build()
+            .unwrap()
+            .run()
+            .unwrap()
+            .join();
+        let collected = collected.lock().unwrap().clone();
+        (collected, result)
+    }
+
+    #[test]
+    fn test_custom_stages() {
+        let source = Source {
+            frames: 5,
+            fail_on: vec![],
+        };
+        let (collected, result) = run_with(source, ErrorPolicy::Skip);
+        assert_eq!(collected, vec![0, 1, 2, 3, 4]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_skip_failed_frames() {
+        let source = Source {
+            frames: 5,
+            fail_on: vec![1, 3],
+        };
+        let (collected, result) = run_with(source, ErrorPolicy::Skip);
+        assert_eq!(collected, vec![0, 2, 4]);
+        let summary = result.unwrap_err();
+        assert!(summary.contains("stage 0 (source): 2 errors"));
+        assert!(summary.contains("frame 3: unreadable"));
+        assert!(!summary.contains("stage 1"));
+    }
+
+    #[test]
+    fn test_abort_on_first_error() {
+        let source = Source {
+            frames: 100,
+            fail_on: vec![2],
+        };
+        let (collected, result) = run_with(source, ErrorPolicy::Abort);
+        assert!(collected.len() <= 2);
+        assert!(result.unwrap_err().contains("stage 0 (source): 1 error"));
+    }
+
+    #[test]
+    fn test_join_inputs_by_index() {
+        let collected = Arc::new(Mutex::new(vec![]));
+        let result = PipelineBuilder::new()
+            .custom_stage(
+                "a",
+                Source {
+                    frames: 4,
+                    fail_on: vec![1],
+                },
+            )
+            .output("a")
+            .custom_stage(
+                "b",
+                Source {
+                    frames: 3,
+                    fail_on: vec![],
+                },
+            )
+            .output("b")
+            .custom_stage("collect", Collect(collected.clone()))
+            .input("a")
+            .input("b")
+            .join(JoinPolicy::Drop)
+            .build()
+            .unwrap()
+            .run()
+            .unwrap()
+            .join();
+        // !! frame 1 failed in `a` and frame 3 only exists in `a`
+        assert_eq!(*collected.lock().unwrap(), vec![0, 0, 2, 2]);
+        assert!(result.unwrap_err().contains("stage 0 (a): 1 error"));
+    }
+
+    #[test]
+    fn test_concat_inputs_with_a_shared_producer() {
+        // !! all of the second input is queued while the first plays, past the cap
+        let collected = Arc::new(Mutex::new(vec![]));
+        let result = PipelineBuilder::new()
+            .custom_stage(
+                "source",
+                Source {
+                    frames: 100,
+                    fail_on: vec![],
+                },
+            )
+            .output("a")
+            .stage("concat", Vec::<String>::new())
+            .input("a")
+            .input("a")
+            .output("both")
+            .custom_stage("collect", Collect(collected.clone()))
+            .input("both")
+            .build()
+            .unwrap()
+            .run()
+            .unwrap()
+            .join();
+        assert!(result.is_ok());
+        assert_eq!(*collected.lock().unwrap(), (0..200).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_invalid_args_and_graph() {
+        let err = PipelineBuilder::new()
+            .input("nothing")
+            .stage("downsample", ["--no-such-flag"])
+            .input("missing")
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.contains("input `nothing` given before any stage"));
+        assert!(err.contains("stage 0 (downsample): error: unexpected argument"));
+        assert!(err.contains("stage 0 (downsample): no output stream with name `missing`"));
+    }
+}
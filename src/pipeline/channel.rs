@@ -1,37 +1,111 @@
-use crossbeam_channel::{bounded, Receiver, Sender};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use super::{PipelineMessage, Progress};
+use super::{ErrorPolicy, PipelineMessage, Progress, StageError};
 
-const MAX_MESSAGES: usize = 30;
+/// Capacity of the stream between two stages
+pub(crate) const MAX_MESSAGES: usize = 30;
 
 pub struct Channel {
+    name: String,
     progress_tx: Sender<Progress>,
     listeners: Vec<Sender<PipelineMessage>>,
+    on_error: ErrorPolicy,
+    /// shared by every stage of a pipeline, set once a stage fails under `ErrorPolicy::Abort`
+    aborted: Arc<AtomicBool>,
+    ended: Cell<bool>,
+    /// set once a point cloud is sent, metrics sent by the stage are then reports about
+    /// its frames rather than frames
+    sent_point_clouds: Cell<bool>,
 }
 
 impl Channel {
     pub fn new(progress_tx: Sender<Progress>) -> Self {
+        Self::for_stage(
+            "",
+            progress_tx,
+            ErrorPolicy::default(),
+            Arc::new(AtomicBool::new(false)),
+        )
+    }
+
+    pub(crate) fn for_stage(
+        name: &str,
+        progress_tx: Sender<Progress>,
+        on_error: ErrorPolicy,
+        aborted: Arc<AtomicBool>,
+    ) -> Self {
         Self {
+            name: name.to_string(),
             progress_tx,
             listeners: vec![],
+            on_error,
+            aborted,
+            ended: Cell::new(false),
+            sent_point_clouds: Cell::new(false),
         }
     }
 
-    pub fn send(&self, message: PipelineMessage) -> Vec<()> {
-        match &message {
-            PipelineMessage::End => self.progress_tx.send(Progress::Completed),
-            _ => self.progress_tx.send(Progress::Incr),
+    /// Sends a message to every listener, nothing is sent after `End`. Metrics count as
+    /// frames in the progress only for stages that send no point clouds, e.g. `metrics`
+    pub fn send(&self, message: PipelineMessage) {
+        if self.ended.get() {
+            return;
+        }
+        let progress = match &message {
+            PipelineMessage::End => {
+                self.ended.set(true);
+                Some(Progress::Completed)
+            }
+            PipelineMessage::Error(_) => None,
+            PipelineMessage::IndexedPointCloud(..)
+            | PipelineMessage::IndexedPointCloudNormal(..) => {
+                self.sent_point_clouds.set(true);
+                Some(Progress::Incr)
+            }
+            PipelineMessage::Metrics(_) if self.sent_point_clouds.get() => None,
+            _ => Some(Progress::Incr),
+        };
+        if let Some(progress) = progress {
+            // !! nobody may be watching the progress, e.g. in tests
+            let _ = self.progress_tx.send(progress);
         }
-        .expect("Should be able to send progress");
 
-        self.listeners
-            .iter()
-            .map(|sender| {
-                sender
-                    .send(message.clone())
-                    .expect("Should be able to send a message through the channel")
-            })
-            .collect()
+        for sender in &self.listeners {
+            // !! a listener that stopped early (e.g. after an abort) is not an error here
+            let _ = sender.send(message.clone());
+        }
+    }
+
+    /// Records a failure of this stage and forwards it downstream in place of the frame
+    pub fn send_error(&self, index: Option<u32>, cause: impl Into<String>) {
+        let error = StageError {
+            stage: self.name.clone(),
+            index,
+            cause: cause.into(),
+        };
+        let _ = self.progress_tx.send(Progress::Error(error.clone()));
+        if self.on_error == ErrorPolicy::Abort {
+            self.aborted.store(true, Ordering::SeqCst);
+        }
+        self.send(PipelineMessage::Error(error));
+    }
+
+    /// Whether the pipeline is being stopped, long running sources should check this between frames
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Sends statistics about the stage to whoever watches the progress
+    pub(crate) fn report(&self, progress: Progress) {
+        let _ = self.progress_tx.send(progress);
+    }
+
+    /// Sends `End` unless the handler already did
+    pub(crate) fn end(&self) {
+        self.send(PipelineMessage::End);
     }
 
     pub fn subscribe(&mut self) -> Receiver<PipelineMessage> {
@@ -39,4 +113,45 @@ impl Channel {
         self.listeners.push(tx);
         rx
     }
+
+    /// Like `subscribe` but never blocks the sender, for listeners on the same thread
+    pub(crate) fn subscribe_unbounded(&mut self) -> Receiver<PipelineMessage> {
+        let (tx, rx) = unbounded();
+        self.listeners.push(tx);
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::PointCloud;
+    use crate::metrics::Metrics;
+
+    fn frames_counted(messages: Vec<PipelineMessage>) -> usize {
+        let (progress_tx, progress_rx) = unbounded();
+        let channel = Channel::new(progress_tx);
+        for message in messages {
+            channel.send(message);
+        }
+        progress_rx
+            .try_iter()
+            .filter(|progress| matches!(progress, Progress::Incr))
+            .count()
+    }
+
+    #[test]
+    fn test_reports_are_not_frames() {
+        let frame = |i| {
+            let pc = PointCloud {
+                number_of_points: 0,
+                points: vec![],
+            };
+            PipelineMessage::IndexedPointCloud(pc, i)
+        };
+        let report = || PipelineMessage::Metrics(Metrics::new());
+        let counted = frames_counted(vec![frame(0), report(), frame(1), report()]);
+        assert_eq!(counted, 2);
+        assert_eq!(frames_counted(vec![report(), report()]), 2);
+    }
 }
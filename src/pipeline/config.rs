@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use clap::Parser;
+use serde_json::Value;
+
+/// Maximum depth when a variable refers to another variable, guards against cycles
+const MAX_VAR_DEPTH: usize = 16;
+
+#[derive(Parser, Debug)]
+#[clap(
+    about = "Runs a pipeline described in a .toml or .json file.\nStages, their arguments and named streams are read from the file.\nVariables written as ${NAME} are substituted from [vars], --var or the environment.",
+    override_usage = "run [OPTIONS] <FILE>"
+)]
+pub struct Args {
+    /// Path to the pipeline file (.toml or .json)
+    file: String,
+
+    /// Sets or overrides a variable, e.g. --var SEQ=longdress
+    #[clap(long = "var", value_name = "KEY=VALUE", num_args = 1)]
+    vars: Vec<String>,
+}
+
+/// A single stage of a pipeline file, already reduced to the tokens the argv parser understands
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageConfig {
+    pub command: String,
+    pub args: Vec<String>,
+    pub inputs: Vec<String>,
+    pub join: Option<String>,
+    pub jobs: Option<usize>,
+    pub output: Option<String>,
+}
+
+impl StageConfig {
+    /// Tokens equivalent to writing this stage on the command line,
+    /// e.g. `["downsample", "-p", "5", "+input=raw", "+output=ds"]`
+    pub fn to_argv(&self) -> Vec<String> {
+        let mut argv = vec![self.command.clone()];
+        argv.extend(self.args.iter().cloned());
+        if !self.inputs.is_empty() {
+            argv.push(format!("+input={}", self.inputs.join(",")));
+        }
+        if let Some(join) = &self.join {
+            argv.push(format!("+join={}", join));
+        }
+        if let Some(jobs) = self.jobs {
+            argv.push(format!("+jobs={}", jobs));
+        }
+        if let Some(output) = &self.output {
+            argv.push(format!("+output={}", output));
+        }
+        argv
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    pub stages: Vec<StageConfig>,
+}
+
+impl PipelineConfig {
+    /// Parses the arguments of `vv run` (including the leading `run`) and loads the pipeline file
+    pub fn from_run_args(args: &[String]) -> Result<Self, String> {
+        let args = Args::parse_from(args);
+        let mut overrides = HashMap::new();
+        for var in &args.vars {
+            let (key, value) = var
+                .split_once('=')
+                .ok_or(format!("Expected --var KEY=VALUE, got `{}`", var))?;
+            overrides.insert(key.to_string(), value.to_string());
+        }
+        Self::from_path(&args.file, &overrides)
+    }
+
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+        overrides: &HashMap<String, String>,
+    ) -> Result<Self, String> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read pipeline file {}: {}", path.display(), e))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml_str(&content, overrides),
+            Some("json") => Self::from_json_str(&content, overrides),
+            _ => Err(format!(
+                "Unsupported pipeline file {}, expected a .toml or .json file",
+                path.display()
+            )),
+        }
+    }
+
+    pub fn from_toml_str(
+        content: &str,
+        overrides: &HashMap<String, String>,
+    ) -> Result<Self, String> {
+        let value: toml::Value =
+            toml::from_str(content).map_err(|e| format!("Invalid pipeline file: {}", e))?;
+        let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        Self::from_value(&value, overrides)
+    }
+
+    pub fn from_json_str(
+        content: &str,
+        overrides: &HashMap<String, String>,
+    ) -> Result<Self, String> {
+        let value: Value =
+            serde_json::from_str(content).map_err(|e| format!("Invalid pipeline file: {}", e))?;
+        Self::from_value(&value, overrides)
+    }
+
+    /// The argv tokens of every stage, in the order they appear in the file
+    pub fn stage_args(&self) -> Vec<Vec<String>> {
+        self.stages.iter().map(StageConfig::to_argv).collect()
+    }
+
+    fn from_value(value: &Value, overrides: &HashMap<String, String>) -> Result<Self, String> {
+        let root = value
+            .as_object()
+            .ok_or("Pipeline file should contain a table at the top level")?;
+
+        let mut vars = HashMap::new();
+        if let Some(file_vars) = root.get("vars") {
+            let file_vars = file_vars.as_object().ok_or("`vars` should be a table")?;
+            for (key, value) in file_vars {
+                vars.insert(key.clone(), scalar_to_string(value, key)?);
+            }
+        }
+        let vars = Variables {
+            overrides,
+            file: &vars,
+        };
+
+        let stages = root
+            .get("stage")
+            .or_else(|| root.get("stages"))
+            .ok_or("Pipeline file should have at least one `stage`")?
+            .as_array()
+            .ok_or("`stage` should be an array of tables")?;
+        if stages.is_empty() {
+            return Err("Pipeline file should have at least one `stage`".to_string());
+        }
+
+        let stages = stages
+            .iter()
+            .enumerate()
+            .map(|(idx, stage)| {
+                parse_stage(stage, &vars).map_err(|e| format!("stage {}: {}", idx, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { stages })
+    }
+}
+
+fn parse_stage(stage: &Value, vars: &Variables) -> Result<StageConfig, String> {
+    let stage = stage.as_object().ok_or("a stage should be a table")?;
+    let command = match stage.get("command") {
+        Some(Value::String(command)) => vars.substitute(command)?,
+        _ => return Err("missing `command`".to_string()),
+    };
+
+    // `args` is forwarded verbatim
+    let mut args = vec![];
+    match stage.get("args") {
+        None => {}
+        Some(Value::Array(values)) => {
+            for value in values {
+                args.push(vars.substitute(&scalar_to_string(value, "args")?)?);
+            }
+        }
+        Some(_) => return Err("`args` should be an array".to_string()),
+    }
+
+    // `options` is turned into `--key value`, or `--key` for flags set to true
+    match stage.get("options") {
+        None => {}
+        Some(Value::Object(options)) => {
+            for (key, value) in options {
+                let flag = if key.len() == 1 {
+                    format!("-{}", key)
+                } else {
+                    format!("--{}", key)
+                };
+                match value {
+                    Value::Bool(true) => args.push(flag),
+                    Value::Bool(false) => {}
+                    Value::Array(values) => {
+                        args.push(flag);
+                        for value in values {
+                            args.push(vars.substitute(&scalar_to_string(value, key)?)?);
+                        }
+                    }
+                    _ => {
+                        args.push(flag);
+                        args.push(vars.substitute(&scalar_to_string(value, key)?)?);
+                    }
+                }
+            }
+        }
+        Some(_) => return Err("`options` should be a table".to_string()),
+    }
+
+    let inputs = match stage.get("input").or_else(|| stage.get("inputs")) {
+        None => vec![],
+        Some(Value::String(input)) => input
+            .split(',')
+            .map(|name| vars.substitute(name.trim()))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(Value::Array(inputs)) => inputs
+            .iter()
+            .map(|name| vars.substitute(&scalar_to_string(name, "input")?))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => return Err("`input` should be a string or an array".to_string()),
+    };
+
+    let join = match stage.get("join") {
+        None => None,
+        Some(Value::String(join)) => Some(vars.substitute(join)?),
+        Some(_) => return Err("`join` should be a string".to_string()),
+    };
+
+    let jobs = match stage.get("jobs") {
+        None => None,
+        Some(Value::Number(n)) => Some(
+            n.as_u64()
+                .filter(|&n| n > 0)
+                .ok_or("`jobs` should be a positive integer")? as usize,
+        ),
+        Some(_) => return Err("`jobs` should be a positive integer".to_string()),
+    };
+
+    let output = match stage.get("output") {
+        None => None,
+        Some(Value::String(output)) => Some(vars.substitute(output)?),
+        Some(_) => return Err("`output` should be a string".to_string()),
+    };
+
+    Ok(StageConfig {
+        command,
+        args,
+        inputs,
+        join,
+        jobs,
+        output,
+    })
+}
+
+fn scalar_to_string(value: &Value, key: &str) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(format!("`{}` should be a string, number or boolean", key)),
+    }
+}
+
+/// Lookup order for `${NAME}`: `--var` overrides, then `[vars]`, then environment variables
+struct Variables<'a> {
+    overrides: &'a HashMap<String, String>,
+    file: &'a HashMap<String, String>,
+}
+
+impl Variables<'_> {
+    fn substitute(&self, s: &str) -> Result<String, String> {
+        self.substitute_with_depth(s, 0)
+    }
+
+    fn substitute_with_depth(&self, s: &str, depth: usize) -> Result<String, String> {
+        if depth > MAX_VAR_DEPTH {
+            return Err(format!("variables nested too deeply in `{}`", s));
+        }
+        let mut result = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(start) = rest.find("${") {
+            result.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find('}')
+                .ok_or(format!("unterminated variable in `{}`", s))?;
+            let name = &rest[start + 2..start + end];
+            let value = self.lookup(name)?;
+            result.push_str(&self.substitute_with_depth(&value, depth + 1)?);
+            rest = &rest[start + end + 1..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+
+    fn lookup(&self, name: &str) -> Result<String, String> {
+        if let Some(value) = self.overrides.get(name).or_else(|| self.file.get(name)) {
+            return Ok(value.clone());
+        }
+        std::env::var(name).map_err(|_| {
+            format!(
+                "undefined variable `{}`, define it in [vars], pass --var {}=... or set it in the environment",
+                name, name
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML_PIPELINE: &str = r#"
+[vars]
+SEQ = "longdress"
+ROOT = "/data/8i/${SEQ}"
+
+[[stage]]
+command = "read"
+args = ["${ROOT}/Ply"]
+output = "raw"
+
+[[stage]]
+command = "downsample"
+options = { p = 5 }
+input = "raw"
+output = "ds"
+jobs = 4
+
+[[stage]]
+command = "write"
+args = ["./out"]
+options = { output-format = "pcd", storage-type = "binary" }
+input = ["ds"]
+"#;
+
+    #[test]
+    fn test_toml_pipeline() {
+        let config = PipelineConfig::from_toml_str(TOML_PIPELINE, &HashMap::new()).unwrap();
+        assert_eq!(
+            config.stage_args(),
+            vec![
+                vec!["read", "/data/8i/longdress/Ply", "+output=raw"],
+                vec![
+                    "downsample",
+                    "-p",
+                    "5",
+                    "+input=raw",
+                    "+jobs=4",
+                    "+output=ds"
+                ],
+                vec![
+                    "write",
+                    "./out",
+                    "--output-format",
+                    "pcd",
+                    "--storage-type",
+                    "binary",
+                    "+input=ds"
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_var_override() {
+        let overrides = HashMap::from([("SEQ".to_string(), "soldier".to_string())]);
+        let config = PipelineConfig::from_toml_str(TOML_PIPELINE, &overrides).unwrap();
+        assert_eq!(config.stages[0].args, vec!["/data/8i/soldier/Ply"]);
+    }
+
+    #[test]
+    fn test_json_pipeline() {
+        let json = r#"{
+            "vars": { "OUT": "./out" },
+            "stages": [
+                { "command": "read", "args": ["./in"], "output": "a" },
+                { "command": "read", "args": ["./ref"], "output": "b" },
+                { "command": "metrics", "input": ["a", "b"], "join": "drop", "output": "m" },
+                { "command": "write", "args": ["${OUT}"], "input": "m" }
+            ]
+        }"#;
+        let config = PipelineConfig::from_json_str(json, &HashMap::new()).unwrap();
+        assert_eq!(
+            config.stage_args(),
+            vec![
+                vec!["read", "./in", "+output=a"],
+                vec!["read", "./ref", "+output=b"],
+                vec!["metrics", "+input=a,b", "+join=drop", "+output=m"],
+                vec!["write", "./out", "+input=m"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_var() {
+        let toml = r#"
+[[stage]]
+command = "read"
+args = ["${VVTK_SURELY_UNDEFINED_VAR}"]
+"#;
+        let err = PipelineConfig::from_toml_str(toml, &HashMap::new()).unwrap_err();
+        assert!(err.starts_with("stage 0:"));
+        assert!(err.contains("VVTK_SURELY_UNDEFINED_VAR"));
+    }
+
+    #[test]
+    fn test_cyclic_var() {
+        let toml = r#"
+[vars]
+A = "${B}"
+B = "${A}"
+
+[[stage]]
+command = "read"
+args = ["${A}"]
+"#;
+        assert!(PipelineConfig::from_toml_str(toml, &HashMap::new()).is_err());
+    }
+}
@@ -1,34 +1,63 @@
 use super::{
-    channel::Channel, subcommands::Subcommand, PipelineMessage, Progress, SubcommandCreator,
+    channel::Channel,
+    join::{message_index, JoinPolicy, Joined, Joiner},
+    parallel::ParallelStage,
+    subcommands::Subcommand,
+    ErrorPolicy, PipelineMessage, Progress, SubcommandCreator,
 };
 use crossbeam_channel::{unbounded, Receiver};
-use std::collections::HashSet;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Instant;
 
 pub struct Executor {
     name: String,
     input_stream_names: Vec<String>,
     output_name: String,
     inputs: Vec<Receiver<PipelineMessage>>,
+    join: JoinPolicy,
+    /// read the inputs one after another instead of joining them, for `concat`
+    concat: bool,
+    /// two of the inputs come from a common upstream stage
+    shared_producer: bool,
     channel: Channel,
-    handler: Box<dyn Subcommand>,
+    /// one handler per worker, `+jobs=N` creates N of them
+    handlers: Vec<Box<dyn Subcommand>>,
+    on_error: ErrorPolicy,
+    aborted: Arc<AtomicBool>,
 }
 
-pub struct ExecutorBuilder {
-    output_stream_names: HashSet<String>,
+/// The name, arguments and streams of one stage, before its handler is created
+#[derive(Debug, Clone)]
+pub struct StageSpec {
+    pub name: String,
+    /// arguments forwarded to the subcommand, starting with its name
+    pub args: Vec<String>,
+    pub input_stream_names: Vec<String>,
+    pub output_name: String,
+    /// how frames of several inputs are matched, given with `+join=drop|error|nearest`
+    pub join: Option<JoinPolicy>,
+    /// number of workers running the handler, given with `+jobs=N`
+    pub jobs: usize,
+    /// a handler supplied through `PipelineBuilder::custom_stage` rather than a `vv` subcommand
+    pub custom: bool,
 }
 
-impl ExecutorBuilder {
-    pub fn new() -> Self {
-        ExecutorBuilder {
-            output_stream_names: HashSet::new(),
+impl StageSpec {
+    pub fn new(name: &str, args: Vec<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            args,
+            input_stream_names: vec![],
+            output_name: "".to_string(),
+            join: None,
+            jobs: 1,
+            custom: false,
         }
     }
 
-    pub fn create(
-        &mut self,
-        args: Vec<String>,
-        creator: SubcommandCreator,
-    ) -> Result<(Executor, Receiver<Progress>), String> {
+    // !! stream names are only parsed here, they are checked against each other in `validate`
+    pub fn parse(args: Vec<String>) -> Result<Self, String> {
         let name = match args.first() {
             Some(command_name) => command_name.clone(),
             None => return Err("Should have command name".to_string()),
@@ -37,82 +66,92 @@ impl ExecutorBuilder {
         let mut inner_args = Vec::new();
         let mut input_stream_names = Vec::new();
         let mut output_name = "".to_string();
+        let mut join = None;
+        let mut jobs = 1;
 
-        let cmd = args[0].clone();
-
-        let mut has_input = false;
-        let mut has_help = false;
-        // println!("args: {:?}", args);
         for arg in args {
-            if arg.eq("--help") || arg.eq("-h") {
-                has_help = true;
-            }
-
-            if arg.starts_with("+input") || arg.starts_with("+in") {
-                let input_streams = match arg.split("=").nth(1) {
+            if let Some(policy) = arg.strip_prefix("+join=") {
+                join = Some(policy.parse()?);
+            } else if let Some(n) = arg.strip_prefix("+jobs=") {
+                jobs = match n.parse::<usize>() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("Expected a positive number of jobs, got `{}`", n)),
+                };
+            } else if arg.starts_with("+input") || arg.starts_with("+in") {
+                let input_streams = match arg.split('=').nth(1) {
                     Some(input_streams) => input_streams,
                     None => return Err("Expected name of input stream".to_string()),
                 };
 
                 for input_name in input_streams.split(',') {
-                    // check if input stream name is in the set, panic if not
-                    if !self.output_stream_names.contains(input_name) {
-                        // get the existing output stream names, concat them with ", "
-                        let existing_output_stream_names = self
-                            .output_stream_names
-                            .iter()
-                            .map(|s| format!("`{}`", s))
-                            .collect::<Vec<String>>()
-                            .join(", ");
-
-                        return Err(format!(
-                            "No output stream with name `{}` found, existing outputs are {}",
-                            input_streams, existing_output_stream_names
-                        ));
-                    } else {
-                        input_stream_names.push(input_name.to_string());
-                    }
+                    input_stream_names.push(input_name.to_string());
                 }
-                has_input = true;
             } else if arg.starts_with("+output") || arg.starts_with("+out") {
                 output_name = match arg.split('=').nth(1) {
                     Some(output_name) => output_name.to_string(),
                     None => return Err("Expected name of output stream".to_string()),
                 };
-
-                self.output_stream_names.insert(output_name.clone());
             } else {
                 inner_args.push(arg);
             }
         }
 
-        if has_input
-            || cmd.as_str() == "read"
-            || cmd.as_str() == "convert"
-            || cmd.as_str() == "info"
-            || cmd.as_str() == "dash"
-            || has_help
-        {
-        } else {
-            return Err(format!(
-                "`{}` needs to consume an input, but no named input is found, specify it using `+input=input_name`",
-                cmd.as_str()
-            ));
+        Ok(Self {
+            name,
+            args: inner_args,
+            input_stream_names,
+            output_name,
+            join,
+            jobs,
+            custom: false,
+        })
+    }
+}
+
+pub struct ExecutorBuilder {
+    on_error: ErrorPolicy,
+    aborted: Arc<AtomicBool>,
+}
+
+impl ExecutorBuilder {
+    pub fn new(on_error: ErrorPolicy) -> Self {
+        ExecutorBuilder {
+            on_error,
+            aborted: Arc::new(AtomicBool::new(false)),
         }
+    }
 
-        let handler = creator(inner_args);
+    pub fn create(
+        &mut self,
+        spec: StageSpec,
+        handlers: Vec<Box<dyn Subcommand>>,
+    ) -> (Executor, Receiver<Progress>) {
+        let StageSpec {
+            name,
+            input_stream_names,
+            output_name,
+            join,
+            custom,
+            ..
+        } = spec;
+        let concat = !custom && name == "concat";
 
         let (progress_tx, progress_rx) = unbounded();
-        let channel = Channel::new(progress_tx);
+        let channel = Channel::for_stage(&name, progress_tx, self.on_error, self.aborted.clone());
         let executor = Executor {
             name,
             input_stream_names,
             output_name,
             inputs: vec![],
+            join: join.unwrap_or_default(),
+            concat,
+            shared_producer: false,
             channel,
-            handler,
+            handlers,
+            on_error: self.on_error,
+            aborted: self.aborted.clone(),
         };
-        Ok((executor, progress_rx))
+        (executor, progress_rx)
     }
 }
 
@@ -153,8 +192,13 @@ impl Executor {
             input_stream_names,
             output_name,
             inputs: vec![],
+            join: JoinPolicy::default(),
+            concat: false,
+            shared_producer: false,
             channel,
-            handler,
+            handlers: vec![handler],
+            on_error: ErrorPolicy::default(),
+            aborted: Arc::new(AtomicBool::new(false)),
         };
         (executor, progress_rx)
     }
@@ -171,8 +215,10 @@ impl Executor {
         self.channel.subscribe()
     }
 
-    pub fn set_inputs(&mut self, inputs: Vec<Receiver<PipelineMessage>>) {
+    /// `shared_producer` is set when two of the inputs come from a common upstream stage
+    pub fn set_inputs(&mut self, inputs: Vec<Receiver<PipelineMessage>>, shared_producer: bool) {
         self.inputs = inputs;
+        self.shared_producer = shared_producer;
     }
 
     pub fn run(self) -> std::thread::JoinHandle<()> {
@@ -184,25 +230,80 @@ impl Executor {
     }
 
     fn start(mut self) {
+        let started = Instant::now();
+        self.process();
+        self.channel.end();
+        self.channel.report(Progress::Finished(started.elapsed()));
+    }
+
+    fn process(&mut self) {
         if self.inputs.is_empty() {
-            self.handler.handle(vec![], &self.channel);
+            if let Err(cause) = self.handlers[0].handle(vec![], &self.channel) {
+                self.channel.send_error(None, cause);
+            }
+            return;
+        }
+        if self.handlers.len() > 1 {
+            ParallelStage::new(&self.channel, &self.input_stream_names, self.join)
+                .with_shared_producer(self.shared_producer)
+                .run(
+                    std::mem::take(&mut self.inputs),
+                    std::mem::take(&mut self.handlers),
+                    &self.name,
+                    self.on_error,
+                    self.aborted.clone(),
+                );
             return;
         }
-        while let Ok(messages) = self
-            .inputs
-            .iter()
-            .map(|recv| recv.recv())
-            .collect::<Result<Vec<PipelineMessage>, _>>()
-        {
-            let should_break = messages.iter().any(|message| {
-                if let PipelineMessage::End = message {
-                    true
-                } else {
-                    false
+        // !! frames of several inputs are matched by index, a skipped frame must not shift the others
+        let inputs = std::mem::take(&mut self.inputs);
+        let mut joiner = if self.concat {
+            Joiner::concat(inputs)
+        } else {
+            Joiner::new(inputs, self.join)
+        }
+        .with_shared_producer(self.shared_producer);
+        while let Some(joined) = joiner.next_reported(&self.channel) {
+            if self.channel.is_aborted() {
+                break;
+            }
+
+            let messages = match joined {
+                Joined::Group(messages) => messages,
+                Joined::Unmatched { input, index } => {
+                    if self.join == JoinPolicy::Error {
+                        self.channel.send_error(
+                            Some(index),
+                            format!(
+                                "no frame with the same index in the other inputs, found in `{}`",
+                                self.input_stream_names[input]
+                            ),
+                        );
+                    }
+                    continue;
                 }
-            });
+            };
 
-            self.handler.handle(messages, &self.channel);
+            let should_break = messages
+                .iter()
+                .any(|message| matches!(message, PipelineMessage::End));
+
+            // !! a frame that failed upstream is skipped here and passed on
+            let upstream_error = messages.iter().find_map(|message| match message {
+                PipelineMessage::Error(error) => Some(error.clone()),
+                _ => None,
+            });
+            if let Some(error) = upstream_error {
+                if !should_break {
+                    self.channel.send(PipelineMessage::Error(error));
+                    continue;
+                }
+            } else {
+                let index = messages.iter().find_map(message_index);
+                if let Err(cause) = self.handlers[0].handle(messages, &self.channel) {
+                    self.channel.send_error(index, cause);
+                }
+            }
 
             if should_break {
                 break;
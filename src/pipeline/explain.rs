@@ -0,0 +1,179 @@
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory};
+
+use super::{executor::StageSpec, validate, VVSubCommand};
+
+/// Describes a pipeline without creating any handler: the stream graph in Graphviz DOT format
+/// followed by a table of the stages with their parsed arguments, inputs and output.
+/// The table is written as DOT comments so that the output can be given to `dot` as is.
+/// Fails with the same problems `Pipeline::execute` would report.
+pub(crate) fn explain(specs: &[StageSpec]) -> Result<String, String> {
+    if specs.is_empty() {
+        return Err("Should have at least one command".to_string());
+    }
+    validate::validate(specs)?;
+
+    let mut problems = vec![];
+    let mut arguments = vec![];
+    for (idx, spec) in specs.iter().enumerate() {
+        if spec.custom {
+            arguments.push("(custom stage)".to_string());
+            continue;
+        }
+        let argv = std::iter::once("vv").chain(spec.args.iter().map(String::as_str));
+        match VVSubCommand::command().try_get_matches_from(argv) {
+            Ok(matches) => arguments.push(match matches.subcommand() {
+                Some((_, matches)) => describe_args(matches),
+                None => String::new(),
+            }),
+            Err(err) => problems.push(format!(
+                "stage {} ({}): {}",
+                idx,
+                spec.name,
+                err.to_string().trim()
+            )),
+        }
+    }
+    if !problems.is_empty() {
+        return Err(problems.join("\n"));
+    }
+
+    Ok(format!(
+        "{}\n{}",
+        to_dot(specs),
+        to_table(specs, &arguments)
+    ))
+}
+
+/// `name=value` for every argument, defaults included and marked as such
+fn describe_args(matches: &ArgMatches) -> String {
+    let mut described = vec![];
+    for id in matches.ids() {
+        let id = id.as_str();
+        let values = match matches.get_raw(id) {
+            Some(values) => values
+                .map(|value| value.to_string_lossy().into_owned())
+                .collect::<Vec<_>>(),
+            None => continue,
+        };
+        let default = if matches.value_source(id) == Some(ValueSource::DefaultValue) {
+            " (default)"
+        } else {
+            ""
+        };
+        described.push(format!("{}={}{}", id, values.join(","), default));
+    }
+    described.join(" ")
+}
+
+fn to_dot(specs: &[StageSpec]) -> String {
+    let mut dot = vec![
+        "digraph pipeline {".to_string(),
+        "  rankdir=LR;".to_string(),
+    ];
+    for (idx, spec) in specs.iter().enumerate() {
+        dot.push(format!(
+            "  stage_{} [shape=box, label=\"{}: {}\"];",
+            idx,
+            idx,
+            escape(&spec.name)
+        ));
+    }
+    for (idx, spec) in specs.iter().enumerate() {
+        for input in &spec.input_stream_names {
+            // !! validated above, every input has exactly one producer
+            if let Some(producer) = specs.iter().position(|s| &s.output_name == input) {
+                dot.push(format!(
+                    "  stage_{} -> stage_{} [label=\"{}\"];",
+                    producer,
+                    idx,
+                    escape(input)
+                ));
+            }
+        }
+    }
+    dot.push("}".to_string());
+    dot.join("\n") + "\n"
+}
+
+fn to_table(specs: &[StageSpec], arguments: &[String]) -> String {
+    let header = ["#", "command", "inputs", "output", "arguments"].map(String::from);
+    let mut rows = vec![header];
+    for (idx, spec) in specs.iter().enumerate() {
+        let mut inputs = spec.input_stream_names.join(",");
+        if let Some(join) = spec.join {
+            inputs.push_str(&format!(" (join: {:?})", join).to_lowercase());
+        }
+        let mut name = spec.name.clone();
+        if spec.jobs > 1 {
+            name.push_str(&format!(" x{}", spec.jobs));
+        }
+        rows.push([
+            idx.to_string(),
+            name,
+            inputs,
+            spec.output_name.clone(),
+            arguments[idx].clone(),
+        ]);
+    }
+
+    let widths = (0..5)
+        .map(|col| rows.iter().map(|row| row[col].len()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    rows.iter()
+        .map(|row| {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            format!("// {}\n", line.trim_end())
+        })
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(stages: &[&str]) -> Vec<StageSpec> {
+        stages
+            .iter()
+            .map(|stage| StageSpec::parse(stage.split(' ').map(String::from).collect()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_explain() {
+        let explanation = explain(&specs(&[
+            "read ./original +output=original",
+            "read ./reconstructed +output=reconstructed",
+            "metrics --metrics cd,hd +input=original,reconstructed +output=metrics",
+            "downsample -p 5 +input=original +output=ds +jobs=4",
+            "write ./metrics +input=metrics",
+        ]))
+        .unwrap();
+        assert!(explanation.starts_with("digraph pipeline {"));
+        assert!(explanation.contains("stage_0 -> stage_2 [label=\"original\"];"));
+        assert!(explanation.contains("stage_1 -> stage_2 [label=\"reconstructed\"];"));
+        assert!(explanation.contains("stage_2 -> stage_4 [label=\"metrics\"];"));
+        assert!(explanation.contains("metrics=cd,hd"));
+        assert!(explanation.contains("points_per_voxel=5"));
+        assert!(explanation.contains("downsample x4"));
+        assert!(explanation.contains("output_format=pcd (default)"));
+    }
+
+    #[test]
+    fn test_explain_reports_problems() {
+        let err = explain(&specs(&["read ./a +output=a", "downsample +input=a"])).unwrap_err();
+        assert!(err.contains("stage 1 (downsample)"));
+
+        let err = explain(&specs(&["read ./a +output=a", "write ./b +input=missing"])).unwrap_err();
+        assert!(err.contains("no output stream with name `missing`"));
+    }
+}
@@ -0,0 +1,512 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crossbeam_channel::{Receiver, Select};
+
+use super::{
+    channel::{Channel, MAX_MESSAGES},
+    PipelineMessage, Progress,
+};
+
+/// What a stage with several inputs does with a frame that has no counterpart
+/// with the same index in the other inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinPolicy {
+    /// Skip the frame silently
+    Drop,
+    /// Skip the frame and report it as a failure of the stage
+    #[default]
+    Error,
+    /// Pair every frame of the first input with the frame of nearest index in each other input
+    Nearest,
+}
+
+impl std::str::FromStr for JoinPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop" => Ok(JoinPolicy::Drop),
+            "error" => Ok(JoinPolicy::Error),
+            "nearest" => Ok(JoinPolicy::Nearest),
+            _ => Err(format!(
+                "Unknown join policy `{}`, expected drop, error or nearest",
+                s
+            )),
+        }
+    }
+}
+
+/// Index of the frame a message belongs to, if any
+pub(crate) fn message_index(message: &PipelineMessage) -> Option<u32> {
+    match message {
+        PipelineMessage::IndexedPointCloud(_, i)
+        | PipelineMessage::IndexedPointCloudNormal(_, i) => Some(*i),
+        PipelineMessage::Error(error) => error.index,
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Joined {
+    /// One message per input, handed to the stage together
+    Group(Vec<PipelineMessage>),
+    /// A frame of `input` without a match in the other inputs
+    Unmatched { input: usize, index: u32 },
+}
+
+/// Groups the messages of several inputs by frame index.
+///
+/// Every input is expected to send its frames in increasing index order, frames may be
+/// missing (e.g. a file that could not be read). With a single input messages are passed
+/// through unchanged.
+///
+/// While waiting on one input, the others are queued up to `MAX_MESSAGES` each, like the
+/// streams between stages, unless they share a producer (see `with_shared_producer`).
+pub(crate) struct Joiner {
+    inputs: Vec<Receiver<PipelineMessage>>,
+    queues: Vec<VecDeque<PipelineMessage>>,
+    /// queues are not capped, set when the inputs come from a common upstream stage
+    shared_producer: bool,
+    ended: Vec<bool>,
+    /// for `JoinPolicy::Nearest`, the last frame taken from each input,
+    /// it may still be the nearest one for the next frame of the first input
+    previous: Vec<Option<PipelineMessage>>,
+    policy: JoinPolicy,
+    /// set for stages reading their inputs one after another instead of joining them
+    concat: Option<Concat>,
+    finished: bool,
+}
+
+/// Position of a `Joiner` reading its inputs one after another
+struct Concat {
+    current: usize,
+    /// added to the indices of the current input
+    offset: u32,
+    /// highest index sent so far, after the offset
+    last_index: Option<u32>,
+}
+
+impl Joiner {
+    pub fn new(inputs: Vec<Receiver<PipelineMessage>>, policy: JoinPolicy) -> Self {
+        let n = inputs.len();
+        Self {
+            inputs,
+            queues: vec![VecDeque::new(); n],
+            shared_producer: false,
+            ended: vec![false; n],
+            previous: vec![None; n],
+            policy,
+            concat: None,
+            finished: false,
+        }
+    }
+
+    /// Reads every input until its `End` before moving on to the next one. Indices are
+    /// shifted to follow the highest index of the previous inputs, gaps are kept.
+    /// The inputs after the current one are queued meanwhile, up to `MAX_MESSAGES` each
+    /// unless they share a producer, in which case they are queued whole.
+    pub fn concat(inputs: Vec<Receiver<PipelineMessage>>) -> Self {
+        let mut joiner = Self::new(inputs, JoinPolicy::default());
+        joiner.concat = Some(Concat {
+            current: 0,
+            offset: 0,
+            last_index: None,
+        });
+        joiner
+    }
+
+    /// Lifts the cap on the queues. A producer feeding several inputs of this stage blocks
+    /// on the input whose queue is full, a capped queue would then wait forever for the
+    /// input this stage is waiting on.
+    pub fn with_shared_producer(mut self, shared_producer: bool) -> Self {
+        self.shared_producer = shared_producer;
+        self
+    }
+
+    /// The next group to hand to the stage, the last one contains `End` from every input
+    pub fn next(&mut self) -> Option<Joined> {
+        if self.finished {
+            return None;
+        }
+        if self.concat.is_some() {
+            let message = self.next_concatenated();
+            self.finished = matches!(message, PipelineMessage::End);
+            return Some(Joined::Group(vec![message]));
+        }
+        if self.inputs.len() == 1 {
+            let message = self.inputs[0].recv().unwrap_or(PipelineMessage::End);
+            self.finished = matches!(message, PipelineMessage::End);
+            return Some(Joined::Group(vec![message]));
+        }
+
+        loop {
+            for k in 0..self.inputs.len() {
+                self.fill(k);
+            }
+
+            // !! messages without a frame index cannot be joined, failures are passed on alone
+            if let Some(k) = (0..self.inputs.len()).find(|&k| self.head_is_unindexed(k)) {
+                let message = self.queues[k].pop_front().unwrap();
+                if matches!(message, PipelineMessage::Error(_)) {
+                    return Some(Joined::Group(vec![message]));
+                }
+                continue;
+            }
+
+            let heads = (0..self.inputs.len())
+                .map(|k| self.queues[k].front().and_then(message_index))
+                .collect::<Vec<_>>();
+
+            let done = match self.policy {
+                JoinPolicy::Nearest => heads[0].is_none(),
+                _ => heads.iter().all(Option::is_none),
+            };
+            if done {
+                self.finished = true;
+                return Some(Joined::Group(vec![PipelineMessage::End; self.inputs.len()]));
+            }
+
+            return Some(match self.policy {
+                JoinPolicy::Drop | JoinPolicy::Error => self.join_exact(&heads),
+                JoinPolicy::Nearest => self.join_nearest(heads[0].unwrap()),
+            });
+        }
+    }
+
+    /// Like `next`, also reports how long the stage waited for its input and how many
+    /// messages are still queued
+    pub fn next_reported(&mut self, channel: &Channel) -> Option<Joined> {
+        let waiting = Instant::now();
+        let joined = self.next();
+        channel.report(Progress::Input {
+            queued: self.queued(),
+            blocked: waiting.elapsed(),
+        });
+        joined
+    }
+
+    /// Messages received or waiting to be received, not yet handed to the stage
+    pub fn queued(&self) -> usize {
+        self.inputs.iter().map(Receiver::len).sum::<usize>()
+            + self.queues.iter().map(VecDeque::len).sum::<usize>()
+    }
+
+    fn join_exact(&mut self, heads: &[Option<u32>]) -> Joined {
+        let lowest = heads.iter().flatten().min().copied().unwrap();
+        if heads.iter().all(|&head| head == Some(lowest)) {
+            return Joined::Group(
+                self.queues
+                    .iter_mut()
+                    .map(|queue| queue.pop_front().unwrap())
+                    .collect(),
+            );
+        }
+        let input = heads.iter().position(|&head| head == Some(lowest)).unwrap();
+        self.queues[input].pop_front();
+        Joined::Unmatched {
+            input,
+            index: lowest,
+        }
+    }
+
+    fn join_nearest(&mut self, index: u32) -> Joined {
+        let mut group = vec![];
+        for k in 1..self.inputs.len() {
+            // !! move past every frame up to `index`, keeping the last one as a candidate
+            loop {
+                self.fill(k);
+                if self.head_is_unindexed(k) {
+                    self.queues[k].pop_front();
+                    continue;
+                }
+                match self.queues[k].front().and_then(message_index) {
+                    Some(i) if i <= index => self.previous[k] = self.queues[k].pop_front(),
+                    _ => break,
+                }
+            }
+            let before = self.previous[k].as_ref();
+            let after = self.queues[k]
+                .front()
+                .filter(|message| message_index(message).is_some());
+            let nearest = match (before, after) {
+                (Some(before), Some(after)) => {
+                    let before_distance = index - message_index(before).unwrap();
+                    let after_distance = message_index(after).unwrap() - index;
+                    if after_distance < before_distance {
+                        after
+                    } else {
+                        before
+                    }
+                }
+                (Some(message), None) | (None, Some(message)) => message,
+                (None, None) => {
+                    self.queues[0].pop_front();
+                    return Joined::Unmatched { input: 0, index };
+                }
+            };
+            group.push(nearest.clone());
+        }
+        let mut primary = vec![self.queues[0].pop_front().unwrap()];
+        primary.extend(group);
+        Joined::Group(primary)
+    }
+
+    fn next_concatenated(&mut self) -> PipelineMessage {
+        loop {
+            let current = self.concat.as_ref().unwrap().current;
+            self.fill(current);
+            let message = self.queues[current]
+                .pop_front()
+                .unwrap_or(PipelineMessage::End);
+            let concat = self.concat.as_mut().unwrap();
+            if matches!(message, PipelineMessage::End) {
+                if concat.current + 1 == self.inputs.len() {
+                    return message;
+                }
+                concat.current += 1;
+                concat.offset = concat.last_index.map_or(0, |last| last + 1);
+                continue;
+            }
+            let mut message = message;
+            let index = match &mut message {
+                PipelineMessage::IndexedPointCloud(_, i)
+                | PipelineMessage::IndexedPointCloudNormal(_, i) => Some(i),
+                PipelineMessage::Error(error) => error.index.as_mut(),
+                _ => None,
+            };
+            if let Some(index) = index {
+                *index += concat.offset;
+                concat.last_index = concat.last_index.max(Some(*index));
+            }
+            return message;
+        }
+    }
+
+    fn head_is_unindexed(&self, k: usize) -> bool {
+        match self.queues[k].front() {
+            Some(PipelineMessage::End) | None => false,
+            Some(message) => message_index(message).is_none(),
+        }
+    }
+
+    /// Receives until input `k` has a message queued or has ended. Messages arriving on the
+    /// other inputs in the meantime are queued too, until their queue is full.
+    fn fill(&mut self, k: usize) {
+        while self.queues[k].is_empty() && !self.ended[k] {
+            let mut select = Select::new();
+            let mut indices = vec![];
+            for (i, input) in self.inputs.iter().enumerate() {
+                // !! a full queue leaves its messages in the stream, which holds back its producer
+                let full = !self.shared_producer && self.queues[i].len() >= MAX_MESSAGES;
+                if !self.ended[i] && !full {
+                    select.recv(input);
+                    indices.push(i);
+                }
+            }
+            let operation = select.select();
+            let i = indices[operation.index()];
+            // !! an input whose stage stopped without sending `End` is treated as ended
+            let message = operation
+                .recv(&self.inputs[i])
+                .unwrap_or(PipelineMessage::End);
+            if matches!(message, PipelineMessage::End) {
+                self.ended[i] = true;
+            }
+            self.queues[i].push_back(message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::PointCloud;
+    use crate::pipeline::StageError;
+    use crossbeam_channel::{bounded, unbounded};
+
+    fn frame(i: u32) -> PipelineMessage {
+        let pc = PointCloud {
+            number_of_points: 0,
+            points: vec![],
+        };
+        PipelineMessage::IndexedPointCloud(pc, i)
+    }
+
+    fn input(indices: &[u32]) -> Receiver<PipelineMessage> {
+        let (tx, rx) = unbounded();
+        for &i in indices {
+            tx.send(frame(i)).unwrap();
+        }
+        tx.send(PipelineMessage::End).unwrap();
+        rx
+    }
+
+    /// Frame indices of every group and unmatched frame, in order
+    fn run(inputs: Vec<Receiver<PipelineMessage>>, policy: JoinPolicy) -> Vec<String> {
+        let mut joiner = Joiner::new(inputs, policy);
+        let mut result = vec![];
+        while let Some(joined) = joiner.next() {
+            result.push(match joined {
+                Joined::Group(messages) => messages
+                    .iter()
+                    .map(|m| match message_index(m) {
+                        Some(i) => i.to_string(),
+                        None => "end".to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+                Joined::Unmatched { input, index } => format!("unmatched {}:{}", input, index),
+            });
+        }
+        result
+    }
+
+    #[test]
+    fn test_join_by_index() {
+        let result = run(
+            vec![input(&[0, 1, 2, 3]), input(&[0, 2, 3, 4])],
+            JoinPolicy::Drop,
+        );
+        assert_eq!(
+            result,
+            vec![
+                "0,0",
+                "unmatched 0:1",
+                "2,2",
+                "3,3",
+                "unmatched 1:4",
+                "end,end"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_nearest() {
+        let result = run(
+            vec![input(&[0, 1, 2, 5]), input(&[0, 2, 3])],
+            JoinPolicy::Nearest,
+        );
+        assert_eq!(result, vec!["0,0", "1,0", "2,2", "5,3", "end,end"]);
+
+        let result = run(vec![input(&[0, 1]), input(&[])], JoinPolicy::Nearest);
+        assert_eq!(result, vec!["unmatched 0:0", "unmatched 0:1", "end,end"]);
+    }
+
+    #[test]
+    fn test_join_upstream_errors() {
+        let (tx, rx) = unbounded();
+        tx.send(frame(0)).unwrap();
+        tx.send(PipelineMessage::Error(StageError {
+            stage: "read".to_string(),
+            index: Some(1),
+            cause: "unreadable".to_string(),
+        }))
+        .unwrap();
+        tx.send(PipelineMessage::DummyForIncrement).unwrap();
+        tx.send(frame(2)).unwrap();
+        tx.send(PipelineMessage::End).unwrap();
+
+        let result = run(vec![rx, input(&[0, 1, 2])], JoinPolicy::Error);
+        assert_eq!(result, vec!["0,0", "1,1", "2,2", "end,end"]);
+    }
+
+    #[test]
+    fn test_concat() {
+        let mut joiner = Joiner::concat(vec![input(&[0, 1, 3]), input(&[]), input(&[1, 2])]);
+        let mut indices = vec![];
+        while let Some(Joined::Group(messages)) = joiner.next() {
+            indices.push(message_index(&messages[0]));
+        }
+        assert_eq!(
+            indices,
+            vec![Some(0), Some(1), Some(3), Some(5), Some(6), None]
+        );
+    }
+
+    #[test]
+    fn test_concat_holds_back_later_inputs() {
+        let (tx, first) = unbounded();
+        let later = input(&(0..100).collect::<Vec<_>>());
+        let remaining = later.clone();
+        let joiner = std::thread::spawn(move || {
+            let mut joiner = Joiner::concat(vec![first, later]);
+            let mut count = 0;
+            while joiner.next().is_some() {
+                count += 1;
+            }
+            count
+        });
+
+        let held_back = 101 - MAX_MESSAGES;
+        let start = Instant::now();
+        while remaining.len() > held_back && start.elapsed().as_secs() < 10 {
+            std::thread::yield_now();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(remaining.len(), held_back);
+
+        tx.send(frame(0)).unwrap();
+        tx.send(PipelineMessage::End).unwrap();
+        assert_eq!(joiner.join().unwrap(), 102);
+    }
+
+    #[test]
+    fn test_concat_shared_producer() {
+        // !! one producer feeding both inputs, with more frames than a stream channel holds
+        let (tx_a, rx_a) = bounded(30);
+        let (tx_b, rx_b) = bounded(30);
+        let producer = std::thread::spawn(move || {
+            for i in 0..100 {
+                tx_a.send(frame(i)).unwrap();
+                tx_b.send(frame(i)).unwrap();
+            }
+            tx_a.send(PipelineMessage::End).unwrap();
+            tx_b.send(PipelineMessage::End).unwrap();
+        });
+        let mut joiner = Joiner::concat(vec![rx_a, rx_b]).with_shared_producer(true);
+        let mut indices = vec![];
+        while let Some(Joined::Group(messages)) = joiner.next() {
+            indices.push(message_index(&messages[0]));
+        }
+        producer.join().unwrap();
+        assert_eq!(indices.len(), 201);
+        assert!(indices[..200]
+            .iter()
+            .enumerate()
+            .all(|(i, index)| *index == Some(i as u32)));
+    }
+
+    #[test]
+    fn test_fast_input_is_held_back() {
+        let fast = input(&(0..100).collect::<Vec<_>>());
+        let remaining = fast.clone();
+        let (tx, slow) = unbounded();
+        let joiner = std::thread::spawn(move || run(vec![fast, slow], JoinPolicy::Drop).len());
+
+        // !! the joiner waits on the slow input once the fast one is a full queue ahead
+        let held_back = 101 - MAX_MESSAGES;
+        let start = Instant::now();
+        while remaining.len() > held_back && start.elapsed().as_secs() < 10 {
+            std::thread::yield_now();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(remaining.len(), held_back);
+
+        for i in 0..100 {
+            tx.send(frame(i)).unwrap();
+        }
+        tx.send(PipelineMessage::End).unwrap();
+        assert_eq!(joiner.join().unwrap(), 101);
+    }
+
+    #[test]
+    fn test_single_input_passes_through() {
+        let (tx, rx) = unbounded();
+        tx.send(frame(3)).unwrap();
+        tx.send(PipelineMessage::DummyForIncrement).unwrap();
+        tx.send(PipelineMessage::End).unwrap();
+        let result = run(vec![rx], JoinPolicy::Error);
+        assert_eq!(result, vec!["3", "end", "end"]);
+    }
+}
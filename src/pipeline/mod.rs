@@ -1,8 +1,14 @@
+mod builder;
 mod channel;
+pub mod config;
 mod executor;
+mod explain;
+mod join;
+mod parallel;
+mod progress;
 pub mod subcommands;
+mod validate;
 use clap::Parser;
-use crossbeam_channel::Receiver;
 // use std::sync::mpsc::Receiver;
 
 use crate::{
@@ -11,15 +17,22 @@ use crate::{
 };
 
 use self::{
-    executor::Executor,
-    executor::ExecutorBuilder,
+    config::PipelineConfig,
+    executor::StageSpec,
+    progress::ProgressDisplay,
     subcommands::{
-        convert, dash, downsample, info, metrics, normal_estimation, read, render, upsample, write,
-        Convert, Dash, Downsampler, Info, MetricsCalculator, NormalEstimation, Read, Render,
-        Subcommand, Upsampler, Write,
+        concat, convert, crop, dash, denoise, diff, downsample, exec, info, merge, metrics,
+        normal_estimation, read, register, render, retime, sample, select, stats, transform,
+        upsample, voxelize, write, Concat, Convert, Crop, Dash, Denoise, Diff, Downsampler, Exec,
+        Info, Merge, MetricsCalculator, NormalEstimation, Read, Register, Render, Retime, Sample,
+        Select, Stats, Subcommand, Transform, Upsampler, Voxelize, Write,
     },
 };
 
+pub use builder::{PipelineBuilder, PipelineGraph, PipelineHandle};
+pub use channel::Channel;
+pub use join::JoinPolicy;
+
 pub type SubcommandCreator = Box<dyn Fn(Vec<String>) -> Box<dyn Subcommand>>;
 
 fn subcommand(s: &str) -> Option<SubcommandCreator> {
@@ -35,6 +48,19 @@ fn subcommand(s: &str) -> Option<SubcommandCreator> {
         // "play" => Some(Box::from(Play::from_args)),
         "dash" => Some(Box::from(Dash::from_args)),
         "info" => Some(Box::from(Info::from_args)),
+        "exec" => Some(Box::from(Exec::from_args)),
+        "merge" => Some(Box::from(Merge::from_args)),
+        "concat" => Some(Box::from(Concat::from_args)),
+        "select" => Some(Box::from(Select::from_args)),
+        "transform" => Some(Box::from(Transform::from_args)),
+        "crop" => Some(Box::from(Crop::from_args)),
+        "voxelize" => Some(Box::from(Voxelize::from_args)),
+        "denoise" => Some(Box::from(Denoise::from_args)),
+        "sample" => Some(Box::from(Sample::from_args)),
+        "register" => Some(Box::from(Register::from_args)),
+        "retime" => Some(Box::from(Retime::from_args)),
+        "stats" => Some(Box::from(Stats::from_args)),
+        "diff" => Some(Box::from(Diff::from_args)),
         _ => None,
     }
 }
@@ -45,21 +71,114 @@ pub enum PipelineMessage {
     IndexedPointCloudNormal(PointCloud<PointXyzRgbaNormal>, u32),
     // PointCloud(PointCloud<PointXyzRgba>),
     Metrics(Metrics),
+    /// a frame that a stage failed to produce, sent in place of the frame
+    Error(StageError),
     End,
     DummyForIncrement,
 }
 
+/// Why a stage failed on a frame, `index` is `None` when the failure is not tied to one frame
+#[derive(Debug, Clone)]
+pub struct StageError {
+    pub stage: String,
+    pub index: Option<u32>,
+    pub cause: String,
+}
+
+impl std::fmt::Display for StageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "frame {}: {}", index, self.cause),
+            None => write!(f, "{}", self.cause),
+        }
+    }
+}
+
+/// What the pipeline does once a stage fails on a frame
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Drop the failed frame and keep going
+    #[default]
+    Skip,
+    /// Stop every stage as soon as possible
+    Abort,
+}
+
 #[derive(Debug)]
 pub enum Progress {
     Incr,
     Completed,
+    Error(StageError),
+    /// Sent each time a stage gets its next input: the messages still queued in its inputs
+    /// and how long it waited for this one
+    Input {
+        queued: usize,
+        blocked: std::time::Duration,
+    },
+    /// Sent once the thread of a stage is done, with the time since it started
+    Finished(std::time::Duration),
 }
+
+/// Options given before the first subcommand, e.g. `vv --on-error abort read ...`
+#[derive(Debug, Default)]
+struct GlobalOptions {
+    on_error: ErrorPolicy,
+    /// where to write the per-stage report once the pipeline is done
+    report: Option<String>,
+    no_progress: bool,
+    explain: bool,
+}
+
+impl GlobalOptions {
+    /// Parses the leading options and returns them with the remaining arguments
+    fn parse(args: &[String]) -> Result<(Self, &[String]), String> {
+        let mut options = Self::default();
+        let mut idx = 0;
+        while idx < args.len() && args[idx].starts_with("--") && args[idx] != "--help" {
+            if args[idx] == "--no-progress" || args[idx] == "--explain" {
+                options.no_progress |= args[idx] == "--no-progress";
+                options.explain |= args[idx] == "--explain";
+                idx += 1;
+                continue;
+            }
+            let (name, inline_value) = match args[idx].split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (args[idx].as_str(), None),
+            };
+            let value = match inline_value {
+                Some(value) => value,
+                None => {
+                    idx += 1;
+                    args.get(idx)
+                        .cloned()
+                        .ok_or(format!("Expected a value for {}", name))?
+                }
+            };
+            match name {
+                "--on-error" => {
+                    options.on_error = <ErrorPolicy as clap::ValueEnum>::from_str(&value, true)
+                        .map_err(|_| {
+                            format!(
+                                "Invalid value `{}` for --on-error, expected skip or abort",
+                                value
+                            )
+                        })?
+                }
+                "--report" => options.report = Some(value),
+                _ => return Err(format!("Unknown option {}", name)),
+            }
+            idx += 1;
+        }
+        Ok((options, &args[idx..]))
+    }
+}
+
 pub struct Pipeline;
 
 impl Pipeline {
     pub fn execute() {
-        let (mut executors, progresses) = match Self::gather_pipeline_from_args() {
-            Ok((executors, progresses)) => (executors, progresses),
+        let (options, stages) = match Self::gather_stages_from_args() {
+            Ok(pipeline) => pipeline,
             Err(err) => {
                 println!("Error: {}", err);
                 println!("Use --help for more information");
@@ -67,70 +186,65 @@ impl Pipeline {
             }
         };
 
-        let mut handles = vec![];
-        let mut names = vec![];
-        let mut progress_recvs = vec![];
-        let all_input_names: Vec<Vec<String>> = executors.iter().map(|e| e.input_names()).collect();
-
-        // !! set named input outputs
-        for (idx, input_names) in all_input_names.iter().enumerate() {
-            let mut inputs = vec![];
-            for input_name in input_names {
-                for executor in &mut executors {
-                    if executor.output_name().eq(input_name) {
-                        inputs.push(executor.output());
-                    }
+        // !! `--explain` stops before any handler is created, so nothing is read or written
+        if options.explain {
+            match Self::explain(stages) {
+                Ok(explanation) => print!("{}", explanation),
+                Err(err) => {
+                    println!("Error: {}", err);
+                    std::process::exit(1);
                 }
             }
-            executors[idx].set_inputs(inputs);
+            return;
         }
 
-        for (exec, progress) in executors.into_iter().zip(progresses) {
-            names.push(exec.name());
-            progress_recvs.push(progress);
-            handles.push(exec.run());
-        }
-
-        // println!("progress_recvs.len(): {}", progress_recvs.len());
-        let mut completed = 0;
-        let mut progress = vec![0; progress_recvs.len()];
-        while completed < progress_recvs.len() {
-            for (idx, recv) in progress_recvs.iter().enumerate() {
-                while let Ok(prog) = recv.try_recv() {
-                    match prog {
-                        Progress::Incr => {
-                            progress[idx] += 1;
-                        }
-                        Progress::Completed => {
-                            completed += 1;
-                        }
-                    }
+        let mut handle =
+            match Self::create_executors(stages, options.on_error).and_then(PipelineGraph::run) {
+                Ok(handle) => handle,
+                Err(err) => {
+                    println!("Error: {}", err);
+                    println!("Use --help for more information");
+                    return;
                 }
+            };
+
+        let mut display = (!options.no_progress).then(|| ProgressDisplay::new(&handle));
+        while !handle.is_finished() {
+            handle.poll();
+            if let Some(display) = display.as_mut() {
+                display.update(&handle);
             }
-            /*
-            println!("=======================");
-            for i in 0..progress.len() {
-                println!("{}: {}", names[i], progress[i])
+            std::thread::sleep(std::time::Duration::from_millis(200));
+        }
+        handle.poll();
+        if let Some(display) = display {
+            display.finish(&handle);
+        }
+
+        if let Some(path) = &options.report {
+            let report =
+                serde_json::to_string_pretty(&handle.report()).expect("report should serialize");
+            if let Err(e) = std::fs::write(path, report) {
+                println!("Cannot write report to {}: {}", path, e);
             }
-            println!("=======================");
-            */
-            std::thread::sleep(std::time::Duration::from_secs(1));
         }
 
-        for handle in handles {
-            handle.join().expect("Failed to wait for thread");
+        if let Err(summary) = handle.join() {
+            println!("{}", summary);
+            std::process::exit(1);
         }
     }
 
-    // !! collect all the arguments from terminal and create the pipeline
-    fn gather_pipeline_from_args() -> Result<(Vec<Executor>, Vec<Receiver<Progress>>), String> {
+    // !! collect all the arguments from terminal and split them into the arguments of each stage
+    fn gather_stages_from_args() -> Result<(GlobalOptions, Vec<Vec<String>>), String> {
         let args: Vec<String> = std::env::args().collect();
-        let mut executors = vec![];
-        let mut progresses = vec![];
-        let mut command_creator: Option<SubcommandCreator> = None;
-        let mut accumulated_args: Vec<String> = vec![];
 
-        let mut executor_builder = ExecutorBuilder::new();
+        let (options, args) = GlobalOptions::parse(&args[1..])?;
+        // !! keep the program name in front so that the indices below stay the same
+        let args: Vec<String> = std::iter::once("vv".to_string())
+            .chain(args.iter().cloned())
+            .collect();
+
         // !! check argument length
         if args.len() < 2 {
             display_main_help_msg();
@@ -141,6 +255,12 @@ impl Pipeline {
             display_main_help_msg();
         }
 
+        // !! `vv run pipeline.toml` reads the stages from a file instead
+        if args[1] == "run" {
+            let config = PipelineConfig::from_run_args(&args[1..])?;
+            return Ok((options, config.stage_args()));
+        }
+
         // !! check the second argument, which is the name of the subcommand, we want at least one subcommand
         if !Self::if_at_least_one_command(&args[1]) {
             return Err(format!(
@@ -150,34 +270,59 @@ impl Pipeline {
         }
 
         // !! skip the first argument, which is the name of the program
-        for arg in args.iter().skip(1) {
-            let is_command = subcommand(arg);
-            if is_command.is_some() {
-                if let Some(creator) = command_creator.take()
-                // !! the first take is always None
-                {
-                    // !! enters here when there are at least two subcommands
-                    let forwarded_args = accumulated_args;
-                    accumulated_args = vec![];
-                    let (executor, progress) = executor_builder.create(forwarded_args, creator)?;
-                    executors.push(executor);
-                    progresses.push(progress);
-                }
-                command_creator = is_command;
+        let stages = Self::split_stages(&args[1..]);
+        Ok((options, stages))
+    }
+
+    // !! split the arguments into one group per subcommand, each group starts with the subcommand name
+    fn split_stages(args: &[String]) -> Vec<Vec<String>> {
+        let mut stages: Vec<Vec<String>> = vec![];
+        for arg in args {
+            match stages.last_mut() {
+                Some(stage) if subcommand(arg).is_none() => stage.push(arg.clone()),
+                _ => stages.push(vec![arg.clone()]),
             }
-            accumulated_args.push(arg.clone());
+        }
+        stages
+    }
+
+    fn explain(stages: Vec<Vec<String>>) -> Result<String, String> {
+        let specs = stages
+            .into_iter()
+            .map(StageSpec::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        explain::explain(&specs)
+    }
+
+    fn create_executors(
+        stages: Vec<Vec<String>>,
+        on_error: ErrorPolicy,
+    ) -> Result<PipelineGraph, String> {
+        if stages.is_empty() {
+            return Err("Should have at least one command".to_string());
         }
 
-        // !! the following is duplicated from the above to handle the case of only one command
-        // !! TODO: maybe better to refactor as "do while" loop
-        let creator = command_creator
-            .take()
-            .ok_or("Should have at least one command")?;
+        let specs = stages
+            .into_iter()
+            .map(StageSpec::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        // !! check the whole graph before any handler is created or thread is spawned,
+        // !! unless help is asked for, in which case the handler prints it and exits
+        let wants_help = specs
+            .iter()
+            .any(|spec| spec.args.iter().any(|arg| arg == "--help" || arg == "-h"));
+        if !wants_help {
+            validate::validate(&specs)?;
+        }
 
-        let (executor, progress) = executor_builder.create(accumulated_args, creator)?;
-        executors.push(executor);
-        progresses.push(progress);
-        Ok((executors, progresses))
+        let mut stages = vec![];
+        for spec in specs {
+            let creator =
+                subcommand(&spec.name).ok_or(format!("Unknown command `{}`", spec.name))?;
+            let handlers = (0..spec.jobs).map(|_| creator(spec.args.clone())).collect();
+            stages.push((spec, handlers));
+        }
+        Ok(PipelineGraph::new(stages, on_error))
     }
 
     fn if_at_least_one_command(first_arg: &str) -> bool {
@@ -207,10 +352,63 @@ enum VVSubCommand {
     Info(info::Args),
     #[clap(name = "dash")]
     Dash(dash::Args),
+    #[clap(name = "exec")]
+    Exec(exec::Args),
+    #[clap(name = "merge")]
+    Merge(merge::Args),
+    #[clap(name = "concat")]
+    Concat(concat::Args),
+    #[clap(name = "select")]
+    Select(select::Args),
+    #[clap(name = "transform")]
+    Transform(transform::Args),
+    #[clap(name = "crop")]
+    Crop(crop::Args),
+    #[clap(name = "voxelize")]
+    Voxelize(voxelize::Args),
+    #[clap(name = "denoise")]
+    Denoise(denoise::Args),
+    #[clap(name = "sample")]
+    Sample(sample::Args),
+    #[clap(name = "register")]
+    Register(register::Args),
+    #[clap(name = "retime")]
+    Retime(retime::Args),
+    #[clap(name = "stats")]
+    Stats(stats::Args),
+    #[clap(name = "diff")]
+    Diff(diff::Args),
+    #[clap(name = "run")]
+    Run(config::Args),
+}
+
+/// Only used for the main help message, the options are parsed by `GlobalOptions`
+#[derive(Parser)]
+#[clap(name = "vv")]
+#[allow(dead_code)]
+struct VVCli {
+    /// What to do when a stage fails on a frame
+    #[clap(long, value_enum, default_value_t = ErrorPolicy::Skip)]
+    on_error: ErrorPolicy,
+
+    /// Writes the wall time, frame count and time blocked on input of every stage to a JSON file
+    #[clap(long, value_name = "FILE")]
+    report: Option<String>,
+
+    /// Hides the per-stage progress bars
+    #[clap(long)]
+    no_progress: bool,
+
+    /// Prints the stream graph in DOT format and the arguments of every stage, without running anything
+    #[clap(long)]
+    explain: bool,
+
+    #[clap(subcommand)]
+    command: VVSubCommand,
 }
 
 fn display_main_help_msg() {
-    let _subcommand = VVSubCommand::parse_from(&["vv", "--help"]);
+    let _cli = VVCli::parse_from(["vv", "--help"]);
 }
 
 #[cfg(test)]
@@ -228,4 +426,47 @@ mod pipeline_mod_test {
         assert!(Pipeline::if_at_least_one_command("convert"));
         assert!(!Pipeline::if_at_least_one_command("not_a_command"));
     }
+
+    #[test]
+    fn global_options_test() {
+        let args: Vec<String> = ["--on-error", "abort", "read", "./in"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (options, rest) = GlobalOptions::parse(&args).unwrap();
+        assert_eq!(options.on_error, ErrorPolicy::Abort);
+        assert_eq!(rest, &args[2..]);
+
+        let args = vec!["--on-error=skip".to_string(), "read".to_string()];
+        let (options, rest) = GlobalOptions::parse(&args).unwrap();
+        assert_eq!(options.on_error, ErrorPolicy::Skip);
+        assert_eq!(rest, &args[1..]);
+
+        let args: Vec<String> = ["--no-progress", "--report", "r.json", "read"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (options, rest) = GlobalOptions::parse(&args).unwrap();
+        assert!(options.no_progress);
+        assert_eq!(options.report.as_deref(), Some("r.json"));
+        assert_eq!(rest, &args[3..]);
+
+        assert!(GlobalOptions::parse(&["--on-error=never".to_string()]).is_err());
+        assert!(GlobalOptions::parse(&["--nope".to_string(), "x".to_string()]).is_err());
+    }
+
+    #[test]
+    fn split_stages_test() {
+        let args: Vec<String> = "read ./in +output=a write ./out +input=a"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        assert_eq!(
+            Pipeline::split_stages(&args),
+            vec![
+                vec!["read", "./in", "+output=a"],
+                vec!["write", "./out", "+input=a"],
+            ]
+        );
+    }
 }
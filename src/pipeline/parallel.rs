@@ -0,0 +1,316 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+
+use super::{
+    channel::Channel,
+    join::{message_index, JoinPolicy, Joined, Joiner},
+    subcommands::Subcommand,
+    ErrorPolicy, PipelineMessage,
+};
+
+/// What a job produced, replayed on the stage channel in the order the jobs were started
+enum Emit {
+    Forward(PipelineMessage),
+    Fail(Option<u32>, String),
+}
+
+struct Job {
+    seq: usize,
+    messages: Vec<PipelineMessage>,
+}
+
+/// One handler of a stage running on its own thread. Its channel only collects the
+/// messages sent by the handler, they are sent downstream once every earlier job is done.
+struct Worker {
+    handler: Box<dyn Subcommand>,
+    channel: Channel,
+    output: Receiver<PipelineMessage>,
+}
+
+unsafe impl Send for Worker {}
+
+impl Worker {
+    fn new(
+        handler: Box<dyn Subcommand>,
+        name: &str,
+        on_error: ErrorPolicy,
+        aborted: Arc<AtomicBool>,
+    ) -> Self {
+        // !! progress is reported by the stage channel when the output is replayed
+        let (progress_tx, _) = unbounded();
+        let mut channel = Channel::for_stage(name, progress_tx, on_error, aborted);
+        let output = channel.subscribe_unbounded();
+        Self {
+            handler,
+            channel,
+            output,
+        }
+    }
+
+    fn run(mut self, jobs: Receiver<Job>, results: Sender<(usize, Vec<Emit>)>) -> Self {
+        for job in jobs {
+            let index = job.messages.iter().find_map(message_index);
+            let result = self.handler.handle(job.messages, &self.channel);
+            let mut emits = self
+                .output
+                .try_iter()
+                .map(|message| match message {
+                    PipelineMessage::Error(error) => Emit::Fail(error.index, error.cause),
+                    message => Emit::Forward(message),
+                })
+                .collect::<Vec<_>>();
+            if let Err(cause) = result {
+                emits.push(Emit::Fail(index, cause));
+            }
+            if results.send((job.seq, emits)).is_err() {
+                break;
+            }
+        }
+        self
+    }
+}
+
+/// Runs a stage with one worker per handler. Groups of input messages are handed out
+/// to whichever worker is free, the output is put back in input order before it is sent.
+pub(crate) struct ParallelStage<'a> {
+    channel: &'a Channel,
+    input_stream_names: &'a [String],
+    join: JoinPolicy,
+    shared_producer: bool,
+    /// output of finished jobs waiting for an earlier job
+    pending: BTreeMap<usize, Vec<Emit>>,
+    next_to_send: usize,
+}
+
+impl<'a> ParallelStage<'a> {
+    pub fn new(channel: &'a Channel, input_stream_names: &'a [String], join: JoinPolicy) -> Self {
+        Self {
+            channel,
+            input_stream_names,
+            join,
+            shared_producer: false,
+            pending: BTreeMap::new(),
+            next_to_send: 0,
+        }
+    }
+
+    /// See `Joiner::with_shared_producer`
+    pub fn with_shared_producer(mut self, shared_producer: bool) -> Self {
+        self.shared_producer = shared_producer;
+        self
+    }
+
+    /// Consumes the inputs until `End`, which is handed to the first handler once every worker is done
+    pub fn run(
+        mut self,
+        inputs: Vec<Receiver<PipelineMessage>>,
+        handlers: Vec<Box<dyn Subcommand>>,
+        name: &str,
+        on_error: ErrorPolicy,
+        aborted: Arc<AtomicBool>,
+    ) {
+        let n = handlers.len();
+        // !! at most `2 * n` jobs are in flight, including finished ones waiting to be sent in order
+        let max_in_flight = 2 * n;
+        let (jobs_tx, jobs_rx) = bounded::<Job>(n);
+        let (results_tx, results_rx) = unbounded();
+        let workers: Vec<JoinHandle<Worker>> = handlers
+            .into_iter()
+            .map(|handler| {
+                let worker = Worker::new(handler, name, on_error, aborted.clone());
+                let jobs_rx = jobs_rx.clone();
+                let results_tx = results_tx.clone();
+                std::thread::spawn(move || worker.run(jobs_rx, results_tx))
+            })
+            .collect();
+        drop(results_tx);
+
+        let mut joiner = Joiner::new(inputs, self.join).with_shared_producer(self.shared_producer);
+        let mut next_seq = 0;
+        let mut end = None;
+        while let Some(joined) = joiner.next_reported(self.channel) {
+            if self.channel.is_aborted() {
+                break;
+            }
+
+            let emits = match joined {
+                Joined::Unmatched { input, index } => {
+                    if self.join == JoinPolicy::Error {
+                        Some(vec![Emit::Fail(
+                            Some(index),
+                            format!(
+                                "no frame with the same index in the other inputs, found in `{}`",
+                                self.input_stream_names[input]
+                            ),
+                        )])
+                    } else {
+                        Some(vec![])
+                    }
+                }
+                Joined::Group(messages) => {
+                    if messages
+                        .iter()
+                        .any(|message| matches!(message, PipelineMessage::End))
+                    {
+                        end = Some(messages);
+                        break;
+                    }
+                    // !! a frame that failed upstream is skipped here and passed on
+                    match messages.iter().find_map(|message| match message {
+                        PipelineMessage::Error(error) => Some(error.clone()),
+                        _ => None,
+                    }) {
+                        Some(error) => Some(vec![Emit::Forward(PipelineMessage::Error(error))]),
+                        None => {
+                            while next_seq - self.next_to_send >= max_in_flight {
+                                match results_rx.recv() {
+                                    Ok((seq, emits)) => self.finish(seq, emits),
+                                    Err(_) => break,
+                                }
+                            }
+                            let job = Job {
+                                seq: next_seq,
+                                messages,
+                            };
+                            next_seq += 1;
+                            if jobs_tx.send(job).is_err() {
+                                break;
+                            }
+                            None
+                        }
+                    }
+                }
+            };
+            // !! messages that need no worker still wait for the jobs started before them
+            if let Some(emits) = emits {
+                self.finish(next_seq, emits);
+                next_seq += 1;
+            }
+            for (seq, emits) in results_rx.try_iter() {
+                self.finish(seq, emits);
+            }
+        }
+
+        drop(jobs_tx);
+        let mut handlers = workers
+            .into_iter()
+            .map(|worker| worker.join().map(|worker| worker.handler))
+            .collect::<Vec<_>>();
+        for (seq, emits) in results_rx.try_iter() {
+            self.finish(seq, emits);
+        }
+
+        if let (Some(messages), Some(Ok(handler))) = (end, handlers.first_mut()) {
+            if let Err(cause) = handler.handle(messages, self.channel) {
+                self.channel.send_error(None, cause);
+            }
+        }
+        // !! a panicking worker is reported like a panicking stage
+        for handler in handlers {
+            if let Err(panic) = handler {
+                std::panic::resume_unwind(panic);
+            }
+        }
+    }
+
+    fn finish(&mut self, seq: usize, emits: Vec<Emit>) {
+        self.pending.insert(seq, emits);
+        while let Some(emits) = self.pending.remove(&self.next_to_send) {
+            for emit in emits {
+                match emit {
+                    Emit::Forward(message) => self.channel.send(message),
+                    Emit::Fail(index, cause) => self.channel.send_error(index, cause),
+                }
+            }
+            self.next_to_send += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::PointCloud;
+    use std::time::Duration;
+
+    /// Forwards every frame after a delay that decreases with the index, failing on odd frames
+    struct Slow;
+
+    impl Subcommand for Slow {
+        fn handle(&mut self, messages: Vec<PipelineMessage>, out: &Channel) -> Result<(), String> {
+            for message in messages {
+                match message {
+                    PipelineMessage::IndexedPointCloud(pc, i) => {
+                        std::thread::sleep(Duration::from_millis(20 - i as u64));
+                        if i % 2 == 1 {
+                            return Err("odd frame".to_string());
+                        }
+                        out.send(PipelineMessage::IndexedPointCloud(pc, i));
+                    }
+                    PipelineMessage::End => out.send(PipelineMessage::End),
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_output_in_input_order() {
+        let (input_tx, input_rx) = unbounded();
+        for i in 0..20 {
+            let pc = PointCloud {
+                number_of_points: 0,
+                points: vec![],
+            };
+            input_tx
+                .send(PipelineMessage::IndexedPointCloud(pc, i))
+                .unwrap();
+        }
+        input_tx.send(PipelineMessage::End).unwrap();
+
+        let (progress_tx, progress_rx) = unbounded();
+        let aborted = Arc::new(AtomicBool::new(false));
+        let mut channel =
+            Channel::for_stage("slow", progress_tx, ErrorPolicy::Skip, aborted.clone());
+        let output = channel.subscribe_unbounded();
+        let handlers: Vec<Box<dyn Subcommand>> = (0..4).map(|_| Box::new(Slow) as _).collect();
+        let names = vec!["in".to_string()];
+        ParallelStage::new(&channel, &names, JoinPolicy::default()).run(
+            vec![input_rx],
+            handlers,
+            "slow",
+            ErrorPolicy::Skip,
+            aborted,
+        );
+
+        let indices = output
+            .try_iter()
+            .map(|message| match message {
+                PipelineMessage::IndexedPointCloud(_, i) => format!("{}", i),
+                PipelineMessage::Error(error) => format!("error {:?}", error.index),
+                PipelineMessage::End => "end".to_string(),
+                _ => "other".to_string(),
+            })
+            .collect::<Vec<_>>();
+        let mut expected = vec![];
+        for i in 0..20 {
+            if i % 2 == 0 {
+                expected.push(format!("{}", i));
+            } else {
+                expected.push(format!("error Some({})", i));
+            }
+        }
+        expected.push("end".to_string());
+        assert_eq!(indices, expected);
+        let errors = progress_rx
+            .try_iter()
+            .filter(|progress| matches!(progress, crate::pipeline::Progress::Error(_)))
+            .count();
+        assert_eq!(errors, 10);
+    }
+}
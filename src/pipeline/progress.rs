@@ -0,0 +1,44 @@
+use kdam::{tqdm, Bar, BarExt};
+
+use super::PipelineHandle;
+
+/// One progress bar per stage showing the frames sent, the frame rate and the input queue
+pub(crate) struct ProgressDisplay {
+    bars: Vec<Bar>,
+}
+
+impl ProgressDisplay {
+    pub fn new(handle: &PipelineHandle) -> Self {
+        let bars = handle
+            .stage_names()
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| {
+                tqdm!(
+                    desc = format!("{} {}", idx, name),
+                    position = idx as u16,
+                    unit = " frames".to_string()
+                )
+            })
+            .collect();
+        Self { bars }
+    }
+
+    pub fn update(&mut self, handle: &PipelineHandle) {
+        for (idx, bar) in self.bars.iter_mut().enumerate() {
+            let errors = handle.errors()[idx].len();
+            let mut postfix = format!("queued={}", handle.queued()[idx]);
+            if errors > 0 {
+                postfix.push_str(&format!(", errors={}", errors));
+            }
+            bar.set_postfix(postfix);
+            let _ = bar.update_to(handle.frames()[idx]);
+        }
+    }
+
+    /// Leaves the bars at their final values and moves the cursor below them
+    pub fn finish(mut self, handle: &PipelineHandle) {
+        self.update(handle);
+        eprint!("{}", "\n".repeat(self.bars.len()));
+    }
+}
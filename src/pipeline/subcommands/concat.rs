@@ -0,0 +1,39 @@
+use clap::Parser;
+
+use crate::pipeline::{channel::Channel, PipelineMessage};
+
+use super::Subcommand;
+
+#[derive(Parser)]
+#[clap(
+    about = "Plays the input streams one after another.\nThe indices of each stream follow the last index of the previous one.\nStreams computed from a common stream are held in memory until their turn.",
+    override_usage = format!("\x1B[1m{}\x1B[0m +input=a,b,... +output=concatenated", "concat")
+)]
+pub struct Args {}
+
+/// The inputs are read one after another and renumbered by the executor,
+/// frames only need to be passed on here
+pub struct Concat;
+
+impl Concat {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let _args: Args = Args::parse_from(args);
+        Box::new(Concat)
+    }
+}
+
+impl Subcommand for Concat {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(_, _)
+                | PipelineMessage::IndexedPointCloudNormal(_, _)
+                | PipelineMessage::End => channel.send(message),
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -9,13 +9,17 @@ use crate::pipeline::PipelineMessage;
 use crate::pipeline::Subcommand;
 
 use crate::utils::{
-    find_all_files, pcd_to_pcd, pcd_to_ply, ply_to_pcd, ply_to_ply, velodyne_bin_to_pcd,
-    velodyne_bin_to_ply, ConvertOutputFormat,
+    find_all_files, las_to_pcd, las_to_ply, pcd_to_pcd, pcd_to_ply, ply_to_pcd, ply_to_ply,
+    text_to_pcd, text_to_ply, to_las, to_text, velodyne_bin_to_pcd, velodyne_bin_to_ply,
+    ConvertOutputFormat,
 };
+use crate::xyz::TextFormat;
+
+use super::read::TextArgs;
 
 #[derive(Parser, Debug)]
 #[clap(
-    about = "Converts a pointcloud file from one format to another.\nSupported formats are .pcd and .ply.\nSupported storage types are binary and ascii."
+    about = "Converts a pointcloud file from one format to another.\nSupported formats are .pcd, .ply, .las and text (.xyz .txt .csv .pts).\nSupported storage types are binary, ascii and compressed_binary (pcd only), las is always binary and text always ascii."
 )]
 pub struct Args {
     #[clap(short, long)]
@@ -29,6 +33,14 @@ pub struct Args {
 
     #[clap(short, long)]
     input: Vec<OsString>,
+
+    /// Reads the packed colors of PCD files written by earlier versions of vvtk,
+    /// which have red and blue swapped otherwise. Only for .pcd and .ply outputs
+    #[clap(long)]
+    legacy_rgba: bool,
+
+    #[clap(flatten)]
+    text: TextArgs,
 }
 
 pub struct Convert {
@@ -44,38 +56,80 @@ impl Convert {
 }
 
 impl Subcommand for Convert {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
         if messages.is_empty() {
             // println!("Start converting...");
-            let mut files = find_all_files(&self.args.input);
+            let mut files = find_all_files(&self.args.input)?;
             files.sort();
 
             // create output dir
             let output_path = Path::new(&self.args.output);
-            std::fs::create_dir_all(output_path).expect("Failed to create output directory");
+            std::fs::create_dir_all(output_path)
+                .map_err(|e| format!("Failed to create output directory {:?}: {e}", output_path))?;
 
-            for file in tqdm!(files.into_iter()) {
-                let current_file_type = file.extension().unwrap();
+            for (i, file) in tqdm!(files.into_iter().enumerate()) {
+                if channel.is_aborted() {
+                    break;
+                }
+                let current_file_type = file
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .unwrap_or_default()
+                    .to_string();
                 let target_file_type = self.args.output_format.to_string();
 
-                match (
-                    current_file_type.to_str().unwrap(),
-                    target_file_type.as_str(),
-                ) {
+                let text = self.args.text.options();
+                // !! every text extension is read the same way
+                let current_file_type = match TextFormat::from_extension(&current_file_type) {
+                    Some(_) => "text".to_string(),
+                    None => current_file_type,
+                };
+                let result = match (current_file_type.as_str(), target_file_type.as_str()) {
                     ("ply", "ply") => ply_to_ply(output_path, self.args.storage_type, file),
                     ("ply", "pcd") => ply_to_pcd(output_path, self.args.storage_type, file),
-                    ("pcd", "ply") => pcd_to_ply(output_path, self.args.storage_type, file),
-                    ("pcd", "pcd") => pcd_to_pcd(output_path, self.args.storage_type, file),
+                    ("pcd", "ply") => pcd_to_ply(
+                        output_path,
+                        self.args.storage_type,
+                        file,
+                        self.args.legacy_rgba,
+                    ),
+                    ("pcd", "pcd") => pcd_to_pcd(
+                        output_path,
+                        self.args.storage_type,
+                        file,
+                        self.args.legacy_rgba,
+                    ),
+                    ("pcd", _) if self.args.legacy_rgba => Err(format!(
+                        "--legacy-rgba converts {:?} to .pcd or .ply only, convert it to .pcd first",
+                        file
+                    )),
                     ("bin", "pcd") => {
                         velodyne_bin_to_pcd(output_path, self.args.storage_type, file)
                     }
                     ("bin", "ply") => {
                         velodyne_bin_to_ply(output_path, self.args.storage_type, file)
                     }
-                    _ => eprintln!("unsupported file type"),
-                }
+                    ("las", "pcd") => las_to_pcd(output_path, self.args.storage_type, file),
+                    ("las", "ply") => las_to_ply(output_path, self.args.storage_type, file),
+                    ("text", "pcd") => {
+                        text_to_pcd(output_path, self.args.storage_type, file, &text)
+                    }
+                    ("text", "ply") => {
+                        text_to_ply(output_path, self.args.storage_type, file, &text)
+                    }
+                    ("ply" | "pcd" | "bin" | "las" | "text", "las") => {
+                        to_las(output_path, self.args.storage_type, file, &text)
+                    }
+                    ("ply" | "pcd" | "bin" | "las" | "text", "xyz" | "csv" | "pts") => {
+                        to_text(output_path, &target_file_type, file, &text)
+                    }
+                    _ => Err(format!("unsupported file type {:?}", file)),
+                };
 
-                channel.send(PipelineMessage::DummyForIncrement);
+                match result {
+                    Ok(()) => channel.send(PipelineMessage::DummyForIncrement),
+                    Err(cause) => channel.send_error(Some(i as u32), cause),
+                }
             }
 
             channel.send(PipelineMessage::End);
@@ -84,5 +138,6 @@ impl Subcommand for Convert {
                 channel.send(message);
             }
         }
+        Ok(())
     }
 }
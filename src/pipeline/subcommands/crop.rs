@@ -0,0 +1,303 @@
+use clap::Parser;
+use nalgebra::{Rotation3, Vector3};
+
+use crate::formats::PointCloud;
+use crate::pipeline::{channel::Channel, PipelineMessage};
+
+use super::transform::parse_floats;
+use super::Subcommand;
+
+#[derive(Parser)]
+#[clap(
+    about = "Keeps the points inside a box, a sphere, a half-space or the view of a camera.",
+    override_usage = format!("\x1B[1m{}\x1B[0m <--box <BOX>|--sphere <SPHERE>|--plane <PLANE>|--frustum> [OPTIONS] +input=in +output=out", "crop")
+)]
+#[clap(group(clap::ArgGroup::new("region").required(true).args(["bounds", "sphere", "plane", "frustum"])))]
+pub struct Args {
+    /// Axis-aligned box, as min_x,min_y,min_z,max_x,max_y,max_z
+    #[clap(long = "box", value_name = "BOX", value_parser = parse_box, allow_hyphen_values = true)]
+    bounds: Option<(Vector3<f32>, Vector3<f32>)>,
+
+    /// Rotation of the box around its center in degrees around the X, Y and Z axes, as x,y,z
+    #[clap(long, requires = "bounds", value_parser = parse_rotation, allow_hyphen_values = true)]
+    rotate: Option<Rotation3<f32>>,
+
+    /// Sphere, as center_x,center_y,center_z,radius
+    #[clap(long, value_parser = parse_sphere, allow_hyphen_values = true)]
+    sphere: Option<(Vector3<f32>, f32)>,
+
+    /// Half-space a*x + b*y + c*z + d >= 0, as a,b,c,d
+    #[clap(long, value_parser = parse_plane, allow_hyphen_values = true)]
+    plane: Option<(Vector3<f32>, f32)>,
+
+    /// What the camera described by the options below sees, the same camera as `render`
+    #[clap(long)]
+    frustum: bool,
+
+    #[clap(short = 'x', long, default_value_t = 0.0, allow_hyphen_values = true)]
+    camera_x: f32,
+    #[clap(short = 'y', long, default_value_t = 0.0, allow_hyphen_values = true)]
+    camera_y: f32,
+    #[clap(short = 'z', long, default_value_t = 1.8, allow_hyphen_values = true)]
+    camera_z: f32,
+    #[clap(long = "yaw", default_value_t = -90.0, allow_hyphen_values = true)]
+    camera_yaw: f32,
+    #[clap(long = "pitch", default_value_t = 0.0, allow_hyphen_values = true)]
+    camera_pitch: f32,
+    /// Vertical field of view in degrees
+    #[clap(long, default_value_t = 45.0)]
+    fov: f32,
+    #[clap(long, default_value_t = 1600)]
+    width: u32,
+    #[clap(long, default_value_t = 900)]
+    height: u32,
+    #[clap(long, default_value_t = 0.1)]
+    znear: f32,
+    #[clap(long, default_value_t = 100.0)]
+    zfar: f32,
+
+    /// Keeps the points outside of the region instead
+    #[clap(long)]
+    outside: bool,
+}
+
+pub(super) fn parse_box(s: &str) -> Result<(Vector3<f32>, Vector3<f32>), String> {
+    match parse_floats(s)?[..] {
+        [x0, y0, z0, x1, y1, z1] => Ok((
+            Vector3::new(x0.min(x1), y0.min(y1), z0.min(z1)),
+            Vector3::new(x0.max(x1), y0.max(y1), z0.max(z1)),
+        )),
+        ref values => Err(format!("expected 6 values, got {}", values.len())),
+    }
+}
+
+fn parse_rotation(s: &str) -> Result<Rotation3<f32>, String> {
+    match parse_floats(s)?[..] {
+        [x, y, z] => Ok(Rotation3::from_euler_angles(
+            x.to_radians(),
+            y.to_radians(),
+            z.to_radians(),
+        )),
+        ref values => Err(format!("expected x,y,z, got {} values", values.len())),
+    }
+}
+
+fn parse_sphere(s: &str) -> Result<(Vector3<f32>, f32), String> {
+    match parse_floats(s)?[..] {
+        [x, y, z, r] if r >= 0.0 => Ok((Vector3::new(x, y, z), r)),
+        [_, _, _, r] => Err(format!("expected a positive radius, got {}", r)),
+        ref values => Err(format!("expected 4 values, got {}", values.len())),
+    }
+}
+
+fn parse_plane(s: &str) -> Result<(Vector3<f32>, f32), String> {
+    match parse_floats(s)?[..] {
+        [0.0, 0.0, 0.0, _] => Err("the normal a,b,c of the plane cannot be zero".to_string()),
+        [a, b, c, d] => Ok((Vector3::new(a, b, c), d)),
+        ref values => Err(format!("expected 4 values, got {}", values.len())),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Region {
+    Box {
+        min: Vector3<f32>,
+        max: Vector3<f32>,
+        /// maps points to the frame of an oriented box, around its center
+        inverse_rotation: Option<Rotation3<f32>>,
+    },
+    Sphere {
+        center: Vector3<f32>,
+        radius: f32,
+    },
+    HalfSpace {
+        normal: Vector3<f32>,
+        offset: f32,
+    },
+    Frustum {
+        position: Vector3<f32>,
+        forward: Vector3<f32>,
+        right: Vector3<f32>,
+        up: Vector3<f32>,
+        /// tangents of half the vertical and horizontal fields of view
+        tan_y: f32,
+        tan_x: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+impl Region {
+    fn from_args(args: &Args) -> Self {
+        if let Some((min, max)) = args.bounds {
+            Region::Box {
+                min,
+                max,
+                inverse_rotation: args.rotate.map(|r| r.inverse()),
+            }
+        } else if let Some((center, radius)) = args.sphere {
+            Region::Sphere { center, radius }
+        } else if let Some((normal, offset)) = args.plane {
+            Region::HalfSpace { normal, offset }
+        } else {
+            // !! same orientation as `Camera::calc_matrix`, the up vector is always +y
+            let (sin_pitch, cos_pitch) = args.camera_pitch.to_radians().sin_cos();
+            let (sin_yaw, cos_yaw) = args.camera_yaw.to_radians().sin_cos();
+            let forward =
+                Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize();
+            let right = forward.cross(&Vector3::y()).normalize();
+            let up = right.cross(&forward);
+            let tan_y = (args.fov.to_radians() / 2.0).tan();
+            Region::Frustum {
+                position: Vector3::new(args.camera_x, args.camera_y, args.camera_z),
+                forward,
+                right,
+                up,
+                tan_y,
+                tan_x: tan_y * args.width as f32 / args.height as f32,
+                znear: args.znear,
+                zfar: args.zfar,
+            }
+        }
+    }
+
+    fn contains(&self, p: Vector3<f32>) -> bool {
+        match *self {
+            Region::Box {
+                min,
+                max,
+                inverse_rotation,
+            } => {
+                let p = match inverse_rotation {
+                    Some(inverse) => {
+                        let center = (min + max) / 2.0;
+                        inverse * (p - center) + center
+                    }
+                    None => p,
+                };
+                (0..3).all(|i| min[i] <= p[i] && p[i] <= max[i])
+            }
+            Region::Sphere { center, radius } => (p - center).norm_squared() <= radius * radius,
+            Region::HalfSpace { normal, offset } => normal.dot(&p) + offset >= 0.0,
+            Region::Frustum {
+                position,
+                forward,
+                right,
+                up,
+                tan_y,
+                tan_x,
+                znear,
+                zfar,
+            } => {
+                let v = p - position;
+                let depth = forward.dot(&v);
+                znear <= depth
+                    && depth <= zfar
+                    && right.dot(&v).abs() <= depth * tan_x
+                    && up.dot(&v).abs() <= depth * tan_y
+            }
+        }
+    }
+}
+
+pub struct Crop {
+    region: Region,
+    outside: bool,
+}
+
+impl Crop {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        Box::new(Crop {
+            region: Region::from_args(&args),
+            outside: args.outside,
+        })
+    }
+
+    fn crop<T>(&self, pc: &mut PointCloud<T>, position: impl Fn(&T) -> Vector3<f32>) {
+        pc.points
+            .retain(|p| self.region.contains(position(p)) != self.outside);
+        pc.number_of_points = pc.points.len();
+    }
+}
+
+impl Subcommand for Crop {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(mut pc, i) => {
+                    self.crop(&mut pc, |p| Vector3::new(p.x, p.y, p.z));
+                    channel.send(PipelineMessage::IndexedPointCloud(pc, i));
+                }
+                PipelineMessage::IndexedPointCloudNormal(mut pc, i) => {
+                    self.crop(&mut pc, |p| Vector3::new(p.x, p.y, p.z));
+                    channel.send(PipelineMessage::IndexedPointCloudNormal(pc, i));
+                }
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
+                PipelineMessage::End => {
+                    channel.send(message);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(args: &[&str]) -> Region {
+        Region::from_args(&Args::parse_from(
+            std::iter::once("crop").chain(args.iter().copied()),
+        ))
+    }
+
+    #[test]
+    fn test_box_and_sphere() {
+        let aabb = region(&["--box", "0,0,0,2,1,1"]);
+        assert!(aabb.contains(Vector3::new(1.9, 0.5, 0.5)));
+        assert!(!aabb.contains(Vector3::new(0.5, 1.5, 0.5)));
+
+        // !! rotated by 90 degrees around z, the box is now 1 wide along x and 2 along y
+        let oriented = region(&["--box", "0,0,0,2,1,1", "--rotate", "0,0,90"]);
+        assert!(oriented.contains(Vector3::new(1.0, 1.4, 0.5)));
+        assert!(!oriented.contains(Vector3::new(1.9, 0.5, 0.5)));
+
+        let sphere = region(&["--sphere", "1,1,1,0.5"]);
+        assert!(sphere.contains(Vector3::new(1.0, 1.4, 1.0)));
+        assert!(!sphere.contains(Vector3::new(1.4, 1.4, 1.0)));
+    }
+
+    #[test]
+    fn test_plane_and_frustum() {
+        // !! cuts everything below y = 0.1, e.g. the floor
+        let plane = region(&["--plane", "0,1,0,-0.1"]);
+        assert!(plane.contains(Vector3::new(5.0, 0.2, -3.0)));
+        assert!(!plane.contains(Vector3::new(0.0, 0.0, 0.0)));
+
+        // !! the default camera of `render`, at z = 1.8 looking down -z
+        let frustum = region(&["--frustum"]);
+        assert!(frustum.contains(Vector3::new(0.0, 0.0, 0.0)));
+        assert!(frustum.contains(Vector3::new(1.0, 0.5, 0.0)));
+        assert!(!frustum.contains(Vector3::new(0.0, 1.0, 0.0)));
+        assert!(!frustum.contains(Vector3::new(0.0, 0.0, 2.0)));
+    }
+
+    #[test]
+    fn test_keep_outside() {
+        let crop = Crop {
+            region: region(&["--sphere", "0,0,0,1"]),
+            outside: true,
+        };
+        let mut pc = PointCloud {
+            number_of_points: 2,
+            points: vec![Vector3::new(0.0, 0.0, 0.5), Vector3::new(0.0, 0.0, 1.5)],
+        };
+        crop.crop(&mut pc, |p| *p);
+        assert_eq!(pc.number_of_points, 1);
+        assert_eq!(pc.points[0].z, 1.5);
+    }
+}
@@ -7,6 +7,7 @@ use std::path::PathBuf;
 
 use crate::pipeline::subcommands::read::FileType;
 use crate::utils::{find_all_files, read_file_to_point_cloud};
+use crate::xyz::is_text_file;
 use std::str::FromStr;
 
 use crate::abr::quetra::Quetra;
@@ -69,21 +70,24 @@ impl Dash {
         })
     }
 
-    fn prepare_bandwidth(&self) -> Vec<f32> {
+    fn prepare_bandwidth(&self) -> Result<Vec<f32>, String> {
         // reading network conditions
         let network_content = std::fs::read_to_string(self.args.network_path.clone())
-            .expect("could not read network file");
+            .map_err(|e| format!("could not read network file: {e}"))?;
         // using f32 for bandwidth in KB/s
         let mut bandwidth: Vec<f32> = Vec::new();
         for line in network_content.lines() {
-            bandwidth.push(line.parse().unwrap());
+            bandwidth.push(
+                line.parse()
+                    .map_err(|_| format!("invalid bandwidth `{}` in network file", line))?,
+            );
         }
-        bandwidth
+        Ok(bandwidth)
     }
 
-    fn main_process(&self) -> Vec<PathBuf> {
+    fn main_process(&self) -> Result<Vec<PathBuf>, String> {
         // adapt from vvdash.rs
-        let bandwidth = self.prepare_bandwidth();
+        let bandwidth = self.prepare_bandwidth()?;
 
         let mut starting_frame_int: usize = 0;
         let mut _frame_increment_int: usize = 0;
@@ -105,7 +109,10 @@ impl Dash {
         let mut input_folder_pathbuf: &PathBuf;
 
         // longdress format: r1_longdress_dec_0000.ply
-        let mut entries = find_all_files(vec![input_folder_r05.clone().into_os_string()].as_ref());
+        let mut entries = find_all_files(vec![input_folder_r05.clone().into_os_string()].as_ref())?;
+        if entries.is_empty() {
+            return Err(format!("No files found in {:?}", input_folder_r05));
+        }
         entries.sort();
         let re = Regex::new(r"(.{2})_(.{9})_(.{3})_(\d{4}).pcd").unwrap();
         let first_entry_filename = entries[0].as_path().to_str().unwrap();
@@ -126,7 +133,7 @@ impl Dash {
         let start_no = starting_frame_int;
 
         let mut in_frame_name_buf = Vec::new();
-        match self.args.algorithm {
+        let in_frame_name_buf = match self.args.algorithm {
             DashAlgo::Naive => {
                 while count < total_frames {
                     let rate_prefix: &str;
@@ -239,14 +246,15 @@ impl Dash {
 
                 in_frame_name_buf
             }
-        }
+        };
+        Ok(in_frame_name_buf)
     }
 }
 
 impl Subcommand for Dash {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
         if messages.is_empty() {
-            let mut in_frame_name_buf = self.main_process();
+            let mut in_frame_name_buf = self.main_process()?;
             if let Some(num) = self.args.num {
                 if num < in_frame_name_buf.len() {
                     in_frame_name_buf = in_frame_name_buf.into_iter().take(num).collect();
@@ -254,6 +262,9 @@ impl Subcommand for Dash {
             }
 
             for (i, file) in in_frame_name_buf.iter().enumerate() {
+                if channel.is_aborted() {
+                    break;
+                }
                 match &self.args.filetype {
                     FileType::All => {}
                     FileType::Pcd => {
@@ -271,11 +282,24 @@ impl Subcommand for Dash {
                             continue;
                         }
                     }
+                    FileType::Las => {
+                        if file.extension().and_then(|ext| ext.to_str()) != Some("las") {
+                            continue;
+                        }
+                    }
+                    FileType::Text => {
+                        if !is_text_file(file) {
+                            continue;
+                        }
+                    }
                 }
 
                 let point_cloud = read_file_to_point_cloud(file);
-                if let Some(pc) = point_cloud {
-                    channel.send(PipelineMessage::IndexedPointCloud(pc, i as u32));
+                match point_cloud {
+                    Some(pc) => channel.send(PipelineMessage::IndexedPointCloud(pc, i as u32)),
+                    None => {
+                        channel.send_error(Some(i as u32), format!("Failed to read {:?}", file))
+                    }
                 }
             }
             channel.send(PipelineMessage::End);
@@ -284,5 +308,6 @@ impl Subcommand for Dash {
                 channel.send(message);
             }
         }
+        Ok(())
     }
 }
@@ -0,0 +1,118 @@
+use clap::Parser;
+
+use crate::denoise::{inliers, OutlierFilter};
+use crate::formats::PointCloud;
+use crate::metrics::Metrics;
+use crate::pipeline::{channel::Channel, PipelineMessage};
+
+use super::Subcommand;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Mean distance to the k nearest neighbors more than --std-ratio standard deviations above average
+    Statistical,
+    /// Fewer than --min-neighbors other points within --radius
+    Radius,
+}
+
+#[derive(Parser)]
+#[clap(
+    about = "Removes outliers, such as floating speckles, from the point clouds.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] +input=in +output=out", "denoise")
+)]
+pub struct Args {
+    #[clap(long, value_enum, default_value_t = Method::Statistical)]
+    method: Method,
+
+    /// Number of neighbors considered by the statistical method
+    #[clap(short, long, default_value_t = 8)]
+    k: usize,
+
+    #[clap(long, default_value_t = 2.0)]
+    std_ratio: f32,
+
+    /// Required by the radius method
+    #[clap(long, required_if_eq("method", "radius"))]
+    radius: Option<f32>,
+
+    #[clap(long, default_value_t = 2)]
+    min_neighbors: usize,
+
+    /// Sends the number of points removed from each frame as a report after the frame
+    #[clap(long)]
+    report: bool,
+}
+
+pub struct Denoise {
+    filter: OutlierFilter,
+    report: bool,
+}
+
+impl Denoise {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        let filter = match args.method {
+            Method::Statistical => OutlierFilter::Statistical {
+                k: args.k,
+                std_ratio: args.std_ratio,
+            },
+            Method::Radius => OutlierFilter::Radius {
+                radius: args.radius.expect("clap requires --radius"),
+                min_neighbors: args.min_neighbors,
+            },
+        };
+        Box::new(Denoise {
+            filter,
+            report: args.report,
+        })
+    }
+
+    /// Removes the outliers in place, returns the number of points removed
+    fn denoise<T>(&self, pc: &mut PointCloud<T>, position: impl Fn(&T) -> [f32; 3]) -> usize {
+        let positions = pc.points.iter().map(position).collect::<Vec<_>>();
+        let mut kept = inliers(&positions, self.filter).into_iter();
+        let before = pc.points.len();
+        pc.points.retain(|_| kept.next().unwrap_or(true));
+        pc.number_of_points = pc.points.len();
+        before - pc.points.len()
+    }
+
+    fn report(&self, index: u32, points: usize, removed: usize, channel: &Channel) {
+        if !self.report {
+            return;
+        }
+        let mut metrics = Metrics::new();
+        metrics.insert("index".to_string(), index.to_string());
+        metrics.insert("points".to_string(), points.to_string());
+        metrics.insert("removed".to_string(), removed.to_string());
+        channel.send(PipelineMessage::Metrics(metrics));
+    }
+}
+
+impl Subcommand for Denoise {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(mut pc, i) => {
+                    let removed = self.denoise(&mut pc, |p| [p.x, p.y, p.z]);
+                    let points = pc.number_of_points;
+                    channel.send(PipelineMessage::IndexedPointCloud(pc, i));
+                    self.report(i, points, removed, channel);
+                }
+                PipelineMessage::IndexedPointCloudNormal(mut pc, i) => {
+                    let removed = self.denoise(&mut pc, |p| [p.x, p.y, p.z]);
+                    let points = pc.number_of_points;
+                    channel.send(PipelineMessage::IndexedPointCloudNormal(pc, i));
+                    self.report(i, points, removed, channel);
+                }
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
+                PipelineMessage::End => {
+                    channel.send(message);
+                }
+            }
+        }
+        Ok(())
+    }
+}
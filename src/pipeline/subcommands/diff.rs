@@ -0,0 +1,316 @@
+use clap::Parser;
+use kiddo::distance::squared_euclidean;
+use rayon::prelude::*;
+
+use crate::formats::{pointxyzrgba::PointXyzRgba, PointCloud};
+use crate::metrics::{build_tree, is_finite};
+use crate::pipeline::{channel::Channel, PipelineMessage};
+
+use super::transform::parse_floats;
+use super::Subcommand;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Distance to the nearest point of the original
+    Geometry,
+    /// Distance between the RGB colors of the point and of the nearest point of the original, from 0 to 441.7
+    Color,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colormap {
+    /// Dark purple to yellow, perceptually uniform
+    Viridis,
+    /// Blue to red through cyan and yellow
+    Jet,
+    /// Black to white through red and yellow
+    Hot,
+    /// Black to white
+    Gray,
+}
+
+impl Colormap {
+    /// Positions and colors of the stops, the colors in between are interpolated linearly
+    fn stops(&self) -> &'static [(f32, [u8; 3])] {
+        match self {
+            Colormap::Viridis => &[
+                (0.0, [68, 1, 84]),
+                (0.25, [59, 82, 139]),
+                (0.5, [33, 145, 140]),
+                (0.75, [94, 201, 98]),
+                (1.0, [253, 231, 37]),
+            ],
+            Colormap::Jet => &[
+                (0.0, [0, 0, 128]),
+                (0.125, [0, 0, 255]),
+                (0.375, [0, 255, 255]),
+                (0.625, [255, 255, 0]),
+                (0.875, [255, 0, 0]),
+                (1.0, [128, 0, 0]),
+            ],
+            Colormap::Hot => &[
+                (0.0, [0, 0, 0]),
+                (0.375, [255, 0, 0]),
+                (0.75, [255, 255, 0]),
+                (1.0, [255, 255, 255]),
+            ],
+            Colormap::Gray => &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])],
+        }
+    }
+
+    /// Color of `t`, clamped to [0, 1]
+    pub fn color(&self, t: f32) -> [u8; 3] {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let stops = self.stops();
+        let upper = stops
+            .iter()
+            .position(|(at, _)| *at >= t)
+            .unwrap_or(stops.len() - 1)
+            .max(1);
+        let (a, color_a) = stops[upper - 1];
+        let (b, color_b) = stops[upper];
+        let alpha = (t - a) / (b - a);
+        [0, 1, 2].map(|c| {
+            (color_a[c] as f32 + (color_b[c] as f32 - color_a[c] as f32) * alpha).round() as u8
+        })
+    }
+}
+
+/// Errors mapped to the ends of the colormap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Range {
+    /// From 0 to the largest error of each frame
+    Auto,
+    Fixed(f32, f32),
+}
+
+fn parse_range(s: &str) -> Result<Range, String> {
+    if s == "auto" {
+        return Ok(Range::Auto);
+    }
+    match parse_floats(s)?[..] {
+        [min, max] if min < max => Ok(Range::Fixed(min, max)),
+        [min, max] => Err(format!("expected min < max, got {} and {}", min, max)),
+        ref values => Err(format!(
+            "expected auto or min,max, got {} values",
+            values.len()
+        )),
+    }
+}
+
+#[derive(Parser)]
+#[clap(
+    about = "Colors every point of the second input stream by its error relative to the first one.\nSends point clouds that can be rendered or written.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] +input=original,reconstructed +output=heatmap", "diff")
+)]
+pub struct Args {
+    #[clap(long, value_enum, default_value_t = Error::Geometry)]
+    error: Error,
+
+    #[clap(long, value_enum, default_value_t = Colormap::Viridis)]
+    colormap: Colormap,
+
+    /// Errors mapped to the ends of the colormap, as min,max, or auto for 0 to the largest error of each frame
+    #[clap(long, default_value = "auto", value_parser = parse_range)]
+    range: Range,
+}
+
+pub struct Diff {
+    error: Error,
+    colormap: Colormap,
+    range: Range,
+}
+
+impl Diff {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        Box::new(Diff {
+            error: args.error,
+            colormap: args.colormap,
+            range: args.range,
+        })
+    }
+
+    /// Error of every reconstructed point, in the order of `reconstructed`,
+    /// `None` for the points with a NaN or infinite coordinate or without a finite original point
+    fn errors(
+        &self,
+        original: &[PointXyzRgba],
+        reconstructed: &[PointXyzRgba],
+    ) -> Vec<Option<f32>> {
+        let tree = build_tree(original.iter().map(|p| [p.x, p.y, p.z]));
+        reconstructed
+            .par_iter()
+            .map(|p| {
+                let position = [p.x, p.y, p.z];
+                if !is_finite(&position) {
+                    return None;
+                }
+                let (distance, &nearest) = tree.nearest_one(&position, &squared_euclidean).ok()?;
+                Some(match self.error {
+                    Error::Geometry => distance.sqrt(),
+                    Error::Color => {
+                        let q = &original[nearest];
+                        let d = |a: u8, b: u8| (a as f32 - b as f32).powi(2);
+                        (d(p.r, q.r) + d(p.g, q.g) + d(p.b, q.b)).sqrt()
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn heatmap(
+        &self,
+        original: &[PointXyzRgba],
+        mut reconstructed: PointCloud<PointXyzRgba>,
+    ) -> PointCloud<PointXyzRgba> {
+        let errors = self.errors(original, &reconstructed.points);
+        let (min, max) = match self.range {
+            Range::Auto => (0.0, errors.iter().flatten().copied().fold(0.0, f32::max)),
+            Range::Fixed(min, max) => (min, max),
+        };
+        // !! a frame without errors in auto mode is all at the low end
+        let scale = if max > min { 1.0 / (max - min) } else { 0.0 };
+        // !! points that cannot be matched take the high end
+        for (p, error) in reconstructed.points.iter_mut().zip(errors) {
+            [p.r, p.g, p.b] = match error {
+                Some(error) => self.colormap.color((error - min) * scale),
+                None => self.colormap.color(1.0),
+            };
+        }
+        reconstructed
+    }
+}
+
+/// Drops the normals, `render` only takes point clouds without them
+fn without_normals(message: PipelineMessage) -> Option<(PointCloud<PointXyzRgba>, u32)> {
+    match message {
+        PipelineMessage::IndexedPointCloud(pc, i) => Some((pc, i)),
+        PipelineMessage::IndexedPointCloudNormal(pc, i) => {
+            let points = pc
+                .points
+                .into_iter()
+                .map(|p| PointXyzRgba {
+                    x: p.x,
+                    y: p.y,
+                    z: p.z,
+                    r: p.r,
+                    g: p.g,
+                    b: p.b,
+                    a: p.a,
+                })
+                .collect::<Vec<_>>();
+            let pc = PointCloud {
+                number_of_points: points.len(),
+                points,
+            };
+            Some((pc, i))
+        }
+        _ => None,
+    }
+}
+
+impl Subcommand for Diff {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        let mut messages_iter = messages.into_iter();
+        let original = messages_iter
+            .next()
+            .ok_or("Expecting two input streams for diff")?;
+        let reconstructed = messages_iter
+            .next()
+            .ok_or("Expecting two input streams for diff")?;
+        if matches!(original, PipelineMessage::End) || matches!(reconstructed, PipelineMessage::End)
+        {
+            channel.send(PipelineMessage::End);
+            return Ok(());
+        }
+
+        if let (Some((original, _)), Some((reconstructed, i))) =
+            (without_normals(original), without_normals(reconstructed))
+        {
+            let pc = self.heatmap(&original.points, reconstructed);
+            channel.send(PipelineMessage::IndexedPointCloud(pc, i));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f32, r: u8) -> PointXyzRgba {
+        PointXyzRgba {
+            x,
+            y: 0.0,
+            z: 0.0,
+            r,
+            g: 0,
+            b: 0,
+            a: 255,
+        }
+    }
+
+    fn cloud(points: Vec<PointXyzRgba>) -> PointCloud<PointXyzRgba> {
+        PointCloud {
+            number_of_points: points.len(),
+            points,
+        }
+    }
+
+    #[test]
+    fn test_colormap() {
+        assert_eq!(Colormap::Gray.color(0.0), [0, 0, 0]);
+        assert_eq!(Colormap::Gray.color(0.5), [128, 128, 128]);
+        assert_eq!(Colormap::Gray.color(2.0), [255, 255, 255]);
+        assert_eq!(Colormap::Viridis.color(0.0), [68, 1, 84]);
+        assert_eq!(Colormap::Viridis.color(1.0), [253, 231, 37]);
+        assert_eq!(Colormap::Hot.color(0.375), [255, 0, 0]);
+        assert_eq!(Colormap::Jet.color(-1.0), [0, 0, 128]);
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let original = vec![point(0.0, 0), point(1.0, 100)];
+        let reconstructed = cloud(vec![point(0.0, 0), point(1.5, 0), point(3.0, 0)]);
+        let mut diff = Diff {
+            error: Error::Geometry,
+            colormap: Colormap::Gray,
+            range: Range::Auto,
+        };
+        let heatmap = diff.heatmap(&original, reconstructed.clone());
+        let reds = heatmap.points.iter().map(|p| p.r).collect::<Vec<_>>();
+        assert_eq!(reds, vec![0, 64, 255]);
+
+        diff.range = Range::Fixed(0.0, 1.0);
+        let heatmap = diff.heatmap(&original, reconstructed.clone());
+        let reds = heatmap.points.iter().map(|p| p.r).collect::<Vec<_>>();
+        assert_eq!(reds, vec![0, 128, 255]);
+
+        diff.error = Error::Color;
+        diff.range = Range::Fixed(0.0, 200.0);
+        let heatmap = diff.heatmap(&original, reconstructed);
+        let reds = heatmap.points.iter().map(|p| p.r).collect::<Vec<_>>();
+        assert_eq!(reds, vec![0, 128, 128]);
+    }
+
+    #[test]
+    fn test_unmatched_points_take_the_high_end() {
+        let diff = Diff {
+            error: Error::Geometry,
+            colormap: Colormap::Gray,
+            range: Range::Auto,
+        };
+        let original = vec![point(0.0, 0), point(1.0, 0)];
+        let reconstructed = cloud(vec![point(0.5, 0), point(f32::NAN, 0), point(0.0, 0)]);
+        let heatmap = diff.heatmap(&original, reconstructed.clone());
+        let reds = heatmap.points.iter().map(|p| p.r).collect::<Vec<_>>();
+        assert_eq!(reds, vec![255, 255, 0]);
+
+        let heatmap = diff.heatmap(&[point(f32::INFINITY, 0)], reconstructed.clone());
+        assert!(heatmap.points.iter().all(|p| p.r == 255));
+
+        let heatmap = diff.heatmap(&[], reconstructed);
+        assert!(heatmap.points.iter().all(|p| p.r == 255));
+    }
+}
@@ -28,7 +28,7 @@ impl Downsampler {
 }
 
 impl Subcommand for Downsampler {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
         for message in messages {
             match message {
                 PipelineMessage::IndexedPointCloud(pc, i) => {
@@ -37,11 +37,13 @@ impl Subcommand for Downsampler {
                 }
                 PipelineMessage::Metrics(_)
                 | PipelineMessage::IndexedPointCloudNormal(_, _)
+                | PipelineMessage::Error(_)
                 | PipelineMessage::DummyForIncrement => {}
                 PipelineMessage::End => {
                     channel.send(message);
                 }
             };
         }
+        Ok(())
     }
 }
@@ -0,0 +1,391 @@
+use clap::Parser;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::formats::{pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud};
+use crate::pcd::{
+    create_pcd, create_pcd_from_pc_normal, read_pcd_file, write_pcd_file, PCDDataType,
+};
+use crate::pipeline::{channel::Channel, PipelineMessage};
+use crate::ply::read_ply_vertices;
+use crate::utils::{
+    pcd_to_ply_from_data, pcd_to_ply_from_data_normal, try_read_file_to_point_cloud,
+};
+
+use super::Subcommand;
+
+/// Number of bytes of the standard error of a failed command kept in the error message
+const MAX_STDERR_LEN: usize = 1024;
+
+/// Makes the working directory of every `exec` handler unique, `+jobs=N` creates N of them
+static NEXT_WORK_DIR: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    Pcd,
+    Ply,
+}
+
+impl FrameFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            FrameFormat::Pcd => "pcd",
+            FrameFormat::Ply => "ply",
+        }
+    }
+}
+
+#[derive(Parser)]
+#[clap(
+    about = "Runs an external command on every frame.\nThe frame is written to {in}, or to the standard input if the command has no {in}.\nThe result is read from {out}, or from the standard output if the command has no {out}.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] <COMMAND>... +input=in +output=out", "exec")
+)]
+pub struct Args {
+    /// Command to run, `{in}`, `{out}` and `{index}` are replaced for every frame.
+    /// Options of exec must come before it.
+    #[clap(required = true, num_args = 1.., allow_hyphen_values = true, trailing_var_arg = true)]
+    command: Vec<String>,
+
+    /// Format of the frames given to and read back from the command
+    #[clap(short, long, value_enum, default_value_t = FrameFormat::Pcd)]
+    format: FrameFormat,
+
+    #[clap(short, long, default_value = "binary")]
+    storage_type: PCDDataType,
+
+    /// Kills the command after this many seconds and reports the frame as failed
+    #[clap(short, long)]
+    timeout: Option<f64>,
+}
+
+pub struct Exec {
+    args: Args,
+    /// created on the first frame, removed with the handler
+    work_dir: Option<PathBuf>,
+}
+
+impl Exec {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        Box::new(Exec {
+            args,
+            work_dir: None,
+        })
+    }
+
+    fn work_dir(&mut self) -> Result<PathBuf, String> {
+        if let Some(dir) = &self.work_dir {
+            return Ok(dir.clone());
+        }
+        let dir = std::env::temp_dir().join(format!(
+            "vv-exec-{}-{}",
+            std::process::id(),
+            NEXT_WORK_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create directory {:?}: {}", dir, e))?;
+        self.work_dir = Some(dir.clone());
+        Ok(dir)
+    }
+
+    /// Runs the command on the frame, the result has normals if the frame has them
+    fn process(
+        &mut self,
+        message: &PipelineMessage,
+        index: u32,
+    ) -> Result<PipelineMessage, String> {
+        let dir = self.work_dir()?;
+        let extension = self.args.format.extension();
+        let in_path = dir.join(format!("in_{}.{}", index, extension));
+        let out_path = dir.join(format!("out_{}.{}", index, extension));
+
+        let (pcd, normals) = match message {
+            PipelineMessage::IndexedPointCloud(pc, _) => (create_pcd(pc), false),
+            PipelineMessage::IndexedPointCloudNormal(pc, _) => {
+                (create_pcd_from_pc_normal(pc), true)
+            }
+            _ => unreachable!("only frames are processed"),
+        };
+        match (self.args.format, normals) {
+            (FrameFormat::Pcd, _) => write_pcd_file(&pcd, self.args.storage_type, &in_path)
+                .map_err(|e| format!("Failed to write {:?}: {}", in_path, e))?,
+            (FrameFormat::Ply, false) => {
+                pcd_to_ply_from_data(&in_path, self.args.storage_type, pcd)
+                    .map_err(|e| format!("Failed to write {:?}: {}", in_path, e))?
+            }
+            (FrameFormat::Ply, true) => {
+                pcd_to_ply_from_data_normal(&in_path, self.args.storage_type, pcd)
+                    .map_err(|e| format!("Failed to write {:?}: {}", in_path, e))?
+            }
+        }
+
+        let uses_in = self.args.command.iter().any(|arg| arg.contains("{in}"));
+        let uses_out = self.args.command.iter().any(|arg| arg.contains("{out}"));
+        let argv = self
+            .args
+            .command
+            .iter()
+            .map(|arg| {
+                arg.replace("{in}", &in_path.to_string_lossy())
+                    .replace("{out}", &out_path.to_string_lossy())
+                    .replace("{index}", &index.to_string())
+            })
+            .collect::<Vec<_>>();
+
+        let stdin = if uses_in {
+            None
+        } else {
+            Some(
+                std::fs::read(&in_path)
+                    .map_err(|e| format!("Failed to read {:?}: {}", in_path, e))?,
+            )
+        };
+        let stdout = run(&argv, stdin, !uses_out, self.args.timeout)?;
+        if let Some(stdout) = stdout {
+            std::fs::write(&out_path, stdout)
+                .map_err(|e| format!("Failed to write {:?}: {}", out_path, e))?;
+        }
+
+        let result = if normals {
+            read_normals(self.args.format, &out_path)
+                .map(|pc| PipelineMessage::IndexedPointCloudNormal(pc, index))
+        } else {
+            try_read_file_to_point_cloud(&out_path)
+                .map(|pc| PipelineMessage::IndexedPointCloud(pc, index))
+        }
+        .map_err(|e| {
+            format!(
+                "Cannot read the output of `{}` as a {} file: {}",
+                argv[0], extension, e
+            )
+        });
+        let _ = std::fs::remove_file(&in_path);
+        let _ = std::fs::remove_file(&out_path);
+        result
+    }
+}
+
+/// Reads the result of a frame with normals, the command must keep them
+fn read_normals(
+    format: FrameFormat,
+    path: &PathBuf,
+) -> Result<PointCloud<PointXyzRgbaNormal>, String> {
+    match format {
+        FrameFormat::Pcd => read_pcd_file(path)
+            .and_then(PointCloud::try_from)
+            .map_err(|e| e.to_string()),
+        FrameFormat::Ply => read_ply_vertices(path),
+    }
+}
+
+impl Drop for Exec {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.work_dir {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+impl Subcommand for Exec {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        for message in messages {
+            match &message {
+                PipelineMessage::IndexedPointCloud(_, i)
+                | PipelineMessage::IndexedPointCloudNormal(_, i) => {
+                    channel.send(self.process(&message, *i)?);
+                }
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
+                PipelineMessage::End => {
+                    channel.send(message);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs `argv`, feeding it `stdin` and returning its standard output if `capture_stdout` is set.
+/// A non-zero exit code or a timeout is an error carrying the end of the standard error.
+fn run(
+    argv: &[String],
+    stdin: Option<Vec<u8>>,
+    capture_stdout: bool,
+    timeout: Option<f64>,
+) -> Result<Option<Vec<u8>>, String> {
+    let program = &argv[0];
+    let mut child = Command::new(program)
+        .args(&argv[1..])
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(if capture_stdout {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Cannot run `{}`: {}", program, e))?;
+
+    // !! the pipes are served from their own threads so that a full pipe never blocks the child
+    let stdin_writer = child.stdin.take().map(|mut pipe| {
+        let bytes = stdin.unwrap_or_default();
+        std::thread::spawn(move || {
+            // !! the command may exit without reading everything, that is reported by its exit code
+            let _ = pipe.write_all(&bytes);
+        })
+    });
+    let stdout_reader = child.stdout.take().map(read_in_background);
+    let stderr_reader = child.stderr.take().map(read_in_background);
+
+    let status = wait(&mut child, timeout).map_err(|e| format!("`{}` {}", program, e))?;
+    if let Some(writer) = stdin_writer {
+        let _ = writer.join();
+    }
+    let stdout = stdout_reader.and_then(|reader| reader.join().ok());
+    let stderr = stderr_reader
+        .and_then(|reader| reader.join().ok())
+        .unwrap_or_default();
+
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr);
+        let stderr = stderr.trim();
+        // !! the cut is moved to a character boundary, the lossy conversion adds 3 byte characters
+        let mut start = stderr.len().saturating_sub(MAX_STDERR_LEN);
+        while !stderr.is_char_boundary(start) {
+            start += 1;
+        }
+        let tail = &stderr[start..];
+        return Err(if tail.is_empty() {
+            format!("`{}` exited with {}", program, status)
+        } else {
+            format!("`{}` exited with {}: {}", program, status, tail)
+        });
+    }
+    Ok(stdout)
+}
+
+fn read_in_background<R: Read + Send + 'static>(mut pipe: R) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut bytes = vec![];
+        let _ = pipe.read_to_end(&mut bytes);
+        bytes
+    })
+}
+
+fn wait(child: &mut Child, timeout: Option<f64>) -> Result<ExitStatus, String> {
+    let timeout = match timeout {
+        Some(timeout) => Duration::from_secs_f64(timeout),
+        None => return child.wait().map_err(|e| e.to_string()),
+    };
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            return Ok(status);
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!("timed out after {:?}", timeout));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::formats::pointxyzrgba::PointXyzRgba;
+
+    fn exec(args: &[&str]) -> Exec {
+        let argv = std::iter::once("exec").chain(args.iter().copied());
+        Exec {
+            args: Args::parse_from(argv),
+            work_dir: None,
+        }
+    }
+
+    fn frame() -> PipelineMessage {
+        let pc = PointCloud {
+            number_of_points: 1,
+            points: vec![PointXyzRgba {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+                r: 4,
+                g: 5,
+                b: 6,
+                a: 255,
+            }],
+        };
+        PipelineMessage::IndexedPointCloud(pc, 7)
+    }
+
+    #[test]
+    fn test_exec_with_paths_and_pipes() {
+        let result = exec(&["cp", "{in}", "{out}"]).process(&frame(), 7).unwrap();
+        let PipelineMessage::IndexedPointCloud(pc, 7) = result else {
+            panic!("expected the frame back, got {:?}", result);
+        };
+        assert_eq!(pc.number_of_points, 1);
+        assert_eq!(pc.points[0].x, 1.0);
+
+        let result = exec(&["cat"]).process(&frame(), 7).unwrap();
+        let PipelineMessage::IndexedPointCloud(pc, 7) = result else {
+            panic!("expected the frame back, got {:?}", result);
+        };
+        assert_eq!(pc.points[0].b, 6);
+    }
+
+    #[test]
+    fn test_exec_keeps_normals() {
+        let pc = PointCloud {
+            number_of_points: 1,
+            points: vec![PointXyzRgbaNormal {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+                r: 4,
+                g: 5,
+                b: 6,
+                a: 255,
+                nx: 0.0,
+                ny: 0.0,
+                nz: 1.0,
+            }],
+        };
+        let frame = PipelineMessage::IndexedPointCloudNormal(pc.clone(), 7);
+        let result = exec(&["cat"]).process(&frame, 7).unwrap();
+        let PipelineMessage::IndexedPointCloudNormal(result, 7) = result else {
+            panic!("expected the frame with normals back, got {:?}", result);
+        };
+        assert_eq!(result.points, pc.points);
+    }
+
+    #[test]
+    fn test_exec_failures() {
+        let err = exec(&["sh", "-c", "echo broken >&2; exit 3"])
+            .process(&frame(), 7)
+            .unwrap_err();
+        assert!(err.contains("`sh` exited with"));
+        assert!(err.ends_with("broken"));
+
+        // !! the end of the standard error is cut inside the 3 byte characters
+        let err = exec(&["sh", "-c", "printf '\\351%.0s' $(seq 1000) >&2; exit 1"])
+            .process(&frame(), 7)
+            .unwrap_err();
+        assert!(err.ends_with('\u{fffd}'));
+
+        let err = exec(&["--timeout", "0.1", "sleep", "5"])
+            .process(&frame(), 7)
+            .unwrap_err();
+        assert!(err.contains("timed out"));
+    }
+}
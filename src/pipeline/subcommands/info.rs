@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::fmt::{self, Debug};
 
 use super::Subcommand;
+use crate::las::{read_las_header, LasHeader};
 use crate::pcd::{read_pcd_header, PCDHeader};
 use crate::pipeline::channel::Channel;
 use crate::pipeline::PipelineMessage;
@@ -13,7 +14,7 @@ use std::path::Path;
 
 #[derive(Parser, Debug)]
 #[clap(
-    about = "Get the info of a pointcloud file or directory.\nSupported formats are .pcd and .ply.\nIf no option is specified, all info will be printed."
+    about = "Get the info of a pointcloud file or directory.\nSupported formats are .pcd, .ply and .las.\nIf no option is specified, all info will be printed."
 )]
 pub struct Args {
     // #[clap(short, long)]
@@ -101,6 +102,19 @@ impl From<PLYHeader> for FileInfo {
     }
 }
 
+impl From<LasHeader> for FileInfo {
+    fn from(value: LasHeader) -> Self {
+        FileInfo {
+            extension: "las".to_string(),
+            storage_type: format!(
+                "{}.{} point format {}",
+                value.version_major, value.version_minor, value.point_format
+            ),
+            num_of_points: value.number_of_points,
+        }
+    }
+}
+
 struct DirInfo {
     extension: String,
     storage_type: String,
@@ -158,8 +172,17 @@ impl Info {
     fn handle_file(&self, path: &Path) -> Result<FileInfo, String> {
         if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
             let file_info: Option<FileInfo> = match ext {
-                "ply" => Some(read_ply_header(path).unwrap().into()),
-                "pcd" => Some(read_pcd_header(path).unwrap().into()),
+                "ply" => Some(read_ply_header(path)?.into()),
+                "pcd" => Some(
+                    read_pcd_header(path)
+                        .map_err(|e| format!("Failed to read {:?}: {}", path, e))?
+                        .into(),
+                ),
+                "las" => Some(
+                    read_las_header(path)
+                        .map_err(|e| format!("Failed to read {:?}: {}", path, e))?
+                        .into(),
+                ),
                 _ => None,
             };
             return file_info.ok_or(format!("Unsupported file format: {}", ext));
@@ -167,10 +190,13 @@ impl Info {
         Err("Unsupported file format.".to_string())
     }
 
-    fn handle_dir(&self, path: &Path) {
+    fn handle_dir(&self, path: &Path) -> Result<(), String> {
         let mut dir_infos: HashMap<String, DirInfo> = HashMap::new();
-        for file_entry in path.read_dir().unwrap() {
-            let file_entry = file_entry.unwrap();
+        let dir_entries = path
+            .read_dir()
+            .map_err(|e| format!("Failed to read directory {:?}: {}", path, e))?;
+        for file_entry in dir_entries {
+            let file_entry = file_entry.map_err(|e| e.to_string())?;
             let file_path = file_entry.path();
             if file_path.is_file() {
                 // if let Some(file_info) = self.handle_file(&file_path)
@@ -194,32 +220,30 @@ impl Info {
         }
 
         if dir_infos.is_empty() {
-            println!("No files found in directory, supported formats are: pcd, ply");
-            return;
+            println!("No files found in directory, supported formats are: pcd, ply, las");
+            return Ok(());
         }
 
         for (_key, value) in dir_infos {
             println!("{}", value.to_info_string(&self.args));
         }
+        Ok(())
     }
 }
 
 impl Subcommand for Info {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
         if messages.is_empty() {
             // println!("self.args {:?}", self.args);
             let path = Path::new(&self.args.path);
 
             if path.is_file() {
-                let file_info = self.handle_file(&path);
-                match file_info {
-                    Ok(file_info) => println!("{}", file_info.to_info_string(&self.args)),
-                    Err(err_msg) => println!("{}", err_msg),
-                }
+                let file_info = self.handle_file(path)?;
+                println!("{}", file_info.to_info_string(&self.args));
             } else if path.is_dir() {
-                self.handle_dir(&path);
+                self.handle_dir(path)?;
             } else {
-                println!("Path is neither a file nor a directory");
+                return Err(format!("Path {:?} is neither a file nor a directory", path));
             }
 
             channel.send(PipelineMessage::End);
@@ -228,5 +252,6 @@ impl Subcommand for Info {
                 channel.send(message);
             }
         }
+        Ok(())
     }
 }
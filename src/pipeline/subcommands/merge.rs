@@ -0,0 +1,67 @@
+use clap::Parser;
+
+use crate::pipeline::{channel::Channel, PipelineMessage};
+
+use super::Subcommand;
+
+#[derive(Parser)]
+#[clap(
+    about = "Merges the frames with the same index from every input stream into one point cloud.",
+    override_usage = format!("\x1B[1m{}\x1B[0m +input=a,b,... +output=merged", "merge")
+)]
+pub struct Args {}
+
+pub struct Merge;
+
+impl Merge {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let _args: Args = Args::parse_from(args);
+        Box::new(Merge)
+    }
+}
+
+impl Subcommand for Merge {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        let mut merged: Option<PipelineMessage> = None;
+        for message in messages {
+            match (&mut merged, message) {
+                (_, PipelineMessage::End) => {
+                    channel.send(PipelineMessage::End);
+                    return Ok(());
+                }
+                (
+                    None,
+                    message @ (PipelineMessage::IndexedPointCloud(_, _)
+                    | PipelineMessage::IndexedPointCloudNormal(_, _)),
+                ) => merged = Some(message),
+                (
+                    Some(PipelineMessage::IndexedPointCloud(pc, _)),
+                    PipelineMessage::IndexedPointCloud(other, _),
+                ) => pc.combine(&other),
+                (
+                    Some(PipelineMessage::IndexedPointCloudNormal(pc, _)),
+                    PipelineMessage::IndexedPointCloudNormal(other, _),
+                ) => {
+                    // !! `combine` needs `Serialize`, which points with normals do not implement
+                    pc.number_of_points += other.number_of_points;
+                    pc.points.extend(other.points);
+                }
+                (
+                    Some(_),
+                    PipelineMessage::IndexedPointCloud(_, _)
+                    | PipelineMessage::IndexedPointCloudNormal(_, _),
+                ) => return Err("Cannot merge point clouds with and without normals".to_string()),
+                (
+                    _,
+                    PipelineMessage::Metrics(_)
+                    | PipelineMessage::Error(_)
+                    | PipelineMessage::DummyForIncrement,
+                ) => {}
+            }
+        }
+        if let Some(merged) = merged {
+            channel.send(merged);
+        }
+        Ok(())
+    }
+}
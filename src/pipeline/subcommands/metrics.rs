@@ -30,14 +30,14 @@ impl MetricsCalculator {
 }
 
 impl Subcommand for MetricsCalculator {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
         let mut messages_iter = messages.into_iter();
         let message_one = messages_iter
             .next()
-            .expect("Expecting two input streams for metrics");
+            .ok_or("Expecting two input streams for metrics")?;
         let message_two = messages_iter
             .next()
-            .expect("Expecting two input streams for metrics");
+            .ok_or("Expecting two input streams for metrics")?;
 
         match (&message_one, &message_two) {
             (
@@ -52,5 +52,6 @@ impl Subcommand for MetricsCalculator {
             }
             (_, _) => {}
         }
+        Ok(())
     }
 }
@@ -1,27 +1,56 @@
+pub mod concat;
 pub mod convert;
+pub mod crop;
 pub mod dash;
+pub mod denoise;
+pub mod diff;
 pub mod downsample;
+pub mod exec;
 pub mod info;
+pub mod merge;
 pub mod metrics;
 pub mod normal_estimation;
 pub mod read;
+pub mod register;
 pub mod render;
+pub mod retime;
+pub mod sample;
+pub mod select;
+pub mod stats;
+pub mod transform;
 pub mod upsample;
+pub mod voxelize;
 pub mod write;
 
+pub use concat::Concat;
 pub use convert::Convert;
+pub use crop::Crop;
 pub use dash::Dash;
+pub use denoise::Denoise;
+pub use diff::Diff;
 pub use downsample::Downsampler;
+pub use exec::Exec;
 pub use info::Info;
+pub use merge::Merge;
 pub use metrics::MetricsCalculator;
 pub use normal_estimation::NormalEstimation;
 pub use read::Read;
+pub use register::Register;
 pub use render::Render;
+pub use retime::Retime;
+pub use sample::Sample;
+pub use select::Select;
+pub use stats::Stats;
+pub use transform::Transform;
 pub use upsample::Upsampler;
+pub use voxelize::Voxelize;
 pub use write::Write;
 
 use super::{channel::Channel, PipelineMessage};
 
 pub trait Subcommand {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, out: &Channel);
+    /// Handles one group of messages, one from each input stream, or an empty group for sources.
+    /// An error skips or aborts depending on `--on-error`, sources report failed frames
+    /// with `Channel::send_error` instead so that they can keep going.
+    fn handle(&mut self, messages: Vec<PipelineMessage>, out: &Channel) -> Result<(), String>;
 }
@@ -1,6 +1,7 @@
 use crate::formats::{
     pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud,
 };
+use crate::pcd::read_pcd_header;
 use crate::pipeline::channel::Channel;
 use crate::pipeline::PipelineMessage;
 use clap::Parser;
@@ -8,21 +9,92 @@ use kdtree::distance::squared_euclidean;
 use kdtree::KdTree;
 use nalgebra::{Matrix3, Vector3};
 use rayon::prelude::*;
-use std::collections::VecDeque;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
 
+use super::transform::parse_floats;
 use super::Subcommand;
 
 type PointType = [f64; 3];
 
+/// How the sign of the normals is chosen, the eigenvectors have an arbitrary one
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orient {
+    /// Keeps the sign of the eigenvectors
+    None,
+    /// Flips the neighbors to agree with each point in breadth-first order from the first point
+    Propagate,
+    /// Toward --viewpoint, or the VIEWPOINT header of --viewpoint-from
+    Viewpoint,
+    /// Away from the centroid of the frame, for closed objects
+    Centroid,
+    /// Along the minimum spanning tree of the neighbor graph weighted by the angles between the normals,
+    /// starting from the highest point of each connected part facing up
+    Mst,
+}
+
 #[derive(Parser)]
 #[clap(about = "Performs normal estimation on point clouds.")]
 pub struct Args {
-    #[clap(short, long, default_value = "30")]
+    #[clap(short, long, default_value = "30", conflicts_with = "radius")]
     k: usize,
+
+    /// Uses the points within this distance as neighbors instead of the k nearest
+    #[clap(long, value_parser = parse_radius)]
+    radius: Option<f64>,
+
+    #[clap(long, value_enum, default_value_t = Orient::Propagate)]
+    orient: Orient,
+
+    /// Point the normals face with --orient viewpoint, as x,y,z [default: 0,0,0]
+    #[clap(long, value_parser = parse_viewpoint, allow_hyphen_values = true, conflicts_with = "viewpoint_from")]
+    viewpoint: Option<[f32; 3]>,
+
+    /// PCD file whose VIEWPOINT header gives the viewpoint, e.g. the first frame of the sequence
+    #[clap(long, value_name = "PCD", value_parser = parse_viewpoint_from)]
+    viewpoint_from: Option<[f32; 3]>,
+}
+
+fn parse_viewpoint(s: &str) -> Result<[f32; 3], String> {
+    match parse_floats(s)?[..] {
+        [x, y, z] => Ok([x, y, z]),
+        ref values => Err(format!("expected 3 values, got {}", values.len())),
+    }
+}
+
+fn parse_radius(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(radius) if radius > 0.0 && radius.is_finite() => Ok(radius),
+        Ok(radius) => Err(format!("expected a positive radius, got {}", radius)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_viewpoint_from(s: &str) -> Result<[f32; 3], String> {
+    let header = read_pcd_header(s).map_err(|e| e.to_string())?;
+    let [x, y, z, ..] = *header.viewpoint();
+    Ok([x, y, z])
+}
+
+/// Points used to fit the tangent plane at every point
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Neighborhood {
+    Nearest(usize),
+    Radius(f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Orientation {
+    None,
+    Propagate,
+    Viewpoint([f32; 3]),
+    Centroid,
+    Mst,
 }
 
 pub struct NormalEstimation {
-    args: Args,
+    neighborhood: Neighborhood,
+    orientation: Orientation,
 }
 
 #[derive(Debug, PartialEq)]
@@ -50,8 +122,23 @@ impl CovarianceMatrix {
 
 impl NormalEstimation {
     pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        let neighborhood = match args.radius {
+            Some(radius) => Neighborhood::Radius(radius),
+            None => Neighborhood::Nearest(args.k),
+        };
+        let orientation = match args.orient {
+            Orient::None => Orientation::None,
+            Orient::Propagate => Orientation::Propagate,
+            Orient::Viewpoint => {
+                Orientation::Viewpoint(args.viewpoint.or(args.viewpoint_from).unwrap_or([0.0; 3]))
+            }
+            Orient::Centroid => Orientation::Centroid,
+            Orient::Mst => Orientation::Mst,
+        };
         Box::from(NormalEstimation {
-            args: Args::parse_from(args),
+            neighborhood,
+            orientation,
         })
     }
 }
@@ -63,12 +150,13 @@ struct EigenData {
 }
 
 impl Subcommand for NormalEstimation {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
         // Perform normal estimation for each point cloud in the messages
         for message in messages {
             match message {
                 PipelineMessage::IndexedPointCloud(pc, i) => {
-                    let normal_estimation_result = perform_normal_estimation(&pc, self.args.k);
+                    let normal_estimation_result =
+                        perform_normal_estimation(&pc, self.neighborhood, self.orientation);
                     channel.send(PipelineMessage::IndexedPointCloudNormal(
                         normal_estimation_result,
                         i,
@@ -76,21 +164,24 @@ impl Subcommand for NormalEstimation {
                 }
                 PipelineMessage::Metrics(_)
                 | PipelineMessage::IndexedPointCloudNormal(_, _)
+                | PipelineMessage::Error(_)
                 | PipelineMessage::DummyForIncrement => {}
                 PipelineMessage::End => {
                     channel.send(message);
                 }
             }
         }
+        Ok(())
     }
 }
 
 fn perform_normal_estimation(
     pc: &PointCloud<PointXyzRgba>,
-    k: usize,
+    neighborhood: Neighborhood,
+    orientation: Orientation,
 ) -> PointCloud<PointXyzRgbaNormal> {
     // Select Neighboring Points
-    let neighbors = select_neighbors(pc, k);
+    let neighbors = select_neighbors(pc, neighborhood);
 
     // Compute Covariance Matrix
     let covariance_matrices = compute_covariance_matrices(&pc, &neighbors);
@@ -123,7 +214,26 @@ fn perform_normal_estimation(
 
     assign_normal_vectors(&mut pc_normal, &eigen_results);
 
-    propagate_normal_orientation(&mut pc_normal, &neighbors);
+    match orientation {
+        Orientation::None => {}
+        Orientation::Propagate => propagate_normal_orientation(&mut pc_normal, &neighbors),
+        Orientation::Viewpoint(viewpoint) => orient_toward(&mut pc_normal, |_| viewpoint),
+        Orientation::Centroid => {
+            let n = pc_normal.points.len().max(1) as f32;
+            let centroid = pc_normal.points.iter().fold([0.0; 3], |c, p| {
+                [c[0] + p.x / n, c[1] + p.y / n, c[2] + p.z / n]
+            });
+            // !! facing away from the centroid is facing toward its mirror image through the point
+            orient_toward(&mut pc_normal, |p| {
+                [
+                    2.0 * p.x - centroid[0],
+                    2.0 * p.y - centroid[1],
+                    2.0 * p.z - centroid[2],
+                ]
+            });
+        }
+        Orientation::Mst => orient_along_mst(&mut pc_normal, &neighbors),
+    }
 
     pc_normal
 }
@@ -138,20 +248,21 @@ fn build_kd_tree(points: &[PointXyzRgba]) -> KdTree<f64, usize, PointType> {
     kdtree
 }
 
-fn select_neighbors(pc: &PointCloud<PointXyzRgba>, k: usize) -> Vec<Vec<usize>> {
+fn select_neighbors(pc: &PointCloud<PointXyzRgba>, neighborhood: Neighborhood) -> Vec<Vec<usize>> {
     let kdtree = build_kd_tree(&pc.points);
     pc.points
         .par_iter() // Parallel iterator
         .enumerate()
         .map(|(i, point)| {
-            // Ask for k+1 neighbors to account for the point itself
-            let ret = kdtree
-                .nearest(
-                    &[point.x as f64, point.y as f64, point.z as f64],
-                    k + 1,
-                    &squared_euclidean,
-                )
-                .unwrap();
+            let position = [point.x as f64, point.y as f64, point.z as f64];
+            let ret = match neighborhood {
+                // Ask for k+1 neighbors to account for the point itself
+                Neighborhood::Nearest(k) => kdtree.nearest(&position, k + 1, &squared_euclidean),
+                Neighborhood::Radius(radius) => {
+                    kdtree.within(&position, radius * radius, &squared_euclidean)
+                }
+            }
+            .unwrap();
             let mut neighbor_indices = Vec::new();
             for &(_dist, &index) in ret.iter() {
                 // Exclude the point itself
@@ -307,6 +418,9 @@ fn assign_normal_vectors(pc: &mut PointCloud<PointXyzRgbaNormal>, eigen_results:
 }
 
 fn propagate_normal_orientation(pc: &mut PointCloud<PointXyzRgbaNormal>, neighbors: &[Vec<usize>]) {
+    if pc.points.is_empty() {
+        return;
+    }
     let root_point_index = 0; // Choose the root point index (e.g., 0)
 
     // Use a queue to perform a breadth-first search
@@ -349,3 +463,133 @@ fn propagate_normal_orientation(pc: &mut PointCloud<PointXyzRgbaNormal>, neighbo
         }
     }
 }
+
+/// Flips the normals that point away from `target(point)`
+fn orient_toward(
+    pc: &mut PointCloud<PointXyzRgbaNormal>,
+    target: impl Fn(&PointXyzRgbaNormal) -> [f32; 3],
+) {
+    for p in &mut pc.points {
+        let [x, y, z] = target(p);
+        if p.nx * (x - p.x) + p.ny * (y - p.y) + p.nz * (z - p.z) < 0.0 {
+            (p.nx, p.ny, p.nz) = (-p.nx, -p.ny, -p.nz);
+        }
+    }
+}
+
+fn flip_to_agree(pc: &mut PointCloud<PointXyzRgbaNormal>, from: usize, to: usize) {
+    let (a, b) = (pc.points[from], pc.points[to]);
+    if a.nx * b.nx + a.ny * b.ny + a.nz * b.nz < 0.0 {
+        let p = &mut pc.points[to];
+        (p.nx, p.ny, p.nz) = (-p.nx, -p.ny, -p.nz);
+    }
+}
+
+/// Hoppe et al.'s propagation, over Prim's minimum spanning tree of the symmetric neighbor graph
+/// so that the sign crosses the edges between nearly parallel normals first
+fn orient_along_mst(pc: &mut PointCloud<PointXyzRgbaNormal>, neighbors: &[Vec<usize>]) {
+    let n = pc.points.len();
+    let mut graph = vec![vec![]; n];
+    for (i, point_neighbors) in neighbors.iter().enumerate() {
+        for &j in point_neighbors {
+            graph[i].push(j);
+            graph[j].push(i);
+        }
+    }
+    // !! seeds are taken from the highest point down, one for each connected part
+    let mut by_height = (0..n).collect::<Vec<_>>();
+    by_height.sort_by(|&a, &b| pc.points[b].z.total_cmp(&pc.points[a].z));
+
+    let mut visited = vec![false; n];
+    // !! weights are 1 - |cos|, stored as integers so that they can be ordered in the heap
+    let weight = |a: &PointXyzRgbaNormal, b: &PointXyzRgbaNormal| {
+        let cos = (a.nx * b.nx + a.ny * b.ny + a.nz * b.nz).abs().min(1.0);
+        ((1.0 - cos) * u32::MAX as f32) as u32
+    };
+    let mut heap = BinaryHeap::new();
+    for seed in by_height {
+        if visited[seed] {
+            continue;
+        }
+        if pc.points[seed].nz < 0.0 {
+            let p = &mut pc.points[seed];
+            (p.nx, p.ny, p.nz) = (-p.nx, -p.ny, -p.nz);
+        }
+        heap.push(Reverse((0, seed, seed)));
+        while let Some(Reverse((_, from, to))) = heap.pop() {
+            if visited[to] {
+                continue;
+            }
+            visited[to] = true;
+            flip_to_agree(pc, from, to);
+            for &next in &graph[to] {
+                if !visited[next] {
+                    let w = weight(&pc.points[to], &pc.points[next]);
+                    heap.push(Reverse((w, to, next)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points on a sphere of radius 1 around the origin, from a Fibonacci lattice
+    fn sphere(n: usize) -> PointCloud<PointXyzRgba> {
+        let golden = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+        let points = (0..n)
+            .map(|i| {
+                let z = 1.0 - 2.0 * (i as f32 + 0.5) / n as f32;
+                let r = (1.0 - z * z).sqrt();
+                let theta = golden * i as f32;
+                PointXyzRgba {
+                    x: r * theta.cos(),
+                    y: r * theta.sin(),
+                    z,
+                    r: 0,
+                    g: 0,
+                    b: 0,
+                    a: 255,
+                }
+            })
+            .collect::<Vec<_>>();
+        PointCloud {
+            number_of_points: points.len(),
+            points,
+        }
+    }
+
+    fn outward(pc: &PointCloud<PointXyzRgbaNormal>) -> usize {
+        pc.points
+            .iter()
+            .filter(|p| p.nx * p.x + p.ny * p.y + p.nz * p.z > 0.9)
+            .count()
+    }
+
+    #[test]
+    fn test_parse_radius() {
+        assert_eq!(parse_radius("0.5"), Ok(0.5));
+        assert!(parse_radius("0").is_err());
+        assert!(parse_radius("-1").is_err());
+        assert!(parse_radius("inf").is_err());
+        assert!(parse_radius("NaN").is_err());
+    }
+
+    #[test]
+    fn test_orientation() {
+        let pc = sphere(500);
+        let nearest = Neighborhood::Nearest(10);
+        for orientation in [Orientation::Centroid, Orientation::Mst] {
+            let normals = perform_normal_estimation(&pc, nearest, orientation);
+            assert_eq!(outward(&normals), 500, "{:?}", orientation);
+        }
+        // !! the viewpoint is inside the sphere, every normal faces inward
+        let normals = perform_normal_estimation(&pc, nearest, Orientation::Viewpoint([0.0; 3]));
+        assert_eq!(outward(&normals), 0);
+
+        let normals = perform_normal_estimation(&pc, Neighborhood::Radius(0.3), Orientation::Mst);
+        assert_eq!(outward(&normals), 500);
+    }
+}
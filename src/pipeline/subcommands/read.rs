@@ -1,10 +1,16 @@
 use clap::Parser;
 use std::ffi::OsString;
+use std::path::Path;
 
 use super::Subcommand;
+use crate::formats::{from_legacy_rgba, PointCloud};
+use crate::las::read_las_file;
+use crate::pcd::read_pcd_file;
 use crate::pipeline::channel::Channel;
 use crate::pipeline::PipelineMessage;
-use crate::utils::{find_all_files, read_file_to_point_cloud};
+use crate::ply::{has_normals, read_ply_header, read_ply_vertices};
+use crate::utils::{find_all_files, try_read_file_to_point_cloud};
+use crate::xyz::{is_text_file, parse_columns, read_text_file, ColorScale, Column, TextOptions};
 
 #[derive(clap::ValueEnum, Clone, Copy)]
 pub enum FileType {
@@ -12,11 +18,45 @@ pub enum FileType {
     Ply,
     Pcd,
     Bin,
+    Las,
+    /// .xyz .txt .csv and .pts
+    Text,
+}
+
+/// Columns of a text file, parsed together to check them as a whole
+#[derive(Clone, Debug)]
+pub struct Columns(Vec<Column>);
+
+/// How text files (.xyz .txt .csv .pts) are read or written
+#[derive(clap::Args, Debug)]
+pub struct TextArgs {
+    /// Columns of text files, comma separated among x, y, z, r, g, b, a, nx, ny, nz, i (intensity) and _ (ignored).
+    /// Read from the header of the file or guessed from the number of values when not given
+    #[clap(long, value_parser = |s: &str| parse_columns(s).map(Columns))]
+    columns: Option<Columns>,
+
+    /// Lines skipped at the start of text files
+    #[clap(long, default_value_t = 0)]
+    skip_lines: usize,
+
+    /// Range of the colors of text files, auto reads them from 0 to 1 if one is fractional and none is larger than 1
+    #[clap(long, value_enum, default_value_t = ColorScale::Auto)]
+    color_scale: ColorScale,
+}
+
+impl TextArgs {
+    pub fn options(&self) -> TextOptions {
+        TextOptions {
+            columns: self.columns.as_ref().map(|c| c.0.clone()),
+            skip_lines: self.skip_lines,
+            color_scale: self.color_scale,
+        }
+    }
 }
 
 #[derive(Parser)]
 #[clap(
-    about = "Reads in one of our supported file formats. \nFiles can be of the type .pcd .ply. \nThe path can be a file path or a directory path contains these files.",
+    about = "Reads in one of our supported file formats. \nFiles can be of the type .pcd .ply .bin .las and text (.xyz .txt .csv .pts). \nThe path can be a file path or a directory path contains these files.",
     override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] <FILES>... +output=plys", "read")
 )]
 pub struct Args {
@@ -28,6 +68,19 @@ pub struct Args {
     #[clap(short, long)]
     /// read previous n files after sorting lexicalgraphically
     num: Option<usize>,
+
+    /// Reads the packed colors of PCD files written by earlier versions of vvtk,
+    /// which have red and blue swapped otherwise
+    #[clap(long)]
+    legacy_rgba: bool,
+
+    /// Reads the positions of LAS files relative to the offset of their header, which keeps
+    /// the precision of coordinates in the millions such as UTM ones. The offsets are printed
+    #[clap(long)]
+    las_recenter: bool,
+
+    #[clap(flatten)]
+    text: TextArgs,
 }
 
 pub struct Read {
@@ -42,11 +95,56 @@ impl Read {
     }
 }
 
+/// Point clouds of ply and text files with normals keep them
+fn read_message(
+    file: &Path,
+    index: u32,
+    text: &TextOptions,
+    legacy_rgba: bool,
+    las_recenter: bool,
+) -> Result<PipelineMessage, String> {
+    if is_text_file(file) {
+        let text = read_text_file(file, text).map_err(|e| e.to_string())?;
+        return Ok(if text.has_normals() {
+            PipelineMessage::IndexedPointCloudNormal(text.into(), index)
+        } else {
+            PipelineMessage::IndexedPointCloud(text.into(), index)
+        });
+    }
+    if file.extension().and_then(|ext| ext.to_str()) == Some("ply") {
+        return if has_normals(&read_ply_header(file)?) {
+            read_ply_vertices(file).map(|pc| PipelineMessage::IndexedPointCloudNormal(pc, index))
+        } else {
+            read_ply_vertices(file).map(|pc| PipelineMessage::IndexedPointCloud(pc, index))
+        };
+    }
+    if legacy_rgba && file.extension().and_then(|ext| ext.to_str()) == Some("pcd") {
+        return read_pcd_file(file)
+            .map(from_legacy_rgba)
+            .and_then(PointCloud::try_from)
+            .map(|pc| PipelineMessage::IndexedPointCloud(pc, index))
+            .map_err(|e| e.to_string());
+    }
+    if las_recenter && file.extension().and_then(|ext| ext.to_str()) == Some("las") {
+        let las = read_las_file(file).map_err(|e| e.to_string())?;
+        let [x, y, z] = las.header().offset;
+        println!(
+            "{:?}: positions relative to the LAS offset {} {} {}",
+            file, x, y, z
+        );
+        let pc = PointCloud::from_las_relative_to_offset(las);
+        return Ok(PipelineMessage::IndexedPointCloud(pc, index));
+    }
+    try_read_file_to_point_cloud(&file.to_path_buf())
+        .map(|pc| PipelineMessage::IndexedPointCloud(pc, index))
+}
+
 impl Subcommand for Read {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
         println!("Reading files");
         if messages.is_empty() {
-            let mut files = find_all_files(&self.args.files);
+            let mut files = find_all_files(&self.args.files)?;
+            let text = self.args.text.options();
             files.sort();
             // if self.num is not None, then take the first self.num files
             if let Some(num) = self.args.num {
@@ -56,6 +154,9 @@ impl Subcommand for Read {
             }
 
             for (i, file) in files.iter().enumerate() {
+                if channel.is_aborted() {
+                    break;
+                }
                 match &self.args.filetype {
                     FileType::All => {}
                     FileType::Pcd => {
@@ -73,11 +174,28 @@ impl Subcommand for Read {
                             continue;
                         }
                     }
+                    FileType::Las => {
+                        if file.extension().and_then(|ext| ext.to_str()) != Some("las") {
+                            continue;
+                        }
+                    }
+                    FileType::Text => {
+                        if !is_text_file(file) {
+                            continue;
+                        }
+                    }
                 }
 
-                let point_cloud = read_file_to_point_cloud(file);
-                if let Some(pc) = point_cloud {
-                    channel.send(PipelineMessage::IndexedPointCloud(pc, i as u32));
+                match read_message(
+                    file,
+                    i as u32,
+                    &text,
+                    self.args.legacy_rgba,
+                    self.args.las_recenter,
+                ) {
+                    Ok(message) => channel.send(message),
+                    Err(e) => channel
+                        .send_error(Some(i as u32), format!("Failed to read {:?}: {}", file, e)),
                 }
             }
             channel.send(PipelineMessage::End);
@@ -86,5 +204,6 @@ impl Subcommand for Read {
                 channel.send(message);
             }
         }
+        Ok(())
     }
 }
@@ -0,0 +1,138 @@
+use clap::Parser;
+use nalgebra::{Matrix4, Point3, Vector3};
+
+use crate::formats::PointCloud;
+use crate::metrics::Metrics;
+use crate::pipeline::{channel::Channel, PipelineMessage};
+use crate::registration::{icp, IcpMethod, IcpOptions};
+
+use super::Subcommand;
+
+#[derive(Parser)]
+#[clap(
+    about = "Aligns the second input stream to the first one with ICP.\nSends the aligned frames, each followed by a report with the transform and the RMS error.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] +input=target,source +output=aligned", "register")
+)]
+pub struct Args {
+    /// Point-to-plane needs the first input to have normals, see `normal`
+    #[clap(long, value_enum, default_value_t = IcpMethod::PointToPoint)]
+    method: IcpMethod,
+
+    #[clap(long, default_value_t = 50)]
+    max_iterations: usize,
+
+    /// Stops once the RMS error improves by less than this or gets worse
+    #[clap(long, default_value_t = 1e-6)]
+    tolerance: f64,
+
+    /// Ignores the pairs of points further apart than this, e.g. parts missing from one of the inputs
+    #[clap(long)]
+    max_distance: Option<f64>,
+}
+
+pub struct Register {
+    options: IcpOptions,
+}
+
+impl Register {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        Box::new(Register {
+            options: IcpOptions {
+                method: args.method,
+                max_iterations: args.max_iterations,
+                tolerance: args.tolerance,
+                max_distance: args.max_distance,
+            },
+        })
+    }
+}
+
+fn positions<T>(pc: &PointCloud<T>, position: impl Fn(&T) -> [f32; 3]) -> Vec<[f32; 3]> {
+    pc.points.iter().map(position).collect()
+}
+
+fn apply(transform: &Matrix4<f64>, x: &mut f32, y: &mut f32, z: &mut f32) {
+    let p = transform.transform_point(&Point3::new(*x as f64, *y as f64, *z as f64));
+    (*x, *y, *z) = (p.x as f32, p.y as f32, p.z as f32);
+}
+
+fn rotate(transform: &Matrix4<f64>, x: &mut f32, y: &mut f32, z: &mut f32) {
+    let n = transform.transform_vector(&Vector3::new(*x as f64, *y as f64, *z as f64));
+    (*x, *y, *z) = (n.x as f32, n.y as f32, n.z as f32);
+}
+
+impl Subcommand for Register {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        let mut messages_iter = messages.into_iter();
+        let target = messages_iter
+            .next()
+            .ok_or("Expecting two input streams for register")?;
+        let source = messages_iter
+            .next()
+            .ok_or("Expecting two input streams for register")?;
+
+        let (target_positions, target_normals) = match &target {
+            PipelineMessage::IndexedPointCloud(pc, _) => (positions(pc, |p| [p.x, p.y, p.z]), None),
+            PipelineMessage::IndexedPointCloudNormal(pc, _) => (
+                positions(pc, |p| [p.x, p.y, p.z]),
+                Some(positions(pc, |p| [p.nx, p.ny, p.nz])),
+            ),
+            PipelineMessage::End => {
+                channel.send(PipelineMessage::End);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        let source_positions = match &source {
+            PipelineMessage::IndexedPointCloud(pc, _) => positions(pc, |p| [p.x, p.y, p.z]),
+            PipelineMessage::IndexedPointCloudNormal(pc, _) => positions(pc, |p| [p.x, p.y, p.z]),
+            PipelineMessage::End => {
+                channel.send(PipelineMessage::End);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+
+        let result = icp(
+            &target_positions,
+            target_normals.as_deref(),
+            &source_positions,
+            self.options,
+        )?;
+        let transform = result.transform;
+        let index = match source {
+            PipelineMessage::IndexedPointCloud(mut pc, i) => {
+                for p in &mut pc.points {
+                    apply(&transform, &mut p.x, &mut p.y, &mut p.z);
+                }
+                channel.send(PipelineMessage::IndexedPointCloud(pc, i));
+                i
+            }
+            PipelineMessage::IndexedPointCloudNormal(mut pc, i) => {
+                for p in &mut pc.points {
+                    apply(&transform, &mut p.x, &mut p.y, &mut p.z);
+                    rotate(&transform, &mut p.nx, &mut p.ny, &mut p.nz);
+                }
+                channel.send(PipelineMessage::IndexedPointCloudNormal(pc, i));
+                i
+            }
+            _ => unreachable!("checked above"),
+        };
+
+        let mut metrics = Metrics::new();
+        metrics.insert("index".to_string(), index.to_string());
+        metrics.insert("rms".to_string(), format!("{:.5}", result.rms));
+        metrics.insert("iterations".to_string(), result.iterations.to_string());
+        // !! row by row, separated by spaces since metrics are written as `key,value`
+        let transform = transform
+            .transpose()
+            .iter()
+            .map(|v| format!("{:.6}", v))
+            .collect::<Vec<_>>()
+            .join(" ");
+        metrics.insert("transform".to_string(), transform);
+        channel.send(PipelineMessage::Metrics(metrics));
+        Ok(())
+    }
+}
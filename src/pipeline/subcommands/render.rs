@@ -121,7 +121,7 @@ impl<'a> Render<'a> {
 }
 
 impl Subcommand for Render<'_> {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
         let max_count = pow(10, self.name_length as usize);
 
         for message in messages {
@@ -131,8 +131,7 @@ impl Subcommand for Render<'_> {
                     let filename = format!("{}.png", padded_count);
                     self.count += 1;
                     if self.count >= max_count {
-                        channel.send(PipelineMessage::End);
-                        panic!("Too many files, please increase the name length by setting --name-length")
+                        return Err("Too many files, please increase the name length by setting --name-length".to_string());
                     }
                     self.writer.write_to_png(pc, &filename);
                 }
@@ -140,6 +139,7 @@ impl Subcommand for Render<'_> {
             }
             channel.send(message);
         }
+        Ok(())
     }
 }
 
@@ -0,0 +1,385 @@
+use clap::Parser;
+use kiddo::distance::squared_euclidean;
+use rayon::prelude::*;
+
+use crate::formats::{
+    pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud,
+};
+use crate::metrics::{build_tree, is_finite};
+use crate::pipeline::{channel::Channel, PipelineMessage};
+
+use super::Subcommand;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Drops or duplicates frames, each output frame is the closest input frame in time
+    Nearest,
+    /// Blends the positions and colors of nearest neighbours in the two surrounding frames
+    Interpolate,
+}
+
+#[derive(Parser)]
+#[clap(
+    about = "Changes the frame rate of a stream, renumbering the frames.\nFrame i of the input is shown at i / from seconds, frame j of the output at j / fps seconds.",
+    override_usage = format!("\x1B[1m{}\x1B[0m --fps <FPS> [OPTIONS] +input=in +output=out", "retime")
+)]
+pub struct Args {
+    /// Frame rate of the output
+    #[clap(long, value_parser = parse_fps)]
+    fps: f64,
+
+    /// Frame rate of the input
+    #[clap(long, default_value_t = 30.0, value_parser = parse_fps)]
+    from: f64,
+
+    #[clap(long, value_enum, default_value_t = Mode::Nearest)]
+    mode: Mode,
+}
+
+fn parse_fps(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fps) if fps > 0.0 => Ok(fps),
+        Ok(fps) => Err(format!("expected a positive frame rate, got {}", fps)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub struct Retime {
+    /// input frames per output frame
+    step: f64,
+    mode: Mode,
+    /// last input frame and its index, output frames up to it have been sent
+    previous: Option<PipelineMessage>,
+    previous_index: u32,
+    /// index of the next output frame
+    next: u64,
+}
+
+impl Retime {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        Box::new(Retime {
+            step: args.from / args.fps,
+            mode: args.mode,
+            previous: None,
+            previous_index: 0,
+            next: 0,
+        })
+    }
+
+    /// Position of output frame `j` in input frame indices
+    fn position(&self, j: u64) -> f64 {
+        j as f64 * self.step
+    }
+
+    /// First output frame at or after the input frame `index`
+    fn first_at(&self, index: u32) -> u64 {
+        // !! rounding errors would skip a frame sitting exactly on `index`
+        ((index as f64 / self.step) - 1e-9).ceil().max(0.0) as u64
+    }
+
+    /// Sends the output frames before the input frame `index`, between `previous` and `next`
+    fn send_until(&mut self, index: f64, next: Option<&PipelineMessage>, channel: &Channel) {
+        let previous = match &self.previous {
+            Some(previous) => previous,
+            None => return,
+        };
+        while self.position(self.next) < index - 1e-9 {
+            let j = self.next;
+            let alpha = (self.position(j) - self.previous_index as f64)
+                / (index - self.previous_index as f64);
+            let frame = match (self.mode, next) {
+                (Mode::Interpolate, Some(next)) if alpha > 1e-9 => blend(previous, next, alpha),
+                (Mode::Nearest, Some(next)) if alpha >= 0.5 => next.clone(),
+                _ => previous.clone(),
+            };
+            channel.send(with_index(frame, j as u32));
+            self.next += 1;
+        }
+    }
+}
+
+fn with_index(message: PipelineMessage, index: u32) -> PipelineMessage {
+    match message {
+        PipelineMessage::IndexedPointCloud(pc, _) => PipelineMessage::IndexedPointCloud(pc, index),
+        PipelineMessage::IndexedPointCloudNormal(pc, _) => {
+            PipelineMessage::IndexedPointCloudNormal(pc, index)
+        }
+        message => message,
+    }
+}
+
+fn lerp(a: f32, b: f32, alpha: f32) -> f32 {
+    a + (b - a) * alpha
+}
+
+fn lerp_u8(a: u8, b: u8, alpha: f32) -> u8 {
+    lerp(a as f32, b as f32, alpha).round() as u8
+}
+
+/// Matches every point of the frame closest in time to its nearest neighbour in the other frame,
+/// the blended frame has as many points as the closest one.
+/// Points with a NaN or infinite coordinate on either side are left unmatched.
+fn matches<T: Sync>(
+    from: &PointCloud<T>,
+    to: &PointCloud<T>,
+    position: impl Fn(&T) -> [f32; 3] + Sync,
+) -> Vec<Option<usize>> {
+    let tree = build_tree(to.points.iter().map(&position));
+    from.points
+        .par_iter()
+        .map(|p| {
+            let position = position(p);
+            if !is_finite(&position) {
+                return None;
+            }
+            tree.nearest_one(&position, &squared_euclidean)
+                .ok()
+                .map(|(_, &i)| i)
+        })
+        .collect()
+}
+
+fn blend_points(a: &PointXyzRgba, b: &PointXyzRgba, alpha: f32) -> PointXyzRgba {
+    PointXyzRgba {
+        x: lerp(a.x, b.x, alpha),
+        y: lerp(a.y, b.y, alpha),
+        z: lerp(a.z, b.z, alpha),
+        r: lerp_u8(a.r, b.r, alpha),
+        g: lerp_u8(a.g, b.g, alpha),
+        b: lerp_u8(a.b, b.b, alpha),
+        a: lerp_u8(a.a, b.a, alpha),
+    }
+}
+
+fn blend_points_normal(
+    a: &PointXyzRgbaNormal,
+    b: &PointXyzRgbaNormal,
+    alpha: f32,
+) -> PointXyzRgbaNormal {
+    let normal = [
+        lerp(a.nx, b.nx, alpha),
+        lerp(a.ny, b.ny, alpha),
+        lerp(a.nz, b.nz, alpha),
+    ];
+    let norm = normal.iter().map(|n| n * n).sum::<f32>().sqrt();
+    let [nx, ny, nz] = if norm > f32::EPSILON {
+        normal.map(|n| n / norm)
+    } else {
+        [a.nx, a.ny, a.nz]
+    };
+    PointXyzRgbaNormal {
+        x: lerp(a.x, b.x, alpha),
+        y: lerp(a.y, b.y, alpha),
+        z: lerp(a.z, b.z, alpha),
+        r: lerp_u8(a.r, b.r, alpha),
+        g: lerp_u8(a.g, b.g, alpha),
+        b: lerp_u8(a.b, b.b, alpha),
+        a: lerp_u8(a.a, b.a, alpha),
+        nx,
+        ny,
+        nz,
+    }
+}
+
+fn blend_clouds<T: Sync + Clone>(
+    a: &PointCloud<T>,
+    b: &PointCloud<T>,
+    alpha: f32,
+    position: impl Fn(&T) -> [f32; 3] + Sync,
+    blend: impl Fn(&T, &T, f32) -> T,
+) -> PointCloud<T> {
+    if a.points.is_empty() || b.points.is_empty() {
+        let closest = if alpha < 0.5 { a } else { b };
+        let points = closest.points.clone();
+        return PointCloud {
+            number_of_points: closest.points.len(),
+            points,
+        };
+    }
+    let points: Vec<T> = if alpha < 0.5 {
+        matches(a, b, &position)
+            .into_iter()
+            .zip(&a.points)
+            .map(|(i, p)| match i {
+                Some(i) => blend(p, &b.points[i], alpha),
+                None => p.clone(),
+            })
+            .collect()
+    } else {
+        matches(b, a, &position)
+            .into_iter()
+            .zip(&b.points)
+            .map(|(i, p)| match i {
+                Some(i) => blend(&a.points[i], p, alpha),
+                None => p.clone(),
+            })
+            .collect()
+    };
+    PointCloud {
+        number_of_points: points.len(),
+        points,
+    }
+}
+
+/// Frame at `alpha` between `a` (0) and `b` (1), frames of different kinds are not blended
+fn blend(a: &PipelineMessage, b: &PipelineMessage, alpha: f64) -> PipelineMessage {
+    let alpha = alpha as f32;
+    match (a, b) {
+        (PipelineMessage::IndexedPointCloud(a, i), PipelineMessage::IndexedPointCloud(b, _)) => {
+            let pc = blend_clouds(a, b, alpha, |p| [p.x, p.y, p.z], blend_points);
+            PipelineMessage::IndexedPointCloud(pc, *i)
+        }
+        (
+            PipelineMessage::IndexedPointCloudNormal(a, i),
+            PipelineMessage::IndexedPointCloudNormal(b, _),
+        ) => {
+            let pc = blend_clouds(a, b, alpha, |p| [p.x, p.y, p.z], blend_points_normal);
+            PipelineMessage::IndexedPointCloudNormal(pc, *i)
+        }
+        _ if alpha < 0.5 => a.clone(),
+        _ => b.clone(),
+    }
+}
+
+impl Subcommand for Retime {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(_, i)
+                | PipelineMessage::IndexedPointCloudNormal(_, i) => {
+                    if self.previous.is_none() {
+                        self.next = self.first_at(i);
+                    } else if i <= self.previous_index {
+                        return Err(format!(
+                            "Frames should arrive in increasing index order, got {} after {}",
+                            i, self.previous_index
+                        ));
+                    }
+                    self.send_until(i as f64, Some(&message), channel);
+                    self.previous = Some(message);
+                    self.previous_index = i;
+                }
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
+                PipelineMessage::End => {
+                    // !! the last frame lasts one input frame, like the others
+                    let end = self.previous_index as f64 + 1.0;
+                    self.send_until(end, None, channel);
+                    channel.send(message);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retime(fps: f64, mode: Mode) -> Retime {
+        Retime {
+            step: 30.0 / fps,
+            mode,
+            previous: None,
+            previous_index: 0,
+            next: 0,
+        }
+    }
+
+    fn frame(x: f32, r: u8, index: u32) -> PipelineMessage {
+        let pc = PointCloud {
+            number_of_points: 1,
+            points: vec![PointXyzRgba {
+                x,
+                y: 0.0,
+                z: 0.0,
+                r,
+                g: 0,
+                b: 0,
+                a: 255,
+            }],
+        };
+        PipelineMessage::IndexedPointCloud(pc, index)
+    }
+
+    /// (index, x, r) of every frame sent for the input frames `0..n` at x = 10 * i and r = 100 * i
+    fn run(mut retime: Retime, n: u32) -> Vec<(u32, f32, u8)> {
+        let (progress_tx, _) = crossbeam_channel::unbounded();
+        let mut channel = Channel::new(progress_tx);
+        let output = channel.subscribe_unbounded();
+        for i in 0..n {
+            retime
+                .handle(vec![frame(10.0 * i as f32, 100 * i as u8, i)], &channel)
+                .unwrap();
+        }
+        retime.handle(vec![PipelineMessage::End], &channel).unwrap();
+        output
+            .try_iter()
+            .filter_map(|message| match message {
+                PipelineMessage::IndexedPointCloud(pc, i) => {
+                    Some((i, pc.points[0].x, pc.points[0].r))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_nearest() {
+        let doubled = run(retime(60.0, Mode::Nearest), 3);
+        let indices = doubled.iter().map(|f| f.0).collect::<Vec<_>>();
+        assert_eq!(indices, vec![0, 1, 2, 3, 4, 5]);
+        let xs = doubled.iter().map(|f| f.1).collect::<Vec<_>>();
+        assert_eq!(xs, vec![0.0, 10.0, 10.0, 20.0, 20.0, 20.0]);
+
+        let halved = run(retime(15.0, Mode::Nearest), 3);
+        assert_eq!(halved, vec![(0, 0.0, 0), (1, 20.0, 200)]);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let doubled = run(retime(60.0, Mode::Interpolate), 3);
+        assert_eq!(
+            doubled,
+            vec![
+                (0, 0.0, 0),
+                (1, 5.0, 50),
+                (2, 10.0, 100),
+                (3, 15.0, 150),
+                (4, 20.0, 200),
+                (5, 20.0, 200)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interpolate_keeps_unmatched_points() {
+        let (progress_tx, _) = crossbeam_channel::unbounded();
+        let mut channel = Channel::new(progress_tx);
+        let output = channel.subscribe_unbounded();
+        let mut retime = retime(60.0, Mode::Interpolate);
+        for message in [
+            frame(f32::NAN, 0, 0),
+            frame(10.0, 100, 1),
+            frame(20.0, 200, 2),
+            frame(f32::INFINITY, 0, 3),
+            PipelineMessage::End,
+        ] {
+            retime.handle(vec![message], &channel).unwrap();
+        }
+        let xs = output
+            .try_iter()
+            .filter_map(|message| match message {
+                PipelineMessage::IndexedPointCloud(pc, _) => Some(pc.points[0].x),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(xs.len(), 8);
+        assert!(xs[0].is_nan());
+        assert_eq!(xs[1..5], [10.0, 10.0, 15.0, 20.0]);
+        assert_eq!(xs[5..], [f32::INFINITY; 3]);
+    }
+}
@@ -0,0 +1,97 @@
+use clap::Parser;
+
+use crate::downsample::sample::{sample, Strategy};
+use crate::formats::PointCloud;
+use crate::pipeline::{channel::Channel, PipelineMessage};
+
+use super::Subcommand;
+
+#[derive(Parser)]
+#[clap(
+    about = "Keeps an exact number of points of every frame.",
+    override_usage = format!("\x1B[1m{}\x1B[0m <--count <COUNT>|--ratio <RATIO>> [OPTIONS] +input=in +output=out", "sample")
+)]
+#[clap(group(clap::ArgGroup::new("target").required(true).args(["count", "ratio"])))]
+pub struct Args {
+    /// Number of points to keep, frames with fewer points are left as they are
+    #[clap(short, long)]
+    count: Option<usize>,
+
+    /// Fraction of the points to keep, between 0 and 1
+    #[clap(short, long, value_parser = parse_ratio)]
+    ratio: Option<f64>,
+
+    #[clap(long, value_enum, default_value_t = Strategy::Random)]
+    strategy: Strategy,
+
+    /// The same seed gives the same points, frames use different random choices
+    #[clap(long, default_value_t = 0)]
+    seed: u64,
+}
+
+fn parse_ratio(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        Ok(ratio) => Err(format!("expected a ratio between 0 and 1, got {}", ratio)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub struct Sample {
+    args: Args,
+}
+
+impl Sample {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        Box::new(Sample { args })
+    }
+
+    fn sample<T: Clone>(
+        &self,
+        pc: PointCloud<T>,
+        index: u32,
+        position: impl Fn(&T) -> [f32; 3],
+    ) -> PointCloud<T> {
+        let count = match (self.args.count, self.args.ratio) {
+            (Some(count), _) => count,
+            (None, Some(ratio)) => (pc.points.len() as f64 * ratio).round() as usize,
+            (None, None) => unreachable!("clap requires one of them"),
+        };
+        let positions = pc.points.iter().map(position).collect::<Vec<_>>();
+        // !! each frame gets its own random choices, which only depend on the seed and the index
+        let seed = self.args.seed ^ (index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        let points = sample(&positions, count, self.args.strategy, seed)
+            .into_iter()
+            .map(|i| pc.points[i].clone())
+            .collect::<Vec<_>>();
+        PointCloud {
+            number_of_points: points.len(),
+            points,
+        }
+    }
+}
+
+impl Subcommand for Sample {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(pc, i) => {
+                    let sampled = self.sample(pc, i, |p| [p.x, p.y, p.z]);
+                    channel.send(PipelineMessage::IndexedPointCloud(sampled, i));
+                }
+                PipelineMessage::IndexedPointCloudNormal(pc, i) => {
+                    let sampled = self.sample(pc, i, |p| [p.x, p.y, p.z]);
+                    channel.send(PipelineMessage::IndexedPointCloudNormal(sampled, i));
+                }
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
+                PipelineMessage::End => {
+                    channel.send(message);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+use clap::Parser;
+
+use crate::pipeline::{channel::Channel, PipelineMessage};
+
+use super::Subcommand;
+
+#[derive(Parser)]
+#[clap(
+    about = "Keeps the frames with an index in a range, optionally every n-th one.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] +input=in +output=selected", "select")
+)]
+pub struct Args {
+    /// First index to keep
+    #[clap(long, default_value_t = 0)]
+    start: u32,
+
+    /// Index to stop at, not included. Keeps every frame after `start` if not set
+    #[clap(long)]
+    end: Option<u32>,
+
+    /// Keeps one frame out of every `step`, counting from `start`
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    step: u32,
+
+    /// Numbers the selected frames from 0 instead of keeping their index
+    #[clap(long)]
+    renumber: bool,
+}
+
+pub struct Select {
+    args: Args,
+}
+
+impl Select {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        Box::new(Select { args })
+    }
+
+    /// Index of the frame after selection, `None` if it is dropped
+    fn select(&self, index: u32) -> Option<u32> {
+        let in_range =
+            index >= self.args.start && !matches!(self.args.end, Some(end) if index >= end);
+        let offset = index.checked_sub(self.args.start)?;
+        if !in_range || offset % self.args.step != 0 {
+            return None;
+        }
+        Some(if self.args.renumber {
+            offset / self.args.step
+        } else {
+            index
+        })
+    }
+}
+
+impl Subcommand for Select {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(pc, i) => {
+                    if let Some(i) = self.select(i) {
+                        channel.send(PipelineMessage::IndexedPointCloud(pc, i));
+                    }
+                }
+                PipelineMessage::IndexedPointCloudNormal(pc, i) => {
+                    if let Some(i) = self.select(i) {
+                        channel.send(PipelineMessage::IndexedPointCloudNormal(pc, i));
+                    }
+                }
+                PipelineMessage::End => channel.send(message),
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let select = Select {
+            args: Args::parse_from(["select", "--start", "100", "--end", "400", "--step", "2"]),
+        };
+        assert_eq!(select.select(99), None);
+        assert_eq!(select.select(100), Some(100));
+        assert_eq!(select.select(101), None);
+        assert_eq!(select.select(398), Some(398));
+        assert_eq!(select.select(400), None);
+
+        let select = Select {
+            args: Args::parse_from(["select", "--start", "10", "--step", "3", "--renumber"]),
+        };
+        assert_eq!(select.select(10), Some(0));
+        assert_eq!(select.select(16), Some(2));
+        assert_eq!(select.select(17), None);
+    }
+}
@@ -0,0 +1,68 @@
+use clap::Parser;
+
+use crate::metrics::calculate_stats;
+use crate::pipeline::{channel::Channel, PipelineMessage};
+
+use super::Subcommand;
+
+#[derive(Parser)]
+#[clap(
+    about = "Computes the bounding box, centroid, point spacing, duplicate points and color statistics of every frame.\nSends them as metrics, which can be written with write and shown by vvplay.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] +input=plys +output=stats", "stats")
+)]
+pub struct Args {
+    /// Number of bins of the histogram of each color channel
+    #[clap(long, default_value_t = 16, value_parser = clap::value_parser!(u16).range(1..=256))]
+    bins: u16,
+}
+
+pub struct Stats {
+    bins: usize,
+}
+
+impl Stats {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        Box::new(Stats {
+            bins: args.bins as usize,
+        })
+    }
+
+    fn send_stats(
+        &self,
+        index: u32,
+        positions: Vec<[f32; 3]>,
+        colors: Vec<[u8; 3]>,
+        channel: &Channel,
+    ) {
+        let mut metrics = calculate_stats(&positions, &colors, self.bins);
+        metrics.insert("index".to_string(), index.to_string());
+        channel.send(PipelineMessage::Metrics(metrics));
+    }
+}
+
+impl Subcommand for Stats {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(pc, i) => {
+                    let positions = pc.points.iter().map(|p| [p.x, p.y, p.z]).collect();
+                    let colors = pc.points.iter().map(|p| [p.r, p.g, p.b]).collect();
+                    self.send_stats(i, positions, colors, channel);
+                }
+                PipelineMessage::IndexedPointCloudNormal(pc, i) => {
+                    let positions = pc.points.iter().map(|p| [p.x, p.y, p.z]).collect();
+                    let colors = pc.points.iter().map(|p| [p.r, p.g, p.b]).collect();
+                    self.send_stats(i, positions, colors, channel);
+                }
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
+                PipelineMessage::End => {
+                    channel.send(message);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,420 @@
+use clap::Parser;
+use nalgebra::{Matrix3, Matrix4, Rotation3, Vector3};
+
+use crate::formats::{
+    pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud,
+};
+use crate::pipeline::{channel::Channel, join::message_index, PipelineMessage};
+
+use super::Subcommand;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Normalize {
+    /// Centers the object at the origin and scales it to fit in [-0.5, 0.5] on every axis
+    UnitCube,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bounds {
+    /// Every frame is normalized with its own bounding box
+    Frame,
+    /// One bounding box for the whole sequence, frames are held back until the end of the input
+    Sequence,
+}
+
+#[derive(Parser)]
+#[clap(
+    about = "Applies an affine transform to every point, normals are rotated with the points.\nThe object is normalized first, then scaled, rotated around the X, Y and Z axes in that order and translated.",
+    override_usage = format!("\x1B[1m{}\x1B[0m [OPTIONS] +input=in +output=out", "transform")
+)]
+pub struct Args {
+    /// Offset added to every point, as x,y,z
+    #[clap(long, value_parser = parse_vector, allow_hyphen_values = true)]
+    translate: Option<Vector3<f32>>,
+
+    /// Rotation in degrees around the X, Y and Z axes, as x,y,z
+    #[clap(long, value_parser = parse_vector, allow_hyphen_values = true)]
+    rotate: Option<Vector3<f32>>,
+
+    /// Scale factor, either one for every axis or x,y,z
+    #[clap(long, value_parser = parse_scale, allow_hyphen_values = true)]
+    scale: Option<Vector3<f32>>,
+
+    /// 4x4 matrix given row by row as 16 comma separated values, replaces the options above
+    #[clap(
+        long,
+        value_parser = parse_matrix,
+        allow_hyphen_values = true,
+        conflicts_with_all = ["translate", "rotate", "scale"]
+    )]
+    matrix: Option<Matrix4<f32>>,
+
+    /// Fits the object in a normalized space before the other transforms
+    #[clap(long, value_enum)]
+    normalize: Option<Normalize>,
+
+    /// Bounding box used by --normalize
+    #[clap(long, value_enum, default_value_t = Bounds::Frame, requires = "normalize")]
+    bounds: Bounds,
+}
+
+impl Args {
+    fn matrix(&self) -> Matrix4<f32> {
+        if let Some(matrix) = self.matrix {
+            return matrix;
+        }
+        let scale = self.scale.unwrap_or_else(|| Vector3::repeat(1.0));
+        let rotation = self
+            .rotate
+            .map(|r| {
+                Rotation3::from_euler_angles(r.x.to_radians(), r.y.to_radians(), r.z.to_radians())
+            })
+            .unwrap_or_else(Rotation3::identity);
+        let translation = self.translate.unwrap_or_else(Vector3::zeros);
+        Matrix4::new_translation(&translation)
+            * rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&scale)
+    }
+}
+
+/// Comma separated numbers, as taken by the options of `transform` and `crop`
+pub(super) fn parse_floats(s: &str) -> Result<Vec<f32>, String> {
+    s.split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f32>()
+                .map_err(|e| format!("`{}`: {}", v, e))
+        })
+        .collect()
+}
+
+fn parse_vector(s: &str) -> Result<Vector3<f32>, String> {
+    match parse_floats(s)?[..] {
+        [x, y, z] => Ok(Vector3::new(x, y, z)),
+        ref values => Err(format!("expected x,y,z, got {} values", values.len())),
+    }
+}
+
+fn parse_scale(s: &str) -> Result<Vector3<f32>, String> {
+    match parse_floats(s)?[..] {
+        [s] => Ok(Vector3::repeat(s)),
+        [x, y, z] => Ok(Vector3::new(x, y, z)),
+        ref values => Err(format!(
+            "expected one or three values, got {}",
+            values.len()
+        )),
+    }
+}
+
+fn parse_matrix(s: &str) -> Result<Matrix4<f32>, String> {
+    let values = parse_floats(s)?;
+    if values.len() != 16 {
+        return Err(format!("expected 16 values, got {}", values.len()));
+    }
+    Ok(Matrix4::from_row_slice(&values))
+}
+
+/// Bounding box of the points seen so far
+#[derive(Debug, Clone, Copy)]
+struct BoundingBox {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+}
+
+impl BoundingBox {
+    fn empty() -> Self {
+        Self {
+            min: Vector3::repeat(f32::INFINITY),
+            max: Vector3::repeat(f32::NEG_INFINITY),
+        }
+    }
+
+    fn add(&mut self, x: f32, y: f32, z: f32) {
+        let p = Vector3::new(x, y, z);
+        self.min = self.min.inf(&p);
+        self.max = self.max.sup(&p);
+    }
+
+    /// Maps the box to [-0.5, 0.5] along its longest side, keeping the proportions
+    fn to_unit_cube(self) -> Matrix4<f32> {
+        if self.min.x > self.max.x {
+            return Matrix4::identity();
+        }
+        let center = (self.min + self.max) / 2.0;
+        let longest = (self.max - self.min).max();
+        // !! a single point is only moved to the origin
+        let scale = if longest > 0.0 { 1.0 / longest } else { 1.0 };
+        Matrix4::new_scaling(scale) * Matrix4::new_translation(&-center)
+    }
+}
+
+/// Points and normals mapped by one affine transform
+struct Affine {
+    matrix: Matrix4<f32>,
+    /// inverse transpose of the linear part, keeps normals perpendicular to the surface.
+    /// `None` if the transform flattens the object, normals are then meaningless
+    normal_matrix: Option<Matrix3<f32>>,
+}
+
+impl Affine {
+    fn new(matrix: Matrix4<f32>) -> Self {
+        let linear = matrix.fixed_view::<3, 3>(0, 0).into_owned();
+        Self {
+            matrix,
+            normal_matrix: linear.try_inverse().map(|inverse| inverse.transpose()),
+        }
+    }
+
+    fn point(&self, x: f32, y: f32, z: f32) -> (f32, f32, f32) {
+        let p = self.matrix.transform_point(&nalgebra::Point3::new(x, y, z));
+        (p.x, p.y, p.z)
+    }
+
+    fn normal(normal_matrix: &Matrix3<f32>, x: f32, y: f32, z: f32) -> (f32, f32, f32) {
+        let n = normal_matrix * Vector3::new(x, y, z);
+        let n = n.try_normalize(f32::EPSILON).unwrap_or(n);
+        (n.x, n.y, n.z)
+    }
+
+    fn apply(&self, pc: &mut PointCloud<PointXyzRgba>) {
+        for p in &mut pc.points {
+            (p.x, p.y, p.z) = self.point(p.x, p.y, p.z);
+        }
+    }
+
+    fn apply_with_normals(&self, pc: &mut PointCloud<PointXyzRgbaNormal>) -> Result<(), String> {
+        let normal_matrix = self
+            .normal_matrix
+            .as_ref()
+            .ok_or("The transform is not invertible, normals cannot be transformed")?;
+        for p in &mut pc.points {
+            (p.x, p.y, p.z) = self.point(p.x, p.y, p.z);
+            (p.nx, p.ny, p.nz) = Self::normal(normal_matrix, p.nx, p.ny, p.nz);
+        }
+        Ok(())
+    }
+}
+
+pub struct Transform {
+    matrix: Matrix4<f32>,
+    normalize: Option<Normalize>,
+    bounds: Bounds,
+    /// frames held back until `End` with `--bounds sequence`
+    held: Vec<PipelineMessage>,
+    sequence_box: BoundingBox,
+}
+
+impl Transform {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        Box::new(Transform {
+            matrix: args.matrix(),
+            normalize: args.normalize,
+            bounds: args.bounds,
+            held: vec![],
+            sequence_box: BoundingBox::empty(),
+        })
+    }
+
+    fn transform(
+        &self,
+        message: PipelineMessage,
+        bounds: BoundingBox,
+    ) -> Result<PipelineMessage, String> {
+        let matrix = match self.normalize {
+            Some(Normalize::UnitCube) => self.matrix * bounds.to_unit_cube(),
+            None => self.matrix,
+        };
+        let affine = Affine::new(matrix);
+        Ok(match message {
+            PipelineMessage::IndexedPointCloud(mut pc, i) => {
+                affine.apply(&mut pc);
+                PipelineMessage::IndexedPointCloud(pc, i)
+            }
+            PipelineMessage::IndexedPointCloudNormal(mut pc, i) => {
+                affine.apply_with_normals(&mut pc)?;
+                PipelineMessage::IndexedPointCloudNormal(pc, i)
+            }
+            message => message,
+        })
+    }
+}
+
+fn bounding_box(message: &PipelineMessage) -> BoundingBox {
+    let mut bounds = BoundingBox::empty();
+    match message {
+        PipelineMessage::IndexedPointCloud(pc, _) => {
+            pc.points.iter().for_each(|p| bounds.add(p.x, p.y, p.z))
+        }
+        PipelineMessage::IndexedPointCloudNormal(pc, _) => {
+            pc.points.iter().for_each(|p| bounds.add(p.x, p.y, p.z))
+        }
+        _ => {}
+    }
+    bounds
+}
+
+impl Subcommand for Transform {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(_, _)
+                | PipelineMessage::IndexedPointCloudNormal(_, _) => {
+                    if self.normalize.is_some() && self.bounds == Bounds::Sequence {
+                        let bounds = bounding_box(&message);
+                        if bounds.min.x <= bounds.max.x {
+                            self.sequence_box
+                                .add(bounds.min.x, bounds.min.y, bounds.min.z);
+                            self.sequence_box
+                                .add(bounds.max.x, bounds.max.y, bounds.max.z);
+                        }
+                        self.held.push(message);
+                    } else {
+                        let bounds = bounding_box(&message);
+                        channel.send(self.transform(message, bounds)?);
+                    }
+                }
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
+                PipelineMessage::End => {
+                    for held in std::mem::take(&mut self.held) {
+                        let index = message_index(&held);
+                        match self.transform(held, self.sequence_box) {
+                            Ok(message) => channel.send(message),
+                            Err(cause) => channel.send_error(index, cause),
+                        }
+                    }
+                    channel.send(message);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::StageError;
+    use approx::assert_relative_eq;
+
+    fn transform(args: &[&str]) -> Transform {
+        let args = Args::parse_from(std::iter::once("transform").chain(args.iter().copied()));
+        Transform {
+            matrix: args.matrix(),
+            normalize: args.normalize,
+            bounds: args.bounds,
+            held: vec![],
+            sequence_box: BoundingBox::empty(),
+        }
+    }
+
+    fn frame(points: &[(f32, f32, f32)], index: u32) -> PipelineMessage {
+        let points = points
+            .iter()
+            .map(|&(x, y, z)| PointXyzRgbaNormal {
+                x,
+                y,
+                z,
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+                nx: 1.0,
+                ny: 0.0,
+                nz: 0.0,
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud {
+            number_of_points: points.len(),
+            points,
+        };
+        PipelineMessage::IndexedPointCloudNormal(pc, index)
+    }
+
+    fn points(message: &PipelineMessage) -> Vec<PointXyzRgbaNormal> {
+        match message {
+            PipelineMessage::IndexedPointCloudNormal(pc, _) => pc.points.clone(),
+            _ => panic!("expected a point cloud with normals"),
+        }
+    }
+
+    #[test]
+    fn test_rotate_and_translate() {
+        let t = transform(&["--rotate", "0,0,90", "--translate", "1,2,3"]);
+        let out = t.transform(frame(&[(1.0, 0.0, 0.0)], 0), BoundingBox::empty());
+        let p = points(&out.unwrap())[0];
+        assert_relative_eq!(p.x, 1.0, epsilon = 1e-5);
+        assert_relative_eq!(p.y, 3.0, epsilon = 1e-5);
+        assert_relative_eq!(p.z, 3.0, epsilon = 1e-5);
+        // !! the normal is rotated but not translated
+        assert_relative_eq!(p.nx, 0.0, epsilon = 1e-5);
+        assert_relative_eq!(p.ny, 1.0, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_non_uniform_scale_keeps_normals_perpendicular() {
+        let t = transform(&["--matrix", "2,0,0,0,0,1,0,0,0,0,1,0,0,0,0,1"]);
+        // !! the plane x + y = 1 with normal (1, 1, 0) becomes x / 2 + y = 1 with normal (1, 2, 0)
+        let mut message = frame(&[(1.0, 0.0, 0.0)], 0);
+        if let PipelineMessage::IndexedPointCloudNormal(pc, _) = &mut message {
+            pc.points[0].nx = 1.0;
+            pc.points[0].ny = 1.0;
+        }
+        let p = points(&t.transform(message, BoundingBox::empty()).unwrap())[0];
+        assert_relative_eq!(p.x, 2.0);
+        assert_relative_eq!(p.ny / p.nx, 2.0, epsilon = 1e-5);
+        assert_relative_eq!(p.nx * p.nx + p.ny * p.ny, 1.0, epsilon = 1e-5);
+    }
+
+    #[test]
+    fn test_normalize_over_sequence() {
+        let (progress_tx, _) = crossbeam_channel::unbounded();
+        let mut channel = Channel::new(progress_tx);
+        let rx = channel.subscribe_unbounded();
+        let mut t = transform(&["--normalize", "unit-cube", "--bounds", "sequence"]);
+        t.handle(
+            vec![frame(&[(0.0, 0.0, 0.0), (512.0, 0.0, 0.0)], 0)],
+            &channel,
+        )
+        .unwrap();
+        t.handle(vec![frame(&[(1024.0, 256.0, 0.0)], 1)], &channel)
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+        t.handle(vec![PipelineMessage::End], &channel).unwrap();
+
+        let first = points(&rx.try_recv().unwrap());
+        assert_relative_eq!(first[0].x, -0.5);
+        assert_relative_eq!(first[1].x, 0.0);
+        assert_relative_eq!(first[0].y, -0.125);
+        let second = points(&rx.try_recv().unwrap());
+        assert_relative_eq!(second[0].x, 0.5);
+        assert_relative_eq!(second[0].y, 0.125);
+        assert!(matches!(rx.try_recv(), Ok(PipelineMessage::End)));
+    }
+
+    #[test]
+    fn test_held_frame_errors_keep_their_index() {
+        let (progress_tx, _) = crossbeam_channel::unbounded();
+        let mut channel = Channel::new(progress_tx);
+        let rx = channel.subscribe_unbounded();
+        let mut t = transform(&[
+            "--matrix",
+            "0,0,0,0,0,1,0,0,0,0,1,0,0,0,0,1",
+            "--normalize",
+            "unit-cube",
+            "--bounds",
+            "sequence",
+        ]);
+        t.handle(
+            vec![frame(&[(1.0, 0.0, 0.0)], 4), PipelineMessage::End],
+            &channel,
+        )
+        .unwrap();
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(PipelineMessage::Error(StageError { index: Some(4), .. }))
+        ));
+    }
+}
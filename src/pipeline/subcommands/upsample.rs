@@ -28,7 +28,7 @@ impl Upsampler {
 }
 
 impl Subcommand for Upsampler {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
         for message in messages {
             match message {
                 PipelineMessage::IndexedPointCloud(pc, i) => {
@@ -37,11 +37,13 @@ impl Subcommand for Upsampler {
                 }
                 PipelineMessage::Metrics(_)
                 | PipelineMessage::IndexedPointCloudNormal(_, _)
+                | PipelineMessage::Error(_)
                 | PipelineMessage::DummyForIncrement => {}
                 PipelineMessage::End => {
                     channel.send(message);
                 }
             };
         }
+        Ok(())
     }
 }
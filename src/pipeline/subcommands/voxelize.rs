@@ -0,0 +1,227 @@
+use clap::Parser;
+use nalgebra::Vector3;
+
+use crate::downsample::voxel::{
+    bounding_box, voxelize, voxelize_with_normals, ColorReduction, Grid,
+};
+use crate::pipeline::{channel::Channel, PipelineMessage};
+
+use super::crop::parse_box;
+use super::transform::Bounds;
+use super::Subcommand;
+
+#[derive(Parser)]
+#[clap(
+    about = "Quantizes the points to a voxel grid, merging the points that fall in the same voxel.",
+    override_usage = format!("\x1B[1m{}\x1B[0m <--bits <BITS>|--voxel-size <VOXEL_SIZE>> [OPTIONS] +input=in +output=out", "voxelize")
+)]
+#[clap(group(clap::ArgGroup::new("grid").required(true).args(["bits", "voxel_size"])))]
+pub struct Args {
+    /// Geometry bit depth: 2^bits voxels along the longest side of the bounding box of each frame,
+    /// the output is in integer voxel coordinates
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..=21))]
+    bits: Option<u32>,
+
+    /// Side of a voxel, the output is at the centers of the voxels
+    #[clap(long, value_parser = parse_voxel_size)]
+    voxel_size: Option<f32>,
+
+    /// Bounding box of the grid of --bits, as min_x,min_y,min_z,max_x,max_y,max_z
+    #[clap(long, requires = "bits", value_parser = parse_box, allow_hyphen_values = true)]
+    bbox: Option<(Vector3<f32>, Vector3<f32>)>,
+
+    /// Bounding box of the grid of --bits when --bbox is not given
+    #[clap(long, value_enum, default_value_t = Bounds::Frame, requires = "bits", conflicts_with = "bbox")]
+    bounds: Bounds,
+
+    /// How the colors of merged points are combined, normals are always averaged
+    #[clap(long, value_enum, default_value_t = ColorReduction::Mean)]
+    color: ColorReduction,
+}
+
+fn parse_voxel_size(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(size) if size > 0.0 && size.is_finite() => Ok(size),
+        Ok(size) => Err(format!("expected a positive voxel size, got {}", size)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub struct Voxelize {
+    grid: Grid,
+    color: ColorReduction,
+    bounds: Bounds,
+    /// frames held back until `End` with `--bounds sequence`
+    held: Vec<PipelineMessage>,
+    sequence_box: [[f32; 3]; 2],
+}
+
+impl Voxelize {
+    pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
+        let args: Args = Args::parse_from(args);
+        let grid = match (args.bits, args.voxel_size, args.bbox) {
+            (Some(bits), _, Some((min, max))) => Grid::BitsInBox(bits, [min.into(), max.into()]),
+            (Some(bits), _, None) => Grid::Bits(bits),
+            (None, Some(size), _) => Grid::Size(size),
+            (None, None, _) => unreachable!("clap requires one of them"),
+        };
+        Box::new(Voxelize {
+            grid,
+            color: args.color,
+            bounds: args.bounds,
+            held: vec![],
+            sequence_box: bounding_box(&[]),
+        })
+    }
+
+    fn holds_frames(&self) -> bool {
+        matches!(self.grid, Grid::Bits(_)) && self.bounds == Bounds::Sequence
+    }
+
+    fn voxelize(&self, message: PipelineMessage, grid: Grid) -> PipelineMessage {
+        match message {
+            PipelineMessage::IndexedPointCloud(pc, i) => {
+                PipelineMessage::IndexedPointCloud(voxelize(&pc, grid, self.color), i)
+            }
+            PipelineMessage::IndexedPointCloudNormal(pc, i) => {
+                PipelineMessage::IndexedPointCloudNormal(
+                    voxelize_with_normals(&pc, grid, self.color),
+                    i,
+                )
+            }
+            message => message,
+        }
+    }
+}
+
+impl Subcommand for Voxelize {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
+        for message in messages {
+            match message {
+                PipelineMessage::IndexedPointCloud(_, _)
+                | PipelineMessage::IndexedPointCloudNormal(_, _) => {
+                    if self.holds_frames() {
+                        let positions = match &message {
+                            PipelineMessage::IndexedPointCloud(pc, _) => {
+                                pc.points.iter().map(|p| [p.x, p.y, p.z]).collect()
+                            }
+                            PipelineMessage::IndexedPointCloudNormal(pc, _) => {
+                                pc.points.iter().map(|p| [p.x, p.y, p.z]).collect()
+                            }
+                            _ => vec![],
+                        };
+                        let [min, max] = bounding_box(&positions);
+                        let [sequence_min, sequence_max] = &mut self.sequence_box;
+                        for i in 0..3 {
+                            sequence_min[i] = sequence_min[i].min(min[i]);
+                            sequence_max[i] = sequence_max[i].max(max[i]);
+                        }
+                        self.held.push(message);
+                    } else {
+                        channel.send(self.voxelize(message, self.grid));
+                    }
+                }
+                PipelineMessage::Metrics(_)
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
+                PipelineMessage::End => {
+                    if let Grid::Bits(bits) = self.grid {
+                        let grid = Grid::BitsInBox(bits, self.sequence_box);
+                        for held in std::mem::take(&mut self.held) {
+                            channel.send(self.voxelize(held, grid));
+                        }
+                    }
+                    channel.send(message);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::{pointxyzrgba::PointXyzRgba, PointCloud};
+
+    fn voxelize(args: &[&str]) -> Box<dyn Subcommand> {
+        let args = std::iter::once("voxelize").chain(args.iter().copied());
+        Voxelize::from_args(args.map(String::from).collect())
+    }
+
+    fn frame(xs: &[f32], i: u32) -> PipelineMessage {
+        let points = xs
+            .iter()
+            .map(|&x| PointXyzRgba {
+                x,
+                y: 0.0,
+                z: 0.0,
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255,
+            })
+            .collect::<Vec<_>>();
+        PipelineMessage::IndexedPointCloud(
+            PointCloud {
+                number_of_points: points.len(),
+                points,
+            },
+            i,
+        )
+    }
+
+    fn xs(message: PipelineMessage) -> Vec<f32> {
+        match message {
+            PipelineMessage::IndexedPointCloud(pc, _) => pc.points.iter().map(|p| p.x).collect(),
+            _ => panic!("expected a point cloud"),
+        }
+    }
+
+    /// The frames sent by a new `voxelize` stage given `args` for `messages`
+    fn run(args: &[&str], messages: Vec<PipelineMessage>) -> Vec<PipelineMessage> {
+        let (progress_tx, _) = crossbeam_channel::unbounded();
+        let mut channel = Channel::new(progress_tx);
+        let rx = channel.subscribe_unbounded();
+        let mut v = voxelize(args);
+        for message in messages {
+            v.handle(vec![message], &channel).unwrap();
+        }
+        rx.try_iter().collect()
+    }
+
+    #[test]
+    fn test_grid_of_the_sequence() {
+        let args = ["--bits", "2", "--bounds", "sequence"];
+        let out = run(&args, vec![frame(&[0.0, 1.0], 0), frame(&[3.0], 1)]);
+        assert!(out.is_empty());
+        let mut out = run(
+            &args,
+            vec![
+                frame(&[0.0, 1.0], 0),
+                frame(&[3.0], 1),
+                PipelineMessage::End,
+            ],
+        )
+        .into_iter();
+        assert_eq!(xs(out.next().unwrap()), vec![0.0, 1.0]);
+        assert_eq!(xs(out.next().unwrap()), vec![3.0]);
+        assert!(matches!(out.next(), Some(PipelineMessage::End)));
+
+        // !! the same frame on its own grid and on a given one
+        let mut out = run(&["--bits", "2"], vec![frame(&[0.0, 1.0], 0)]);
+        assert_eq!(xs(out.remove(0)), vec![0.0, 3.0]);
+        let args = ["--bits", "2", "--bbox", "-3,0,0,3,1,1"];
+        let mut out = run(&args, vec![frame(&[0.0, 1.0], 0)]);
+        assert_eq!(xs(out.remove(0)), vec![2.0]);
+    }
+
+    #[test]
+    fn test_voxel_size_must_be_positive() {
+        for size in ["0", "-1", "inf"] {
+            let args = ["voxelize", "--voxel-size", size];
+            assert!(Args::try_parse_from(args).is_err());
+        }
+        assert!(Args::try_parse_from(["voxelize", "--voxel-size", "0.5"]).is_ok());
+    }
+}
@@ -2,13 +2,21 @@ use cgmath::num_traits::pow;
 use clap::Parser;
 // use log::warn;
 
+use crate::formats::{
+    pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud,
+};
+use crate::las::write_las_file;
 use crate::pcd::{create_pcd, create_pcd_from_pc_normal, write_pcd_file, PCDDataType};
 use crate::pipeline::channel::Channel;
 use crate::pipeline::PipelineMessage;
-use crate::utils::{pcd_to_ply_from_data, pcd_to_ply_from_data_normal, ConvertOutputFormat};
+use crate::utils::{
+    check_las_storage_type, pcd_to_ply_from_data, pcd_to_ply_from_data_normal, ConvertOutputFormat,
+};
+use crate::xyz::write_text_file;
 use std::fs::File;
 use std::path::Path;
 
+use super::read::TextArgs;
 use super::Subcommand;
 
 #[derive(Parser)]
@@ -28,35 +36,68 @@ pub struct Args {
 
     #[clap(long, default_value_t = 5)]
     name_length: usize,
+
+    /// Columns and color range of text files (xyz, csv, pts), --skip-lines is ignored
+    #[clap(flatten)]
+    text: TextArgs,
 }
 pub struct Write {
     args: Args,
     count: u64,
+    /// metrics are numbered on their own, a stream can carry both frames and metrics
+    metrics_count: u64,
 }
 
 impl Write {
     pub fn from_args(args: Vec<String>) -> Box<dyn Subcommand> {
         let args = Args::parse_from(args);
-        std::fs::create_dir_all(Path::new(&args.output_dir))
-            .expect("Failed to create output directory");
-        Box::from(Write { args, count: 0 })
+        Box::from(Write {
+            args,
+            count: 0,
+            metrics_count: 0,
+        })
+    }
+}
+
+/// LAS has no normals
+fn without_normals(pc: &PointCloud<PointXyzRgbaNormal>) -> PointCloud<PointXyzRgba> {
+    let points = pc
+        .points
+        .iter()
+        .map(|p| PointXyzRgba {
+            x: p.x,
+            y: p.y,
+            z: p.z,
+            r: p.r,
+            g: p.g,
+            b: p.b,
+            a: p.a,
+        })
+        .collect::<Vec<_>>();
+    PointCloud {
+        number_of_points: points.len(),
+        points,
     }
 }
 
 impl Subcommand for Write {
-    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) {
+    fn handle(&mut self, messages: Vec<PipelineMessage>, channel: &Channel) -> Result<(), String> {
         println!("Start writing...");
         let output_path = Path::new(&self.args.output_dir);
+        if !output_path.exists() {
+            std::fs::create_dir_all(output_path).map_err(|e| {
+                format!("Failed to create output directory {:?}: {}", output_path, e)
+            })?;
+        }
         let max_count = pow(10, self.args.name_length);
         for message in messages {
-            println!("message: {:?}", message);
             match &message {
                 PipelineMessage::IndexedPointCloud(pc, i) => {
                     // println!("Writing point cloud with point num {}", pc.points.len());
                     let pcd_data_type = self
                         .args
                         .storage_type
-                        .expect("PCD data type should be provided");
+                        .ok_or("PCD data type should be provided")?;
                     let output_format = self.args.output_format.to_string();
 
                     // !! use index(i) instead of count to make sure the order of files
@@ -64,52 +105,53 @@ impl Subcommand for Write {
                     let file_name = format!("{}.{}", padded_count, output_format);
                     self.count += 1;
                     if self.count >= max_count {
-                        channel.send(PipelineMessage::End);
-                        panic!("Too many files, please increase the name length by setting --name-length")
+                        return Err("Too many files, please increase the name length by setting --name-length".to_string());
                     }
 
                     let file_name = Path::new(&file_name);
                     let output_file = output_path.join(file_name);
-                    if !output_path.exists() {
-                        std::fs::create_dir_all(output_path)
-                            .expect("Failed to create output directory");
-                    }
 
                     // use pcd format as a trasition format now
                     let pcd = create_pcd(pc);
 
                     match output_format.as_str() {
                         "pcd" => {
-                            if let Err(e) = write_pcd_file(&pcd, pcd_data_type, &output_file) {
-                                println!("Failed to write {:?}\n{e}", output_file);
-                            }
+                            write_pcd_file(&pcd, pcd_data_type, &output_file)
+                                .map_err(|e| format!("Failed to write {:?}: {e}", output_file))?;
                         }
                         "ply" => {
-                            if let Err(e) = pcd_to_ply_from_data(&output_file, pcd_data_type, pcd) {
-                                println!("Failed to write {:?}\n{e}", output_file);
-                            }
+                            pcd_to_ply_from_data(&output_file, pcd_data_type, pcd)
+                                .map_err(|e| format!("Failed to write {:?}: {e}", output_file))?;
+                        }
+                        "las" => {
+                            check_las_storage_type(pcd_data_type)?;
+                            write_las_file(pc, &output_file)
+                                .map_err(|e| format!("Failed to write {:?}: {e}", output_file))?;
+                        }
+                        "xyz" | "csv" | "pts" => {
+                            write_text_file(pc, &self.args.text.options(), &output_file)
+                                .map_err(|e| format!("Failed to write {:?}: {e}", output_file))?;
                         }
                         _ => {
-                            println!("Unsupported output format {}", output_format);
-                            continue;
+                            return Err(format!("Unsupported output format {}", output_format));
                         }
                     }
                 }
                 PipelineMessage::Metrics(metrics) => {
-                    let file_name = format!("{}.metrics", self.count);
-                    self.count += 1;
+                    let file_name = format!("{}.metrics", self.metrics_count);
+                    self.metrics_count += 1;
                     let file_name = Path::new(&file_name);
                     let output_file = output_path.join(file_name);
-                    File::create(output_file)
+                    File::create(&output_file)
                         .and_then(|mut f| metrics.write_to(&mut f))
-                        .expect("Should be able to create file to write metrics to");
+                        .map_err(|e| format!("Failed to write {:?}: {e}", output_file))?;
                 }
                 PipelineMessage::IndexedPointCloudNormal(pc, i) => {
                     // println!("Writing point cloud with point num {}", pc.points.len());
                     let pcd_data_type = self
                         .args
                         .storage_type
-                        .expect("PCD data type should be provided");
+                        .ok_or("PCD data type should be provided")?;
                     let output_format = self.args.output_format.to_string();
 
                     // !! use index(i) instead of count to make sure the order of files
@@ -117,42 +159,44 @@ impl Subcommand for Write {
                     let file_name = format!("{}.{}", padded_count, output_format);
                     self.count += 1;
                     if self.count >= max_count {
-                        channel.send(PipelineMessage::End);
-                        panic!("Too many files, please increase the name length by setting --name-length")
+                        return Err("Too many files, please increase the name length by setting --name-length".to_string());
                     }
 
                     let file_name = Path::new(&file_name);
                     let output_file = output_path.join(file_name);
-                    if !output_path.exists() {
-                        std::fs::create_dir_all(output_path)
-                            .expect("Failed to create output directory");
-                    }
 
                     // use pcd format as a trasition format now
                     let pcd = create_pcd_from_pc_normal(pc);
 
                     match output_format.as_str() {
                         "pcd" => {
-                            if let Err(e) = write_pcd_file(&pcd, pcd_data_type, &output_file) {
-                                println!("Failed to write {:?}\n{e}", output_file);
-                            }
+                            write_pcd_file(&pcd, pcd_data_type, &output_file)
+                                .map_err(|e| format!("Failed to write {:?}: {e}", output_file))?;
                         }
                         "ply" => {
-                            if let Err(e) =
-                                pcd_to_ply_from_data_normal(&output_file, pcd_data_type, pcd)
-                            {
-                                println!("Failed to write {:?}\n{e}", output_file);
-                            }
+                            pcd_to_ply_from_data_normal(&output_file, pcd_data_type, pcd)
+                                .map_err(|e| format!("Failed to write {:?}: {e}", output_file))?;
+                        }
+                        "las" => {
+                            check_las_storage_type(pcd_data_type)?;
+                            write_las_file(&without_normals(pc), &output_file)
+                                .map_err(|e| format!("Failed to write {:?}: {e}", output_file))?;
+                        }
+                        "xyz" | "csv" | "pts" => {
+                            write_text_file(pc, &self.args.text.options(), &output_file)
+                                .map_err(|e| format!("Failed to write {:?}: {e}", output_file))?;
                         }
                         _ => {
-                            println!("Unsupported output format {}", output_format);
-                            continue;
+                            return Err(format!("Unsupported output format {}", output_format));
                         }
                     }
                 }
-                PipelineMessage::End | PipelineMessage::DummyForIncrement => {}
+                PipelineMessage::End
+                | PipelineMessage::Error(_)
+                | PipelineMessage::DummyForIncrement => {}
             }
             channel.send(message);
         }
+        Ok(())
     }
 }
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+
+use super::{executor::StageSpec, subcommand};
+
+/// Number of input streams a subcommand consumes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    fn accepts(&self, n: usize) -> bool {
+        match *self {
+            Arity::Exactly(expected) => n == expected,
+            Arity::AtLeast(min) => n >= min,
+        }
+    }
+}
+
+impl std::fmt::Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (prefix, n) = match *self {
+            Arity::Exactly(n) => ("exactly", n),
+            Arity::AtLeast(n) => ("at least", n),
+        };
+        write!(f, "{} {} input{}", prefix, n, if n == 1 { "" } else { "s" })
+    }
+}
+
+fn input_arity(command: &str) -> Arity {
+    match command {
+        // !! sources produce their own frames, anything fed to them would be ignored
+        "read" | "convert" | "info" | "dash" => Arity::Exactly(0),
+        "metrics" | "register" | "diff" => Arity::Exactly(2),
+        "write" | "render" | "exec" | "select" | "retime" | "stats" => Arity::Exactly(1),
+        "merge" => Arity::AtLeast(2),
+        _ => Arity::AtLeast(1),
+    }
+}
+
+/// Commands handling every group of frames on its own, which can be run on several workers
+const PER_FRAME_COMMANDS: &[&str] = &[
+    "downsample",
+    "upsample",
+    "normal",
+    "metrics",
+    "exec",
+    "merge",
+    "select",
+    "transform",
+    "crop",
+    "voxelize",
+    "denoise",
+    "sample",
+    "register",
+    "stats",
+    "diff",
+];
+
+/// Stages that only send their frames once the whole input has been read
+fn holds_whole_sequence(spec: &StageSpec) -> bool {
+    matches!(spec.name.as_str(), "transform" | "voxelize")
+        && (spec.args.iter().any(|arg| arg == "--bounds=sequence")
+            || spec
+                .args
+                .windows(2)
+                .any(|w| w[0] == "--bounds" && w[1] == "sequence"))
+}
+
+fn stage_label(idx: usize, spec: &StageSpec) -> String {
+    format!("stage {} ({})", idx, spec.name)
+}
+
+/// Checks the stream graph of a pipeline, reporting every problem found:
+/// unknown commands, unknown or duplicate stream names, wrong number of inputs and cycles.
+pub(crate) fn validate(specs: &[StageSpec]) -> Result<(), String> {
+    let mut problems = vec![];
+
+    // !! map each output stream to the stage producing it, the first one wins for wiring purposes
+    let mut producers: HashMap<&str, usize> = HashMap::new();
+    for (idx, spec) in specs.iter().enumerate() {
+        if spec.output_name.is_empty() {
+            continue;
+        }
+        match producers.get(spec.output_name.as_str()) {
+            Some(&first) => problems.push(format!(
+                "{}: duplicate output stream `{}`, already produced by {}",
+                stage_label(idx, spec),
+                spec.output_name,
+                stage_label(first, &specs[first])
+            )),
+            None => {
+                producers.insert(&spec.output_name, idx);
+            }
+        }
+    }
+
+    let mut existing_outputs = producers
+        .keys()
+        .map(|s| format!("`{}`", s))
+        .collect::<Vec<_>>();
+    existing_outputs.sort();
+    let existing_outputs = if existing_outputs.is_empty() {
+        "none".to_string()
+    } else {
+        existing_outputs.join(", ")
+    };
+
+    for (idx, spec) in specs.iter().enumerate() {
+        // !! custom stages are not in the registry and may take any number of inputs
+        if !spec.custom && subcommand(&spec.name).is_none() {
+            problems.push(format!(
+                "{}: unknown command `{}`",
+                stage_label(idx, spec),
+                spec.name
+            ));
+            continue;
+        }
+
+        for input_name in &spec.input_stream_names {
+            if !producers.contains_key(input_name.as_str()) {
+                problems.push(format!(
+                    "{}: no output stream with name `{}` found, existing outputs are {}",
+                    stage_label(idx, spec),
+                    input_name,
+                    existing_outputs
+                ));
+            }
+        }
+
+        let arity = if spec.custom {
+            Arity::AtLeast(0)
+        } else {
+            input_arity(&spec.name)
+        };
+        let n = spec.input_stream_names.len();
+        if !arity.accepts(n) {
+            let hint = if n == 0 {
+                ", specify it using `+input=input_name`"
+            } else {
+                ""
+            };
+            problems.push(format!(
+                "{}: expects {}, got {}{}",
+                stage_label(idx, spec),
+                arity,
+                n,
+                hint
+            ));
+        }
+
+        if spec.join.is_some() && spec.name == "concat" && !spec.custom {
+            problems.push(format!(
+                "{}: concat reads its inputs one after another, `+join` does not apply",
+                stage_label(idx, spec)
+            ));
+        } else if spec.join.is_some() && n < 2 {
+            problems.push(format!(
+                "{}: `+join` only applies to stages with several inputs, got {}",
+                stage_label(idx, spec),
+                n
+            ));
+        }
+
+        if spec.jobs > 1 && (spec.custom || !PER_FRAME_COMMANDS.contains(&spec.name.as_str())) {
+            problems.push(format!(
+                "{}: `+jobs` is only supported by {}",
+                stage_label(idx, spec),
+                PER_FRAME_COMMANDS.join(", ")
+            ));
+        } else if spec.jobs > 1 && !spec.custom && holds_whole_sequence(spec) {
+            problems.push(format!(
+                "{}: `--bounds sequence` needs every frame in one handler, `+jobs` does not apply",
+                stage_label(idx, spec)
+            ));
+        }
+    }
+
+    // !! edges go from a stage to the stages producing its inputs
+    let dependencies: Vec<Vec<usize>> = specs
+        .iter()
+        .map(|spec| {
+            spec.input_stream_names
+                .iter()
+                .filter_map(|name| producers.get(name.as_str()).copied())
+                .collect()
+        })
+        .collect();
+    for cycle in find_cycles(&dependencies) {
+        let path = cycle
+            .iter()
+            .map(|&idx| stage_label(idx, &specs[idx]))
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        problems.push(format!("cycle between streams: {}", path));
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Invalid pipeline:\n  {}", problems.join("\n  ")))
+    }
+}
+
+/// Depth first search over the dependency graph, returns one path per back edge found,
+/// starting and ending with the same stage
+fn find_cycles(dependencies: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Unvisited,
+        InProgress,
+        Done,
+    }
+
+    let mut state = vec![State::Unvisited; dependencies.len()];
+    let mut cycles = vec![];
+
+    for root in 0..dependencies.len() {
+        if state[root] != State::Unvisited {
+            continue;
+        }
+        // !! stack of (stage, index of the next dependency to visit), also the current path
+        let mut stack = vec![(root, 0)];
+        state[root] = State::InProgress;
+        while let Some((node, next)) = stack.pop() {
+            if next == dependencies[node].len() {
+                state[node] = State::Done;
+                continue;
+            }
+            stack.push((node, next + 1));
+            let dep = dependencies[node][next];
+            match state[dep] {
+                State::Unvisited => {
+                    state[dep] = State::InProgress;
+                    stack.push((dep, 0));
+                }
+                State::InProgress => {
+                    let start = stack
+                        .iter()
+                        .position(|&(n, _)| n == dep)
+                        .expect("stage in progress should be on the stack");
+                    let mut cycle: Vec<usize> = stack[start..].iter().map(|&(n, _)| n).collect();
+                    cycle.push(dep);
+                    cycles.push(cycle);
+                }
+                State::Done => {}
+            }
+        }
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specs(stages: &[&str]) -> Vec<StageSpec> {
+        stages
+            .iter()
+            .map(|stage| StageSpec::parse(stage.split(' ').map(String::from).collect()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_pipeline() {
+        let specs = specs(&[
+            "read ./a +output=a",
+            "read ./b +output=b",
+            "metrics +input=a,b +join=nearest +jobs=4 +output=m",
+            "write ./out +input=m",
+        ]);
+        assert!(validate(&specs).is_ok());
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let specs = specs(&[
+            "read ./a +output=a",
+            "read ./b +output=a",
+            "write ./out +input=typo",
+            "metrics +input=a",
+            "downsample -p 2",
+            "upsample -f 2 +input=a +join=drop",
+            "write ./out +input=a +jobs=2",
+            "transform --normalize unit-cube --bounds sequence +input=a +jobs=2",
+            "voxelize --bits 10 --bounds=sequence +input=a +jobs=2",
+        ]);
+        let err = validate(&specs).unwrap_err();
+        assert!(err.contains(
+            "stage 1 (read): duplicate output stream `a`, already produced by stage 0 (read)"
+        ));
+        assert!(err.contains("stage 2 (write): no output stream with name `typo` found"));
+        assert!(err.contains("stage 3 (metrics): expects exactly 2 inputs, got 1"));
+        assert!(err.contains("stage 4 (downsample): expects at least 1 input, got 0"));
+        assert!(
+            err.contains("stage 5 (upsample): `+join` only applies to stages with several inputs")
+        );
+        assert!(err.contains("stage 7 (transform): `--bounds sequence` needs every frame"));
+        assert!(err.contains("stage 8 (voxelize): `--bounds sequence` needs every frame"));
+    }
+
+    #[test]
+    fn test_source_with_input() {
+        let specs = specs(&["read ./a +output=a", "read ./b +input=a +output=b"]);
+        let err = validate(&specs).unwrap_err();
+        assert!(err.contains("stage 1 (read): expects exactly 0 inputs, got 1"));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let specs = specs(&[
+            "read ./a +output=a",
+            "upsample -f 2 +input=c +output=b",
+            "downsample -p 2 +input=b +output=c",
+        ]);
+        let err = validate(&specs).unwrap_err();
+        assert!(err.contains("cycle between streams: stage 1 (upsample) -> stage 2 (downsample) -> stage 1 (upsample)"));
+    }
+
+    #[test]
+    fn test_find_cycles() {
+        assert!(find_cycles(&[vec![], vec![0], vec![0, 1]]).is_empty());
+        assert_eq!(find_cycles(&[vec![0]]), vec![vec![0, 0]]);
+    }
+}
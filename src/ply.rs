@@ -1,82 +1,647 @@
 use std::path::Path;
 
-use ply_rs::ply::Property;
+use ply_rs::ply::{
+    DefaultElement, ElementDef, Encoding, Header, Ply, Property, PropertyAccess, PropertyDef,
+    PropertyType, ScalarType,
+};
 
-use ply_rs::ply::Header;
+use crate::formats::{
+    pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud,
+};
+use crate::pcd::{PCDField, PCDFieldDataType, PCDHeader, PointCloudData};
 
-use crate::formats::{pointxyzrgba::PointXyzRgba, PointCloud};
+/// Vertex properties read into the fields of the points, the other ones are kept by
+/// [`extra_properties`]
+const POSITION: [&str; 3] = ["x", "y", "z"];
+const COLOR: [&str; 4] = ["red", "green", "blue", "alpha"];
+const NORMAL: [&str; 3] = ["nx", "ny", "nz"];
 
 pub fn read_ply_header<P: AsRef<Path>>(path_buf: P) -> Result<Header, String> {
-    let vertex_parser = ply_rs::parser::Parser::<PointXyzRgba>::new();
+    let vertex_parser = ply_rs::parser::Parser::<DefaultElement>::new();
     let f = std::fs::File::open(path_buf.as_ref())
-        .expect(&format!("Unable to open file {:?}", path_buf.as_ref()));
+        .map_err(|e| format!("Unable to open file {:?}: {e}", path_buf.as_ref()))?;
     let mut f = std::io::BufReader::new(f);
 
-    let header = vertex_parser.read_header(&mut f).expect(&format!(
-        "Failed to read header for ply file {:?}",
-        path_buf.as_ref()
-    ));
+    let header = vertex_parser.read_header(&mut f).map_err(|e| {
+        format!(
+            "Failed to read header for ply file {:?}: {e}",
+            path_buf.as_ref()
+        )
+    })?;
 
     Ok(header)
 }
 
-pub fn read_ply<P: AsRef<Path>>(path_buf: P) -> Option<PointCloud<PointXyzRgba>> {
-    let vertex_parser = ply_rs::parser::Parser::<PointXyzRgba>::new();
+/// Reads every element of the file with all their properties, for conversions that keep them.
+/// [read_ply_vertices] is faster when only the points are needed
+pub fn read_ply_file<P: AsRef<Path>>(path_buf: P) -> Result<Ply<DefaultElement>, String> {
+    let parser = ply_rs::parser::Parser::<DefaultElement>::new();
     let f = std::fs::File::open(path_buf.as_ref())
-        .unwrap_or_else(|_| panic!("Unable to open file {:?}", path_buf.as_ref()));
+        .map_err(|e| format!("Unable to open file {:?}: {e}", path_buf.as_ref()))?;
     let mut f = std::io::BufReader::new(f);
+    parser
+        .read_ply(&mut f)
+        .map_err(|e| format!("Failed to read ply file {:?}: {e}", path_buf.as_ref()))
+}
+
+/// Points read straight from the vertices of a ply file by [read_ply_vertices]
+pub trait PlyPoint: PropertyAccess {
+    /// Vertex properties the points cannot do without
+    const REQUIRED: &'static [&'static str];
+}
+
+impl PlyPoint for PointXyzRgba {
+    const REQUIRED: &'static [&'static str] = &POSITION;
+}
+
+impl PlyPoint for PointXyzRgbaNormal {
+    const REQUIRED: &'static [&'static str] = &["x", "y", "z", "nx", "ny", "nz"];
+}
 
+/// Reads the vertices of the file into points, converted as [PointCloud::try_from] does.
+/// The other elements and vertex properties are skipped, [read_ply_file] keeps them
+pub fn read_ply_vertices<T: PlyPoint, P: AsRef<Path>>(
+    path_buf: P,
+) -> Result<PointCloud<T>, String> {
+    let path = path_buf.as_ref();
+    let vertex_parser = ply_rs::parser::Parser::<T>::new();
+    let f =
+        std::fs::File::open(path).map_err(|e| format!("Unable to open file {:?}: {e}", path))?;
+    let mut f = std::io::BufReader::new(f);
     let header = vertex_parser
         .read_header(&mut f)
-        .unwrap_or_else(|_| panic!("Failed to read header for ply file {:?}", path_buf.as_ref()));
-
-    let mut vertex_list = Vec::new();
-    for (_, element) in &header.elements {
-        if element.name.as_str() == "vertex" {
-            vertex_list = match vertex_parser.read_payload_for_element(&mut f, element, &header) {
-                Ok(v) => v,
-                Err(e) => {
-                    println!("Failed to convert {:?}\n{e}", path_buf.as_ref());
-                    return None;
-                }
-            }
+        .map_err(|e| format!("Failed to read header for ply file {:?}: {e}", path))?;
+
+    let vertex = header
+        .elements
+        .get("vertex")
+        .ok_or_else(|| "The ply file has no vertex element".to_string())?;
+    let missing = T::REQUIRED.iter().find(|name| {
+        !matches!(
+            vertex.properties.get(**name),
+            Some(PropertyDef {
+                data_type: PropertyType::Scalar(_),
+                ..
+            })
+        )
+    });
+    if let Some(name) = missing {
+        return Err(format!("Missing scalar vertex property {name}"));
+    }
+
+    // !! the elements are stored one after the other, those before the vertices are read to skip them
+    let element_parser = ply_rs::parser::Parser::<DefaultElement>::new();
+    for (name, element) in &header.elements {
+        if name == "vertex" {
+            let points = vertex_parser
+                .read_payload_for_element(&mut f, element, &header)
+                .map_err(|e| format!("Failed to read ply file {:?}: {e}", path))?;
+            return Ok(PointCloud {
+                number_of_points: points.len(),
+                points,
+            });
+        }
+        element_parser
+            .read_payload_for_element(&mut f, element, &header)
+            .map_err(|e| format!("Failed to read ply file {:?}: {e}", path))?;
+    }
+    unreachable!("the vertex element is in the header")
+}
+
+pub fn read_ply<P: AsRef<Path>>(path_buf: P) -> Option<PointCloud<PointXyzRgba>> {
+    match read_ply_vertices(path_buf.as_ref()) {
+        Ok(pc) => Some(pc),
+        Err(e) => {
+            println!("Failed to convert {:?}\n{e}", path_buf.as_ref());
+            None
+        }
+    }
+}
+
+pub fn read_ply_normal<P: AsRef<Path>>(path_buf: P) -> Option<PointCloud<PointXyzRgbaNormal>> {
+    match read_ply_vertices(path_buf.as_ref()) {
+        Ok(pc) => Some(pc),
+        Err(e) => {
+            println!("Failed to convert {:?}\n{e}", path_buf.as_ref());
+            None
         }
     }
-    Some(PointCloud {
-        number_of_points: vertex_list.len(),
-        points: vertex_list,
+}
+
+/// Whether the vertices of the file have `nx ny nz` normals
+pub fn has_normals(header: &Header) -> bool {
+    match header.elements.get("vertex") {
+        Some(vertex) => NORMAL
+            .iter()
+            .all(|name| vertex.properties.contains_key(*name)),
+        None => false,
+    }
+}
+
+fn vertices(ply: &Ply<DefaultElement>) -> Result<&Vec<DefaultElement>, String> {
+    ply.payload
+        .get("vertex")
+        .ok_or_else(|| "The ply file has no vertex element".to_string())
+}
+
+fn scalar(property: &Property) -> Option<f64> {
+    match *property {
+        Property::Char(v) => Some(v as f64),
+        Property::UChar(v) => Some(v as f64),
+        Property::Short(v) => Some(v as f64),
+        Property::UShort(v) => Some(v as f64),
+        Property::Int(v) => Some(v as f64),
+        Property::UInt(v) => Some(v as f64),
+        Property::Float(v) => Some(v as f64),
+        Property::Double(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// The property converted to f32, whatever its scalar type
+fn read_f32(vertex: &DefaultElement, name: &str) -> Result<f32, String> {
+    vertex
+        .get(name)
+        .and_then(scalar)
+        .map(|v| v as f32)
+        .ok_or_else(|| format!("Missing scalar vertex property {name}"))
+}
+
+/// Integer colors are clamped to 0..255, float colors are scaled from 0..1
+fn channel(property: &Property) -> Option<u8> {
+    match *property {
+        Property::UChar(v) => Some(v),
+        Property::Float(v) => Some((v * 255.0).round().clamp(0.0, 255.0) as u8),
+        Property::Double(v) => Some((v * 255.0).round().clamp(0.0, 255.0) as u8),
+        ref property => scalar(property).map(|v| v.clamp(0.0, 255.0) as u8),
+    }
+}
+
+fn read_channel(vertex: &DefaultElement, name: &str, default: u8) -> u8 {
+    vertex.get(name).and_then(channel).unwrap_or(default)
+}
+
+fn read_point(vertex: &DefaultElement) -> Result<PointXyzRgba, String> {
+    Ok(PointXyzRgba {
+        x: read_f32(vertex, POSITION[0])?,
+        y: read_f32(vertex, POSITION[1])?,
+        z: read_f32(vertex, POSITION[2])?,
+        r: read_channel(vertex, COLOR[0], 255),
+        g: read_channel(vertex, COLOR[1], 255),
+        b: read_channel(vertex, COLOR[2], 255),
+        a: read_channel(vertex, COLOR[3], 255),
     })
 }
 
-impl ply_rs::ply::PropertyAccess for PointXyzRgba {
+/// Same conversions as [read_point], without building a [DefaultElement] for every vertex
+impl PropertyAccess for PointXyzRgba {
+    fn new() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            r: 255,
+            g: 255,
+            b: 255,
+            a: 255,
+        }
+    }
+
+    fn set_property(&mut self, key: &String, property: Property) {
+        match key.as_str() {
+            "x" => self.x = scalar(&property).map_or(self.x, |v| v as f32),
+            "y" => self.y = scalar(&property).map_or(self.y, |v| v as f32),
+            "z" => self.z = scalar(&property).map_or(self.z, |v| v as f32),
+            "red" => self.r = channel(&property).unwrap_or(self.r),
+            "green" => self.g = channel(&property).unwrap_or(self.g),
+            "blue" => self.b = channel(&property).unwrap_or(self.b),
+            "alpha" => self.a = channel(&property).unwrap_or(self.a),
+            _ => {}
+        }
+    }
+}
+
+impl PropertyAccess for PointXyzRgbaNormal {
     fn new() -> Self {
         Self {
             x: 0.0,
             y: 0.0,
             z: 0.0,
-            r: 0,
-            g: 0,
-            b: 0,
+            r: 255,
+            g: 255,
+            b: 255,
             a: 255,
+            nx: 0.0,
+            ny: 0.0,
+            nz: 0.0,
         }
     }
 
     fn set_property(&mut self, key: &String, property: Property) {
-        match (key.as_ref(), property) {
-            ("x", Property::Double(v)) => self.x = v as f32,
-            ("y", Property::Double(v)) => self.y = v as f32,
-            ("z", Property::Double(v)) => self.z = v as f32,
-            ("x", Property::UInt(v)) => self.x = v as f32,
-            ("y", Property::UInt(v)) => self.y = v as f32,
-            ("z", Property::UInt(v)) => self.z = v as f32,
-            ("x", Property::Float(v)) => self.x = v,
-            ("y", Property::Float(v)) => self.y = v,
-            ("z", Property::Float(v)) => self.z = v,
-            ("red", Property::UChar(v)) => self.r = v,
-            ("green", Property::UChar(v)) => self.g = v,
-            ("blue", Property::UChar(v)) => self.b = v,
-            ("alpha", Property::UChar(v)) => self.a = v,
+        match key.as_str() {
+            "x" => self.x = scalar(&property).map_or(self.x, |v| v as f32),
+            "y" => self.y = scalar(&property).map_or(self.y, |v| v as f32),
+            "z" => self.z = scalar(&property).map_or(self.z, |v| v as f32),
+            "red" => self.r = channel(&property).unwrap_or(self.r),
+            "green" => self.g = channel(&property).unwrap_or(self.g),
+            "blue" => self.b = channel(&property).unwrap_or(self.b),
+            "alpha" => self.a = channel(&property).unwrap_or(self.a),
+            "nx" => self.nx = scalar(&property).map_or(self.nx, |v| v as f32),
+            "ny" => self.ny = scalar(&property).map_or(self.ny, |v| v as f32),
+            "nz" => self.nz = scalar(&property).map_or(self.nz, |v| v as f32),
             _ => {}
         }
     }
 }
+
+impl TryFrom<&Ply<DefaultElement>> for PointCloud<PointXyzRgba> {
+    type Error = String;
+
+    /// Positions can be of any scalar type, colors are `uchar` or `float` and `double` from 0 to 1.
+    /// Vertices without colors are white.
+    fn try_from(ply: &Ply<DefaultElement>) -> Result<Self, Self::Error> {
+        let points = vertices(ply)?
+            .iter()
+            .map(read_point)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            number_of_points: points.len(),
+            points,
+        })
+    }
+}
+
+impl TryFrom<&Ply<DefaultElement>> for PointCloud<PointXyzRgbaNormal> {
+    type Error = String;
+
+    /// Same as for [PointXyzRgba], with the normals in `nx ny nz`
+    fn try_from(ply: &Ply<DefaultElement>) -> Result<Self, Self::Error> {
+        let points = vertices(ply)?
+            .iter()
+            .map(|vertex| {
+                let p = read_point(vertex)?;
+                Ok(PointXyzRgbaNormal {
+                    x: p.x,
+                    y: p.y,
+                    z: p.z,
+                    r: p.r,
+                    g: p.g,
+                    b: p.b,
+                    a: p.a,
+                    nx: read_f32(vertex, NORMAL[0])?,
+                    ny: read_f32(vertex, NORMAL[1])?,
+                    nz: read_f32(vertex, NORMAL[2])?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self {
+            number_of_points: points.len(),
+            points,
+        })
+    }
+}
+
+/// Points written as the vertices of a ply file
+pub trait PlyVertex {
+    fn property_defs() -> Vec<PropertyDef>;
+
+    fn to_element(&self) -> DefaultElement;
+}
+
+fn float_def(name: &str) -> PropertyDef {
+    PropertyDef::new(name.to_string(), PropertyType::Scalar(ScalarType::Float))
+}
+
+fn uchar_def(name: &str) -> PropertyDef {
+    PropertyDef::new(name.to_string(), PropertyType::Scalar(ScalarType::UChar))
+}
+
+impl PlyVertex for PointXyzRgba {
+    fn property_defs() -> Vec<PropertyDef> {
+        vec![
+            float_def("x"),
+            float_def("y"),
+            float_def("z"),
+            uchar_def("red"),
+            uchar_def("green"),
+            uchar_def("blue"),
+        ]
+    }
+
+    fn to_element(&self) -> DefaultElement {
+        let mut element = DefaultElement::new();
+        element.insert("x".to_string(), Property::Float(self.x));
+        element.insert("y".to_string(), Property::Float(self.y));
+        element.insert("z".to_string(), Property::Float(self.z));
+        element.insert("red".to_string(), Property::UChar(self.r));
+        element.insert("green".to_string(), Property::UChar(self.g));
+        element.insert("blue".to_string(), Property::UChar(self.b));
+        element
+    }
+}
+
+impl PlyVertex for PointXyzRgbaNormal {
+    fn property_defs() -> Vec<PropertyDef> {
+        let mut defs = PointXyzRgba::property_defs();
+        defs.extend([float_def("nx"), float_def("ny"), float_def("nz")]);
+        defs
+    }
+
+    fn to_element(&self) -> DefaultElement {
+        let mut element = PointXyzRgba {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            r: self.r,
+            g: self.g,
+            b: self.b,
+            a: self.a,
+        }
+        .to_element();
+        element.insert("nx".to_string(), Property::Float(self.nx));
+        element.insert("ny".to_string(), Property::Float(self.ny));
+        element.insert("nz".to_string(), Property::Float(self.nz));
+        element
+    }
+}
+
+/// A ply file with one vertex per point
+pub fn create_ply<T: PlyVertex>(pc: &PointCloud<T>, encoding: Encoding) -> Ply<DefaultElement> {
+    let mut element = ElementDef::new("vertex".to_string());
+    for def in T::property_defs() {
+        element.properties.insert(def.name.clone(), def);
+    }
+    element.count = pc.points.len();
+
+    let mut ply = Ply::<DefaultElement>::new();
+    ply.header.encoding = encoding;
+    ply.header.elements.insert("vertex".to_string(), element);
+    ply.payload.insert(
+        "vertex".to_string(),
+        pc.points.iter().map(PlyVertex::to_element).collect(),
+    );
+    ply
+}
+
+/// Scalar vertex properties that are not positions, colors or normals, with their type
+pub fn extra_properties(header: &Header) -> Vec<(String, ScalarType)> {
+    let Some(vertex) = header.elements.get("vertex") else {
+        return vec![];
+    };
+    vertex
+        .properties
+        .values()
+        .filter(|def| {
+            let name = def.name.as_str();
+            !POSITION.contains(&name) && !COLOR.contains(&name) && !NORMAL.contains(&name)
+        })
+        .filter_map(|def| match &def.data_type {
+            PropertyType::Scalar(scalar_type) => Some((def.name.clone(), scalar_type.clone())),
+            PropertyType::List(..) => None,
+        })
+        .collect()
+}
+
+fn pcd_data_type(scalar_type: &ScalarType) -> PCDFieldDataType {
+    match scalar_type {
+        ScalarType::Char => PCDFieldDataType::I8,
+        ScalarType::UChar => PCDFieldDataType::U8,
+        ScalarType::Short => PCDFieldDataType::I16,
+        ScalarType::UShort => PCDFieldDataType::U16,
+        ScalarType::Int => PCDFieldDataType::I32,
+        ScalarType::UInt => PCDFieldDataType::U32,
+        ScalarType::Float => PCDFieldDataType::F32,
+        ScalarType::Double => PCDFieldDataType::F64,
+    }
+}
+
+/// Native endian bytes of the property, converted to the declared type of the field
+fn write_scalar(bytes: &mut Vec<u8>, property: Option<&Property>, data_type: PCDFieldDataType) {
+    let v = property.and_then(scalar).unwrap_or(0.0);
+    match data_type {
+        PCDFieldDataType::I8 => bytes.extend_from_slice(&(v as i8).to_ne_bytes()),
+        PCDFieldDataType::U8 => bytes.extend_from_slice(&(v as u8).to_ne_bytes()),
+        PCDFieldDataType::I16 => bytes.extend_from_slice(&(v as i16).to_ne_bytes()),
+        PCDFieldDataType::U16 => bytes.extend_from_slice(&(v as u16).to_ne_bytes()),
+        PCDFieldDataType::I32 => bytes.extend_from_slice(&(v as i32).to_ne_bytes()),
+        PCDFieldDataType::U32 => bytes.extend_from_slice(&(v as u32).to_ne_bytes()),
+        PCDFieldDataType::F32 => bytes.extend_from_slice(&(v as f32).to_ne_bytes()),
+        PCDFieldDataType::F64 => bytes.extend_from_slice(&v.to_ne_bytes()),
+    }
+}
+
+/// Appends the [`extra_properties`] of the vertices of `ply` to the points of `pcd` as fields
+/// of the same type. `pcd` must have one point per vertex, in the same order.
+pub fn append_extra_fields(
+    pcd: PointCloudData,
+    ply: &Ply<DefaultElement>,
+) -> Result<PointCloudData, String> {
+    let extras = extra_properties(&ply.header);
+    if extras.is_empty() {
+        return Ok(pcd);
+    }
+    let vertices = vertices(ply)?;
+    let header = pcd.header();
+    let points = header.points() as usize;
+    if points != vertices.len() {
+        return Err(format!(
+            "Expected {} vertices, got {}",
+            points,
+            vertices.len()
+        ));
+    }
+
+    let extras = extras
+        .into_iter()
+        .map(|(name, scalar_type)| {
+            let data_type = pcd_data_type(&scalar_type);
+            PCDField::new(name.clone(), data_type.into(), data_type.into(), 1)
+                .map(|field| (name, data_type, field))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let point_size = pcd.data().len() / points.max(1);
+    let extra_size = extras
+        .iter()
+        .map(|(_, _, field)| field.size() as usize)
+        .sum::<usize>();
+    let mut data = Vec::with_capacity(points * (point_size + extra_size));
+    for (point, vertex) in pcd.data().chunks_exact(point_size.max(1)).zip(vertices) {
+        data.extend_from_slice(point);
+        for (name, data_type, _) in &extras {
+            write_scalar(&mut data, vertex.get(name), *data_type);
+        }
+    }
+
+    let mut fields = header.fields().clone();
+    fields.extend(extras.into_iter().map(|(_, _, field)| field));
+    let header = PCDHeader::new(
+        header.version(),
+        fields,
+        header.width(),
+        header.height(),
+        *header.viewpoint(),
+        header.points(),
+        header.data_type(),
+    )?;
+    PointCloudData::new(header, data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pcd::create_pcd;
+
+    fn vertex(properties: Vec<(&str, Property)>) -> DefaultElement {
+        let mut element = DefaultElement::new();
+        for (name, property) in properties {
+            element.insert(name.to_string(), property);
+        }
+        element
+    }
+
+    fn ply_of(defs: Vec<(&str, ScalarType)>, vertices: Vec<DefaultElement>) -> Ply<DefaultElement> {
+        let mut element = ElementDef::new("vertex".to_string());
+        for (name, scalar_type) in defs {
+            element.properties.insert(
+                name.to_string(),
+                PropertyDef::new(name.to_string(), PropertyType::Scalar(scalar_type)),
+            );
+        }
+        element.count = vertices.len();
+        let mut ply = Ply::<DefaultElement>::new();
+        ply.header.elements.insert("vertex".to_string(), element);
+        ply.payload.insert("vertex".to_string(), vertices);
+        ply
+    }
+
+    #[test]
+    fn test_float_colors_and_normals() {
+        let ply = ply_of(
+            vec![
+                ("x", ScalarType::Double),
+                ("y", ScalarType::Double),
+                ("z", ScalarType::Int),
+                ("red", ScalarType::Float),
+                ("green", ScalarType::Float),
+                ("blue", ScalarType::Float),
+                ("nx", ScalarType::Float),
+                ("ny", ScalarType::Float),
+                ("nz", ScalarType::Float),
+            ],
+            vec![vertex(vec![
+                ("x", Property::Double(1.5)),
+                ("y", Property::Double(-2.0)),
+                ("z", Property::Int(3)),
+                ("red", Property::Float(1.0)),
+                ("green", Property::Float(0.5)),
+                ("blue", Property::Float(0.0)),
+                ("nx", Property::Float(0.0)),
+                ("ny", Property::Float(1.0)),
+                ("nz", Property::Float(0.0)),
+            ])],
+        );
+        assert!(has_normals(&ply.header));
+
+        let pc = PointCloud::<PointXyzRgbaNormal>::try_from(&ply).unwrap();
+        let p = &pc.points[0];
+        assert_eq!([p.x, p.y, p.z], [1.5, -2.0, 3.0]);
+        assert_eq!([p.r, p.g, p.b, p.a], [255, 128, 0, 255]);
+        assert_eq!([p.nx, p.ny, p.nz], [0.0, 1.0, 0.0]);
+
+        // !! written and converted back without losing the normals
+        let written = create_ply(&pc, Encoding::Ascii);
+        assert!(has_normals(&written.header));
+        let read = PointCloud::<PointXyzRgbaNormal>::try_from(&written).unwrap();
+        assert_eq!(read.points, pc.points);
+    }
+
+    #[test]
+    fn test_property_access_matches_default_elements() {
+        let properties = vec![
+            ("x", Property::Double(1.5)),
+            ("y", Property::UInt(2)),
+            ("z", Property::Short(-3)),
+            ("red", Property::Double(0.5)),
+            ("green", Property::UShort(300)),
+            ("alpha", Property::UChar(7)),
+            ("nx", Property::Float(1.0)),
+            ("ny", Property::Int(0)),
+            ("nz", Property::Float(0.0)),
+            ("intensity", Property::Float(9.0)),
+        ];
+        let element = vertex(properties.clone());
+        let mut point = <PointXyzRgbaNormal as PropertyAccess>::new();
+        for (name, property) in properties {
+            point.set_property(&name.to_string(), property);
+        }
+        let expected = read_point(&element).unwrap();
+        assert_eq!(
+            [point.x, point.y, point.z],
+            [expected.x, expected.y, expected.z]
+        );
+        assert_eq!(
+            [point.r, point.g, point.b, point.a],
+            [expected.r, expected.g, expected.b, expected.a]
+        );
+        assert_eq!([point.r, point.g, point.b, point.a], [128, 255, 255, 7]);
+        assert_eq!([point.nx, point.ny, point.nz], [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_missing_position() {
+        let ply = ply_of(
+            vec![("x", ScalarType::Float), ("y", ScalarType::Float)],
+            vec![vertex(vec![
+                ("x", Property::Float(0.0)),
+                ("y", Property::Float(0.0)),
+            ])],
+        );
+        assert!(!has_normals(&ply.header));
+        let err = PointCloud::<PointXyzRgba>::try_from(&ply).unwrap_err();
+        assert_eq!(err, "Missing scalar vertex property z");
+    }
+
+    #[test]
+    fn test_extra_fields() {
+        let ply = ply_of(
+            vec![
+                ("x", ScalarType::Float),
+                ("y", ScalarType::Float),
+                ("z", ScalarType::Float),
+                ("intensity", ScalarType::UShort),
+                ("time", ScalarType::Double),
+            ],
+            vec![
+                vertex(vec![
+                    ("x", Property::Float(0.0)),
+                    ("y", Property::Float(0.0)),
+                    ("z", Property::Float(0.0)),
+                    ("intensity", Property::UShort(300)),
+                    ("time", Property::Double(0.25)),
+                ]),
+                vertex(vec![
+                    ("x", Property::Float(1.0)),
+                    ("y", Property::Float(1.0)),
+                    ("z", Property::Float(1.0)),
+                    ("intensity", Property::UShort(7)),
+                    ("time", Property::Double(0.5)),
+                ]),
+            ],
+        );
+        let pc = PointCloud::<PointXyzRgba>::try_from(&ply).unwrap();
+        let pcd = append_extra_fields(create_pcd(&pc), &ply).unwrap();
+
+        let names = pcd
+            .header()
+            .fields()
+            .iter()
+            .map(|f| f.name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["x", "y", "z", "rgba", "intensity", "time"]);
+        // !! 16 bytes of position and color, then 2 of intensity and 8 of time
+        assert_eq!(pcd.data().len(), 2 * 26);
+        assert_eq!(pcd.data()[16..18], 300u16.to_ne_bytes());
+        assert_eq!(pcd.data()[44..52], 0.5f64.to_ne_bytes());
+    }
+}
@@ -0,0 +1,309 @@
+use kiddo::distance::squared_euclidean;
+use nalgebra::{Matrix3, Matrix4, Matrix6, Rotation3, Translation3, Vector3, Vector6};
+use rayon::prelude::*;
+
+use crate::metrics::{build_tree, is_finite};
+
+/// What ICP minimizes at every iteration
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IcpMethod {
+    /// Distance between matched points
+    #[default]
+    PointToPoint,
+    /// Distance from each point to the tangent plane of its match, needs the normals of the target
+    PointToPlane,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IcpOptions {
+    pub method: IcpMethod,
+    pub max_iterations: usize,
+    /// stops once the RMS error improves by less than this
+    pub tolerance: f64,
+    /// pairs further apart than this are not used, all pairs are used if `None`
+    pub max_distance: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IcpResult {
+    /// maps the source onto the target
+    pub transform: Matrix4<f64>,
+    /// root mean square distance from the transformed source points to their nearest target points
+    pub rms: f64,
+    pub iterations: usize,
+}
+
+/// Rigid transform aligning `source` to `target` with iterative closest points.
+/// `target_normals` are required by `IcpMethod::PointToPlane`.
+/// Points with a NaN or infinite coordinate are ignored.
+pub fn icp(
+    target: &[[f32; 3]],
+    target_normals: Option<&[[f32; 3]]>,
+    source: &[[f32; 3]],
+    options: IcpOptions,
+) -> Result<IcpResult, String> {
+    if target.is_empty() || source.is_empty() {
+        return Err("Cannot register an empty point cloud".to_string());
+    }
+    let normals = match (options.method, target_normals) {
+        (IcpMethod::PointToPlane, None) => {
+            return Err("Point-to-plane ICP needs the normals of the first input".to_string())
+        }
+        (IcpMethod::PointToPlane, Some(normals)) => Some(normals),
+        (IcpMethod::PointToPoint, _) => None,
+    };
+
+    let tree = build_tree(target.iter().copied());
+    if tree.size() == 0 {
+        return Err("The first input has no point with finite coordinates".to_string());
+    }
+    let mut rotation = Matrix3::<f64>::identity();
+    let mut translation = Vector3::<f64>::zeros();
+    let mut best: Option<IcpResult> = None;
+    let mut iterations = 0;
+    let max_distance_squared = options.max_distance.map(|d| d * d);
+
+    loop {
+        // !! pairs of (transformed source point, index of the nearest target point)
+        let pairs = source
+            .par_iter()
+            .filter(|p| is_finite(p))
+            .filter_map(|p| {
+                let p = rotation * to_vector(p) + translation;
+                let (distance, &nearest) = tree
+                    .nearest_one(&[p.x as f32, p.y as f32, p.z as f32], &squared_euclidean)
+                    .ok()?;
+                match max_distance_squared {
+                    Some(max) if distance as f64 > max => None,
+                    _ => Some((p, nearest)),
+                }
+            })
+            .collect::<Vec<_>>();
+        if pairs.len() < 3 {
+            return Err(format!(
+                "Only {} pairs of points within the maximum distance, cannot register",
+                pairs.len()
+            ));
+        }
+        let rms = (pairs
+            .iter()
+            .map(|(p, i)| (to_vector(&target[*i]) - p).norm_squared())
+            .sum::<f64>()
+            / pairs.len() as f64)
+            .sqrt();
+
+        // !! a step may make the error worse, e.g. when `max_distance` changes the pairs:
+        // the best transform so far is kept and a worse one is taken as divergence
+        let improvement = best.as_ref().map_or(f64::INFINITY, |best| best.rms - rms);
+        if improvement > 0.0 {
+            best = Some(IcpResult {
+                transform: to_homogeneous(&rotation, &translation),
+                rms,
+                iterations,
+            });
+        }
+        if iterations == options.max_iterations || improvement < options.tolerance {
+            return Ok(best.expect("the first iteration is always the best so far"));
+        }
+        iterations += 1;
+
+        let (step_rotation, step_translation) = match normals {
+            None => point_to_point_step(&pairs, target),
+            Some(normals) => point_to_plane_step(&pairs, target, normals)?,
+        };
+        rotation = step_rotation * rotation;
+        translation = step_rotation * translation + step_translation;
+    }
+}
+
+fn to_vector(p: &[f32; 3]) -> Vector3<f64> {
+    Vector3::new(p[0] as f64, p[1] as f64, p[2] as f64)
+}
+
+fn to_homogeneous(rotation: &Matrix3<f64>, translation: &Vector3<f64>) -> Matrix4<f64> {
+    let mut transform = rotation.to_homogeneous();
+    transform
+        .fixed_view_mut::<3, 1>(0, 3)
+        .copy_from(translation);
+    transform
+}
+
+/// Best rigid transform between the pairs, from the SVD of their cross-covariance (Kabsch)
+fn point_to_point_step(
+    pairs: &[(Vector3<f64>, usize)],
+    target: &[[f32; 3]],
+) -> (Matrix3<f64>, Vector3<f64>) {
+    let n = pairs.len() as f64;
+    let source_center = pairs.iter().map(|(p, _)| p).sum::<Vector3<f64>>() / n;
+    let target_center = pairs
+        .iter()
+        .map(|(_, i)| to_vector(&target[*i]))
+        .sum::<Vector3<f64>>()
+        / n;
+    let covariance = pairs
+        .iter()
+        .map(|(p, i)| (p - source_center) * (to_vector(&target[*i]) - target_center).transpose())
+        .sum::<Matrix3<f64>>();
+    let svd = covariance.svd(true, true);
+    let (u, v_t) = (svd.u.unwrap(), svd.v_t.unwrap());
+    let mut rotation = v_t.transpose() * u.transpose();
+    // !! a reflection is the best fit for flat or degenerate pairs, flip it back to a rotation
+    if rotation.determinant() < 0.0 {
+        let mut v = v_t.transpose();
+        v.column_mut(2).neg_mut();
+        rotation = v * u.transpose();
+    }
+    (rotation, target_center - rotation * source_center)
+}
+
+/// Least squares step for the distances to the tangent planes, linearized for small rotations
+fn point_to_plane_step(
+    pairs: &[(Vector3<f64>, usize)],
+    target: &[[f32; 3]],
+    normals: &[[f32; 3]],
+) -> Result<(Matrix3<f64>, Vector3<f64>), String> {
+    let mut a = Matrix6::<f64>::zeros();
+    let mut b = Vector6::<f64>::zeros();
+    for (p, i) in pairs {
+        let q = to_vector(&target[*i]);
+        let n = to_vector(&normals[*i]);
+        let c = p.cross(&n);
+        let row = Vector6::new(c.x, c.y, c.z, n.x, n.y, n.z);
+        a += row * row.transpose();
+        b += row * (q - p).dot(&n);
+    }
+    let x = a
+        .cholesky()
+        .map(|cholesky| cholesky.solve(&b))
+        .ok_or("Point-to-plane ICP is underconstrained, the normals may be missing or all alike")?;
+    let rotation = Rotation3::from_euler_angles(x[0], x[1], x[2]);
+    let translation = Translation3::new(x[3], x[4], x[5]);
+    Ok((*rotation.matrix(), translation.vector))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Points on the faces of a box, with their normals
+    fn box_surface() -> (Vec<[f32; 3]>, Vec<[f32; 3]>) {
+        let mut points = vec![];
+        let mut normals = vec![];
+        for i in 0..=10 {
+            for j in 0..=10 {
+                let (u, v) = (i as f32 / 10.0, j as f32 / 10.0);
+                for (p, n) in [
+                    ([u, v, 0.0], [0.0, 0.0, -1.0]),
+                    ([u, v, 0.5], [0.0, 0.0, 1.0]),
+                    ([u, 0.0, v / 2.0], [0.0, -1.0, 0.0]),
+                    ([u, 1.0, v / 2.0], [0.0, 1.0, 0.0]),
+                    ([0.0, u, v / 2.0], [-1.0, 0.0, 0.0]),
+                ] {
+                    points.push(p);
+                    normals.push(n);
+                }
+            }
+        }
+        (points, normals)
+    }
+
+    fn moved(points: &[[f32; 3]]) -> (Vec<[f32; 3]>, Matrix4<f64>) {
+        let rotation = Rotation3::from_euler_angles(0.05, -0.03, 0.08);
+        let transform = to_homogeneous(rotation.matrix(), &Vector3::new(0.04, -0.02, 0.03));
+        let moved = points
+            .iter()
+            .map(|p| {
+                let p = transform.transform_point(&to_vector(p).into());
+                [p.x as f32, p.y as f32, p.z as f32]
+            })
+            .collect();
+        (moved, transform)
+    }
+
+    #[test]
+    fn test_icp_recovers_transform() {
+        let (target, normals) = box_surface();
+        let (source, applied) = moved(&target);
+        for method in [IcpMethod::PointToPoint, IcpMethod::PointToPlane] {
+            let result = icp(
+                &target,
+                Some(&normals),
+                &source,
+                IcpOptions {
+                    method,
+                    max_iterations: 100,
+                    tolerance: 1e-9,
+                    max_distance: None,
+                },
+            )
+            .unwrap();
+            assert!(result.rms < 1e-3, "{:?}: rms {}", method, result.rms);
+            let identity = result.transform * applied;
+            assert!(
+                (identity - Matrix4::identity()).abs().max() < 1e-3,
+                "{:?}: {}",
+                method,
+                identity
+            );
+        }
+    }
+
+    #[test]
+    fn test_icp_ignores_non_finite_points() {
+        let (mut target, _) = box_surface();
+        let (mut source, applied) = moved(&target);
+        target.push([f32::NAN, 0.0, 0.0]);
+        source.push([0.0, f32::INFINITY, 0.0]);
+        let options = IcpOptions {
+            method: IcpMethod::PointToPoint,
+            max_iterations: 100,
+            tolerance: 1e-9,
+            max_distance: None,
+        };
+        let result = icp(&target, None, &source, options).unwrap();
+        assert!(
+            (result.transform * applied - Matrix4::identity())
+                .abs()
+                .max()
+                < 1e-3
+        );
+
+        assert!(icp(&[[f32::NAN; 3]], None, &source, options).is_err());
+    }
+
+    #[test]
+    fn test_icp_keeps_the_best_transform() {
+        // !! normals that do not match the surface make the second step worse than the first
+        let (target, normals) = box_surface();
+        let normals = normals
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let t = i as f32 * 0.7;
+                [n[0] + t.sin(), n[1] + t.cos(), n[2] + (2.0 * t).sin()]
+            })
+            .collect::<Vec<_>>();
+        let (source, _) = moved(&target);
+        let options = IcpOptions {
+            method: IcpMethod::PointToPlane,
+            max_iterations: 20,
+            tolerance: 1e-6,
+            max_distance: Some(0.05),
+        };
+        let result = icp(&target, Some(&normals), &source, options).unwrap();
+        assert_eq!(result.iterations, 1);
+        assert!(result.rms < 0.019, "rms {}", result.rms);
+    }
+
+    #[test]
+    fn test_point_to_plane_needs_normals() {
+        let (target, _) = box_surface();
+        let options = IcpOptions {
+            method: IcpMethod::PointToPlane,
+            max_iterations: 10,
+            tolerance: 1e-6,
+            max_distance: None,
+        };
+        assert!(icp(&target, None, &target, options).is_err());
+    }
+}
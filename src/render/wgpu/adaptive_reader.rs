@@ -53,7 +53,8 @@ fn infer_format(src: &String) -> String {
 }
 
 impl AdaptiveReader {
-    pub fn new(src: &Vec<String>) -> Self {
+    /// `legacy_rgba` reads PCD files written by earlier versions of vvtk, see [crate::formats::from_legacy_rgba]
+    pub fn new(src: &Vec<String>, legacy_rgba: bool) -> Self {
         // TODO: remove the hard limit on the src len
         if src.len() != 1 && src.len() != 3 {
             panic!("src can only be of size 1 or 3")
@@ -66,7 +67,10 @@ impl AdaptiveReader {
         let mut readers = vec![];
 
         for path in paths.iter() {
-            readers.push(PointCloudFileReader::from_directory(path, &play_format));
+            readers.push(
+                PointCloudFileReader::from_directory(path, &play_format)
+                    .with_legacy_rgba(legacy_rgba),
+            );
         }
 
         if readers.is_empty() || readers[0].is_empty() {
@@ -1,5 +1,5 @@
 use crate::formats::pointxyzrgba::PointXyzRgba;
-use crate::formats::PointCloud;
+use crate::formats::{from_legacy_rgba, PointCloud};
 use crate::pcd::read_pcd_file;
 use crate::BufMsg;
 
@@ -40,6 +40,8 @@ pub trait RenderReaderCameraPos<T: Renderable> {
 
 pub struct PcdFileReader {
     files: Vec<PathBuf>,
+    /// see [from_legacy_rgba]
+    legacy_rgba: bool,
 }
 
 impl PcdFileReader {
@@ -60,7 +62,16 @@ impl PcdFileReader {
             }
         }
         files.sort();
-        Self { files }
+        Self {
+            files,
+            legacy_rgba: false,
+        }
+    }
+
+    /// Reads files written by earlier versions of vvtk, see [from_legacy_rgba]
+    pub fn with_legacy_rgba(mut self, legacy_rgba: bool) -> Self {
+        self.legacy_rgba = legacy_rgba;
+        self
     }
 
     pub fn file_at(&self, index: usize) -> Option<&PathBuf> {
@@ -70,6 +81,8 @@ impl PcdFileReader {
 
 pub struct PointCloudFileReader {
     files: Vec<PathBuf>,
+    /// see [from_legacy_rgba]
+    legacy_rgba: bool,
 }
 
 impl PointCloudFileReader {
@@ -90,7 +103,26 @@ impl PointCloudFileReader {
             }
         }
         files.sort();
-        Self { files }
+        Self {
+            files,
+            legacy_rgba: false,
+        }
+    }
+
+    /// Reads PCD files written by earlier versions of vvtk, see [from_legacy_rgba]
+    pub fn with_legacy_rgba(mut self, legacy_rgba: bool) -> Self {
+        self.legacy_rgba = legacy_rgba;
+        self
+    }
+
+    fn read(&self, file_path: &PathBuf) -> Option<PointCloud<PointXyzRgba>> {
+        if self.legacy_rgba && file_path.extension().and_then(|ext| ext.to_str()) == Some("pcd") {
+            return read_pcd_file(file_path)
+                .map(from_legacy_rgba)
+                .and_then(PointCloud::try_from)
+                .ok();
+        }
+        read_file_to_point_cloud(file_path)
     }
 }
 
@@ -101,7 +133,7 @@ impl RenderReader<PointCloud<PointXyzRgba>> for PointCloudFileReader {
 
     fn get_at(&mut self, index: usize) -> Option<PointCloud<PointXyzRgba>> {
         let file_path = self.files.get(index)?;
-        read_file_to_point_cloud(file_path)
+        self.read(file_path)
     }
 
     fn len(&self) -> usize {
@@ -128,7 +160,7 @@ impl RenderReaderCameraPos<PointCloud<PointXyzRgba>> for PointCloudFileReader {
         _camera_pos: Option<CameraPosition>,
     ) -> (Option<CameraPosition>, Option<PointCloud<PointXyzRgba>>) {
         let file_path = self.files.get(index).unwrap();
-        (None, read_file_to_point_cloud(file_path))
+        (None, self.read(file_path))
     }
 
     fn len(&self) -> usize {
@@ -150,10 +182,18 @@ impl RenderReader<PointCloud<PointXyzRgba>> for PcdFileReader {
     }
 
     fn get_at(&mut self, index: usize) -> Option<PointCloud<PointXyzRgba>> {
-        self.files
-            .get(index)
-            .and_then(|f| read_pcd_file(f).ok())
-            .map(PointCloud::from)
+        self.files.get(index).and_then(|f| {
+            read_pcd_file(f)
+                .map(|pcd| {
+                    if self.legacy_rgba {
+                        from_legacy_rgba(pcd)
+                    } else {
+                        pcd
+                    }
+                })
+                .and_then(PointCloud::try_from)
+                .ok()
+        })
     }
 
     fn len(&self) -> usize {
@@ -1,15 +1,20 @@
 use crate::{
-    formats::{pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud},
-    pcd::{create_pcd, read_pcd_file, write_pcd_file, PCDDataType, PointCloudData},
-    ply::read_ply,
+    formats::{
+        from_legacy_rgba, pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal,
+        PointCloud,
+    },
+    las::{read_las_file, write_las_file, write_las_file_with_offset},
+    pcd::{
+        create_pcd, create_pcd_from_pc_normal, read_pcd_file, write_pcd_file, PCDDataType,
+        PointCloudData,
+    },
+    ply::{
+        append_extra_fields, create_ply, has_normals, read_ply_file, read_ply_vertices, PlyVertex,
+    },
     velodyne::read_velodyn_bin_file,
+    xyz::{is_text_file, read_text_file, write_text_file, TextOptions},
 };
-use ply_rs::{
-    parser, ply,
-    ply::DefaultElement,
-    ply::{Encoding, Payload},
-    writer,
-};
+use ply_rs::{parser, ply, ply::Encoding, writer};
 use std::fs::File;
 use std::str::FromStr;
 use std::{
@@ -25,16 +30,27 @@ use crate::render::wgpu::camera::CameraPosition;
 use cgmath::{InnerSpace, Point3, Vector3};
 
 pub fn read_file_to_point_cloud(file: &PathBuf) -> Option<PointCloud<PointXyzRgba>> {
-    if let Some(ext) = file.extension().and_then(|ext| ext.to_str()) {
-        let point_cloud = match ext {
-            "ply" => read_ply(file),
-            "pcd" => read_pcd_file(file).map(PointCloud::from).ok(),
-            "bin" => read_velodyn_bin_file(file).map(PointCloud::from).ok(),
-            _ => None,
-        };
-        return point_cloud;
+    try_read_file_to_point_cloud(file).ok()
+}
+
+/// Same as `read_file_to_point_cloud`, with the reason the file could not be read
+pub fn try_read_file_to_point_cloud(file: &PathBuf) -> Result<PointCloud<PointXyzRgba>, String> {
+    match file.extension().and_then(|ext| ext.to_str()) {
+        Some("ply") => read_ply_vertices(file),
+        Some("pcd") => read_pcd_file(file)
+            .and_then(PointCloud::try_from)
+            .map_err(|e| e.to_string()),
+        Some("bin") => read_velodyn_bin_file(file)
+            .map(PointCloud::from)
+            .map_err(|e| e.to_string()),
+        Some("las") => read_las_file(file)
+            .map(PointCloud::from)
+            .map_err(|e| e.to_string()),
+        _ if is_text_file(file) => read_text_file(file, &TextOptions::default())
+            .map(PointCloud::from)
+            .map_err(|e| e.to_string()),
+        _ => Err("Unsupported file extension".to_string()),
     }
-    None
 }
 
 fn check_files_existence(files: &Vec<OsString>) -> bool {
@@ -49,9 +65,9 @@ fn check_files_existence(files: &Vec<OsString>) -> bool {
     flag
 }
 
-pub fn find_all_files(os_strings: &Vec<OsString>) -> Vec<PathBuf> {
+pub fn find_all_files(os_strings: &Vec<OsString>) -> Result<Vec<PathBuf>, String> {
     if !check_files_existence(os_strings) {
-        panic!("Some files do not exist")
+        return Err("Some files do not exist".to_string());
     }
     let mut files_to_convert = vec![];
     for file_str in os_strings {
@@ -62,7 +78,7 @@ pub fn find_all_files(os_strings: &Vec<OsString>) -> Vec<PathBuf> {
             files_to_convert.push(path.to_path_buf());
         }
     }
-    files_to_convert
+    Ok(files_to_convert)
 }
 
 pub fn expand_directory(p: &Path) -> Vec<PathBuf> {
@@ -408,34 +424,62 @@ pub fn predict_quality(geo_qp: f32, attr_qp: f32) -> f32 {
         - 0.00678052 * attr_qp * attr_qp
 }
 
-pub fn ply_to_ply(output_path: &Path, storage_type: PCDDataType, file_path: PathBuf) {
+/// Name of the converted file inside `output_path`, keeping the stem of `file_path`
+fn output_file_for(
+    output_path: &Path,
+    file_path: &Path,
+    extension: &str,
+) -> Result<PathBuf, String> {
+    let file_name = file_path
+        .file_name()
+        .ok_or(format!("{:?} is not a file", file_path))?;
+    Ok(output_path.join(Path::new(file_name).with_extension(extension)))
+}
+
+pub fn ply_to_ply(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+) -> Result<(), String> {
     let ply_parser = parser::Parser::<ply::DefaultElement>::new();
-    let mut f = std::fs::File::open(&file_path).unwrap();
-    let mut ply = ply_parser.read_ply(&mut f).unwrap();
+    let mut f = std::fs::File::open(&file_path)
+        .map_err(|e| format!("Failed to open {:?}: {e}", file_path))?;
+    let mut ply = ply_parser
+        .read_ply(&mut f)
+        .map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
 
     ply.header.encoding = match storage_type {
         PCDDataType::Ascii => ply_rs::ply::Encoding::Ascii,
         PCDDataType::Binary => set_encoding(),
-        _ => unreachable!(),
+        PCDDataType::CompressedBinary => {
+            return Err("PLY has no compressed encoding, use binary or ascii".into())
+        }
     };
 
-    let filename = Path::new(file_path.file_name().unwrap()).with_extension("ply");
-    let output_file = output_path.join(filename);
-    let mut file = File::create(&output_file).unwrap();
+    let output_file = output_file_for(output_path, &file_path, "ply")?;
+    let mut file = File::create(&output_file)
+        .map_err(|e| format!("Failed to create {:?}: {e}", output_file))?;
 
     let ply_writer = writer::Writer::<ply::DefaultElement>::new();
-    if let Err(e) = ply_writer.write_ply(&mut file, &mut ply) {
-        println!(
+    ply_writer.write_ply(&mut file, &mut ply).map_err(|e| {
+        format!(
             "Failed to write {:?} to {:?}\n{e}",
             file_path.into_os_string(),
             output_file.into_os_string()
-        );
-    }
+        )
+    })?;
+    Ok(())
 }
 
-pub fn pcd_to_pcd(output_path: &Path, storage_type: PCDDataType, file_path: PathBuf) {
-    let pcd = read_pcd_file(file_path.clone()).unwrap();
-    create_file_write_pcd_helper(&pcd, output_path, storage_type, file_path);
+/// `legacy_rgba` reads files written by earlier versions of vvtk, see [from_legacy_rgba]
+pub fn pcd_to_pcd(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+    legacy_rgba: bool,
+) -> Result<(), String> {
+    let pcd = read_pcd_file_of_version(&file_path, legacy_rgba)?;
+    create_file_write_pcd_helper(&pcd, output_path, storage_type, file_path)
 }
 
 pub fn create_file_write_pcd_helper(
@@ -443,101 +487,55 @@ pub fn create_file_write_pcd_helper(
     output_path: &Path,
     storage_type: PCDDataType,
     file_path: PathBuf,
-) {
-    let filename = Path::new(file_path.file_name().unwrap()).with_extension("pcd");
-    let output_file = output_path.join(filename);
-    if let Err(e) = write_pcd_file(&pcd, storage_type, &output_file) {
-        println!(
+) -> Result<(), String> {
+    let output_file = output_file_for(output_path, &file_path, "pcd")?;
+    write_pcd_file(pcd, storage_type, &output_file).map_err(|e| {
+        format!(
             "Failed to write {:?} to {:?}\n{e}",
             file_path.into_os_string(),
             output_file.into_os_string()
-        );
-    }
+        )
+    })
 }
 
-pub fn ply_to_pcd(output_path: &Path, storage_type: PCDDataType, file_path: PathBuf) {
-    let pointxyzrgba = read_ply(file_path.clone()).unwrap();
-    let pcd = create_pcd(&pointxyzrgba);
-    create_file_write_pcd_helper(&pcd, output_path, storage_type, file_path);
+pub fn ply_to_pcd(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+) -> Result<(), String> {
+    let ply = read_ply_file(&file_path)?;
+    let pcd = if has_normals(&ply.header) {
+        create_pcd_from_pc_normal(&PointCloud::try_from(&ply)?)
+    } else {
+        create_pcd(&PointCloud::try_from(&ply)?)
+    };
+    let pcd = append_extra_fields(pcd, &ply)
+        .map_err(|e| format!("Failed to convert {:?}: {e}", file_path))?;
+    create_file_write_pcd_helper(&pcd, output_path, storage_type, file_path)
 }
 
-pub fn pcd_to_ply_from_data(
+fn write_ply_file<T: PlyVertex>(
     output_path: &Path,
     storage_type: PCDDataType,
-    pcd: PointCloudData,
+    pc: &PointCloud<T>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let x_prop_def = ply_rs::ply::PropertyDef::new(
-        "x".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::Float),
-    );
-    let y_prop_def = ply_rs::ply::PropertyDef::new(
-        "y".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::Float),
-    );
-    let z_prop_def = ply_rs::ply::PropertyDef::new(
-        "z".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::Float),
-    );
-    let red_prop_def = ply_rs::ply::PropertyDef::new(
-        "red".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::UChar),
-    );
-    let green_prop_def = ply_rs::ply::PropertyDef::new(
-        "green".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::UChar),
-    );
-    let blue_prop_def = ply_rs::ply::PropertyDef::new(
-        "blue".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::UChar),
-    );
-
-    let mut element = ply_rs::ply::ElementDef::new("vertex".to_string());
-    element.properties.insert("x".to_string(), x_prop_def);
-    element.properties.insert("y".to_string(), y_prop_def);
-    element.properties.insert("z".to_string(), z_prop_def);
-    element.properties.insert("red".to_string(), red_prop_def);
-    element
-        .properties
-        .insert("green".to_string(), green_prop_def);
-    element.properties.insert("blue".to_string(), blue_prop_def);
-    element.count = pcd.header().width() as usize;
-
-    let mut ply_header = ply_rs::ply::Header::new();
-    ply_header.encoding = match storage_type {
+    let encoding = match storage_type {
         PCDDataType::Ascii => ply_rs::ply::Encoding::Ascii,
         PCDDataType::Binary => set_encoding(),
-        _ => unreachable!(),
+        PCDDataType::CompressedBinary => {
+            return Err("PLY has no compressed encoding, use binary or ascii".into())
+        }
     };
-    ply_header.elements.insert("vertex".to_string(), element);
-
-    let pcd_pointxyzrgba: PointCloud<PointXyzRgba> = pcd.into();
-    let mut pay_load_vec = Vec::<DefaultElement>::new();
-    pcd_pointxyzrgba.points.into_iter().for_each(|point| {
-        let mut ply_point = DefaultElement::new();
-        ply_point.insert("x".to_string(), ply_rs::ply::Property::Float(point.x));
-        ply_point.insert("y".to_string(), ply_rs::ply::Property::Float(point.y));
-        ply_point.insert("z".to_string(), ply_rs::ply::Property::Float(point.z));
-        ply_point.insert("red".to_string(), ply_rs::ply::Property::UChar(point.r));
-        ply_point.insert("green".to_string(), ply_rs::ply::Property::UChar(point.g));
-        ply_point.insert("blue".to_string(), ply_rs::ply::Property::UChar(point.b));
-        pay_load_vec.push(ply_point);
-    });
-    let mut pay_load = Payload::<DefaultElement>::new();
-    pay_load.insert("vertex".to_string(), pay_load_vec);
-
-    let mut ply = ply_rs::ply::Ply::<DefaultElement>::new();
-    ply.header = ply_header;
-    ply.payload = pay_load;
-
-    // println!("Writing to {:?}", output_path);
+    let mut ply = create_ply(pc, encoding);
+
     // get dir part and check existence, create if not exist
-    let dir = output_path.parent().unwrap();
-    if !dir.exists() {
-        std::fs::create_dir_all(dir).unwrap();
+    if let Some(dir) = output_path.parent() {
+        if !dir.exists() {
+            std::fs::create_dir_all(dir)?;
+        }
     }
 
-    println!("Writing to {:?}", output_path);
-    let mut file = File::create(output_path).unwrap();
+    let mut file = File::create(output_path)?;
 
     let ply_writer = writer::Writer::<ply::DefaultElement>::new();
     if let Err(e) = ply_writer.write_ply(&mut file, &mut ply) {
@@ -547,140 +545,249 @@ pub fn pcd_to_ply_from_data(
     }
 }
 
+pub fn pcd_to_ply_from_data(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    pcd: PointCloudData,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pc: PointCloud<PointXyzRgba> = pcd.try_into()?;
+    println!("Writing to {:?}", output_path);
+    write_ply_file(output_path, storage_type, &pc)
+}
+
 pub fn pcd_to_ply_from_data_normal(
     output_path: &Path,
     storage_type: PCDDataType,
     pcd: PointCloudData,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let x_prop_def = ply_rs::ply::PropertyDef::new(
-        "x".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::Float),
-    );
-    let y_prop_def = ply_rs::ply::PropertyDef::new(
-        "y".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::Float),
-    );
-    let z_prop_def = ply_rs::ply::PropertyDef::new(
-        "z".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::Float),
-    );
-    let red_prop_def = ply_rs::ply::PropertyDef::new(
-        "red".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::UChar),
-    );
-    let green_prop_def = ply_rs::ply::PropertyDef::new(
-        "green".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::UChar),
-    );
-    let blue_prop_def = ply_rs::ply::PropertyDef::new(
-        "blue".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::UChar),
-    );
-    let nx_prop_def = ply_rs::ply::PropertyDef::new(
-        "nx".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::Float),
-    );
-    let ny_prop_def = ply_rs::ply::PropertyDef::new(
-        "ny".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::Float),
-    );
-    let nz_prop_def = ply_rs::ply::PropertyDef::new(
-        "nz".to_string(),
-        ply_rs::ply::PropertyType::Scalar(ply_rs::ply::ScalarType::Float),
-    );
-
-    let mut element = ply_rs::ply::ElementDef::new("vertex".to_string());
-    element.properties.insert("x".to_string(), x_prop_def);
-    element.properties.insert("y".to_string(), y_prop_def);
-    element.properties.insert("z".to_string(), z_prop_def);
-    element.properties.insert("red".to_string(), red_prop_def);
-    element
-        .properties
-        .insert("green".to_string(), green_prop_def);
-    element.properties.insert("blue".to_string(), blue_prop_def);
-    element.properties.insert("nx".to_string(), nx_prop_def);
-    element.properties.insert("ny".to_string(), ny_prop_def);
-    element.properties.insert("nz".to_string(), nz_prop_def);
-    element.count = pcd.header().width() as usize;
-
-    let mut ply_header = ply_rs::ply::Header::new();
-    ply_header.encoding = match storage_type {
-        PCDDataType::Ascii => ply_rs::ply::Encoding::Ascii,
-        PCDDataType::Binary => set_encoding(),
-        _ => unreachable!(),
-    };
-    ply_header.elements.insert("vertex".to_string(), element);
-
-    let pcd_pointxyzrgbanormal: PointCloud<PointXyzRgbaNormal> = pcd.into();
-    let mut pay_load_vec = Vec::<DefaultElement>::new();
-    pcd_pointxyzrgbanormal.points.into_iter().for_each(|point| {
-        let mut ply_point = DefaultElement::new();
-        ply_point.insert("x".to_string(), ply_rs::ply::Property::Float(point.x));
-        ply_point.insert("y".to_string(), ply_rs::ply::Property::Float(point.y));
-        ply_point.insert("z".to_string(), ply_rs::ply::Property::Float(point.z));
-        ply_point.insert("red".to_string(), ply_rs::ply::Property::UChar(point.r));
-        ply_point.insert("green".to_string(), ply_rs::ply::Property::UChar(point.g));
-        ply_point.insert("blue".to_string(), ply_rs::ply::Property::UChar(point.b));
-        ply_point.insert("nx".to_string(), ply_rs::ply::Property::Float(point.nx));
-        ply_point.insert("ny".to_string(), ply_rs::ply::Property::Float(point.ny));
-        ply_point.insert("nz".to_string(), ply_rs::ply::Property::Float(point.nz));
-        pay_load_vec.push(ply_point);
-    });
-    let mut pay_load = Payload::<DefaultElement>::new();
-    pay_load.insert("vertex".to_string(), pay_load_vec);
-
-    let mut ply = ply_rs::ply::Ply::<DefaultElement>::new();
-    ply.header = ply_header;
-    ply.payload = pay_load;
-
-    let dir = output_path.parent().unwrap();
-    if !dir.exists() {
-        std::fs::create_dir_all(dir).unwrap();
-    }
-
-    let mut file = File::create(output_path).unwrap();
+    let pc: PointCloud<PointXyzRgbaNormal> = pcd.try_into()?;
+    write_ply_file(output_path, storage_type, &pc)
+}
 
-    let ply_writer = writer::Writer::<ply::DefaultElement>::new();
-    if let Err(e) = ply_writer.write_ply(&mut file, &mut ply) {
-        Result::Err(Box::new(e))
+fn read_pcd_file_of_version(file_path: &Path, legacy_rgba: bool) -> Result<PointCloudData, String> {
+    let pcd =
+        read_pcd_file(file_path).map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
+    Ok(if legacy_rgba {
+        from_legacy_rgba(pcd)
     } else {
-        Result::Ok(())
-    }
+        pcd
+    })
 }
 
-pub fn pcd_to_ply(output_path: &Path, storage_type: PCDDataType, file_path: PathBuf) {
-    let pcd = read_pcd_file(&file_path).unwrap();
-    let filename = Path::new(file_path.file_name().unwrap()).with_extension("ply");
-    let output_file = output_path.join(filename);
-    if let Err(e) = pcd_to_ply_from_data(&output_file, storage_type, pcd) {
-        println!(
+/// `legacy_rgba` reads files written by earlier versions of vvtk, see [from_legacy_rgba]
+pub fn pcd_to_ply(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+    legacy_rgba: bool,
+) -> Result<(), String> {
+    let pcd = read_pcd_file_of_version(&file_path, legacy_rgba)?;
+    let output_file = output_file_for(output_path, &file_path, "ply")?;
+    let has_normals = pcd
+        .header()
+        .fields()
+        .iter()
+        .any(|f| matches!(f.name(), "normal_x" | "nx"));
+    let written = if has_normals {
+        pcd_to_ply_from_data_normal(&output_file, storage_type, pcd)
+    } else {
+        pcd_to_ply_from_data(&output_file, storage_type, pcd)
+    };
+    written.map_err(|e| {
+        format!(
             "Failed to write {:?} to {:?}\n{e}",
             file_path.into_os_string(),
             output_file.to_str(),
-        );
-    }
+        )
+    })
 }
 
-pub fn velodyne_bin_to_ply(output_path: &Path, storage_type: PCDDataType, file_path: PathBuf) {
-    let vbd = read_velodyn_bin_file(&file_path).unwrap();
+pub fn velodyne_bin_to_ply(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+) -> Result<(), String> {
+    let vbd = read_velodyn_bin_file(&file_path)
+        .map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
     let pc: PointCloud<PointXyzRgba> = vbd.into();
     let pcd: PointCloudData = create_pcd(&pc);
-    let filename = Path::new(file_path.file_name().unwrap()).with_extension("ply");
-    let output_file = output_path.join(filename);
-    if let Err(e) = pcd_to_ply_from_data(&output_file, storage_type, pcd) {
-        println!(
+    let output_file = output_file_for(output_path, &file_path, "ply")?;
+    pcd_to_ply_from_data(&output_file, storage_type, pcd).map_err(|e| {
+        format!(
             "Failed to write {:?} to {:?}\n{e}",
             file_path.into_os_string(),
             output_file.to_str(),
-        );
-    }
+        )
+    })
 }
 
-pub fn velodyne_bin_to_pcd(output_path: &Path, storage_type: PCDDataType, file_path: PathBuf) {
-    let vbd = read_velodyn_bin_file(&file_path).unwrap();
+pub fn velodyne_bin_to_pcd(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+) -> Result<(), String> {
+    let vbd = read_velodyn_bin_file(&file_path)
+        .map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
     let pointxyzrgba: PointCloud<PointXyzRgba> = vbd.into();
     let pcd: PointCloudData = create_pcd(&pointxyzrgba);
-    create_file_write_pcd_helper(&pcd, output_path, storage_type, file_path);
+    create_file_write_pcd_helper(&pcd, output_path, storage_type, file_path)
+}
+
+pub fn las_to_pcd(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+) -> Result<(), String> {
+    let las =
+        read_las_file(&file_path).map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
+    let pc: PointCloud<PointXyzRgba> = las.into();
+    let pcd: PointCloudData = create_pcd(&pc);
+    create_file_write_pcd_helper(&pcd, output_path, storage_type, file_path)
+}
+
+pub fn las_to_ply(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+) -> Result<(), String> {
+    let las =
+        read_las_file(&file_path).map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
+    let pc: PointCloud<PointXyzRgba> = las.into();
+    let output_file = output_file_for(output_path, &file_path, "ply")?;
+    write_ply_file(&output_file, storage_type, &pc).map_err(|e| {
+        format!(
+            "Failed to write {:?} to {:?}\n{e}",
+            file_path.into_os_string(),
+            output_file.to_str(),
+        )
+    })
+}
+
+/// LAS is always binary, the other storage types are rejected
+pub fn check_las_storage_type(storage_type: PCDDataType) -> Result<(), String> {
+    match storage_type {
+        PCDDataType::Binary => Ok(()),
+        _ => Err("LAS is only stored as binary".to_string()),
+    }
+}
+
+/// Converts any file that can be read, normals and extra properties are dropped.
+/// LAS files keep the offset of their header, and so their coordinates
+pub fn to_las(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+    options: &TextOptions,
+) -> Result<(), String> {
+    check_las_storage_type(storage_type)?;
+    if file_path.extension().and_then(|ext| ext.to_str()) == Some("las") {
+        let las = read_las_file(&file_path)
+            .map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
+        let offset = las.header().offset;
+        let output_file = output_file_for(output_path, &file_path, "las")?;
+        let pc = PointCloud::from_las_relative_to_offset(las);
+        return write_las_file_with_offset(&pc, offset, &output_file).map_err(|e| {
+            format!(
+                "Failed to write {:?} to {:?}\n{e}",
+                file_path.into_os_string(),
+                output_file.to_str(),
+            )
+        });
+    }
+    let pc = if is_text_file(&file_path) {
+        read_text_file(&file_path, options)
+            .map(PointCloud::from)
+            .map_err(|e| e.to_string())
+    } else {
+        try_read_file_to_point_cloud(&file_path)
+    }
+    .map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
+    let output_file = output_file_for(output_path, &file_path, "las")?;
+    write_las_file(&pc, &output_file).map_err(|e| {
+        format!(
+            "Failed to write {:?} to {:?}\n{e}",
+            file_path.into_os_string(),
+            output_file.to_str(),
+        )
+    })
+}
+
+pub fn text_to_pcd(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+    options: &TextOptions,
+) -> Result<(), String> {
+    let text = read_text_file(&file_path, options)
+        .map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
+    let pcd = if text.has_normals() {
+        create_pcd_from_pc_normal(&text.into())
+    } else {
+        create_pcd(&text.into())
+    };
+    create_file_write_pcd_helper(&pcd, output_path, storage_type, file_path)
+}
+
+pub fn text_to_ply(
+    output_path: &Path,
+    storage_type: PCDDataType,
+    file_path: PathBuf,
+    options: &TextOptions,
+) -> Result<(), String> {
+    let text = read_text_file(&file_path, options)
+        .map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
+    let output_file = output_file_for(output_path, &file_path, "ply")?;
+    let written = if text.has_normals() {
+        let pc: PointCloud<PointXyzRgbaNormal> = text.into();
+        write_ply_file(&output_file, storage_type, &pc)
+    } else {
+        let pc: PointCloud<PointXyzRgba> = text.into();
+        write_ply_file(&output_file, storage_type, &pc)
+    };
+    written.map_err(|e| {
+        format!(
+            "Failed to write {:?} to {:?}\n{e}",
+            file_path.into_os_string(),
+            output_file.to_str(),
+        )
+    })
+}
+
+/// Text files are always ascii, `options` gives the columns of the output, or of the input
+/// when converting from another text file
+pub fn to_text(
+    output_path: &Path,
+    extension: &str,
+    file_path: PathBuf,
+    options: &TextOptions,
+) -> Result<(), String> {
+    let output_file = output_file_for(output_path, &file_path, extension)?;
+    let written = if is_text_file(&file_path) {
+        let text = read_text_file(&file_path, options)
+            .map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
+        let output_options = TextOptions {
+            columns: None,
+            ..options.clone()
+        };
+        if text.has_normals() {
+            let pc: PointCloud<PointXyzRgbaNormal> = text.into();
+            write_text_file(&pc, &output_options, &output_file)
+        } else {
+            let pc: PointCloud<PointXyzRgba> = text.into();
+            write_text_file(&pc, &output_options, &output_file)
+        }
+    } else {
+        let pc = try_read_file_to_point_cloud(&file_path)
+            .map_err(|e| format!("Failed to read {:?}: {e}", file_path))?;
+        write_text_file(&pc, options, &output_file)
+    };
+    written.map_err(|e| {
+        format!(
+            "Failed to write {:?} to {:?}\n{e}",
+            file_path.into_os_string(),
+            output_file.to_str(),
+        )
+    })
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -689,6 +796,10 @@ pub enum ConvertOutputFormat {
     PCD,
     PNG,
     MP4,
+    LAS,
+    XYZ,
+    CSV,
+    PTS,
 }
 
 impl ToString for ConvertOutputFormat {
@@ -698,6 +809,10 @@ impl ToString for ConvertOutputFormat {
             ConvertOutputFormat::PCD => "pcd",
             ConvertOutputFormat::PNG => "png",
             ConvertOutputFormat::MP4 => "mp4",
+            ConvertOutputFormat::LAS => "las",
+            ConvertOutputFormat::XYZ => "xyz",
+            ConvertOutputFormat::CSV => "csv",
+            ConvertOutputFormat::PTS => "pts",
         }
         .to_string()
     }
@@ -712,6 +827,10 @@ impl FromStr for ConvertOutputFormat {
             "pcd" => Ok(ConvertOutputFormat::PCD),
             "png" => Ok(ConvertOutputFormat::PNG),
             "mp4" => Ok(ConvertOutputFormat::MP4),
+            "las" => Ok(ConvertOutputFormat::LAS),
+            "xyz" => Ok(ConvertOutputFormat::XYZ),
+            "csv" => Ok(ConvertOutputFormat::CSV),
+            "pts" => Ok(ConvertOutputFormat::PTS),
             _ => Err(format!("{} is not a valid output format", s)),
         }
     }
@@ -730,6 +849,7 @@ fn set_encoding() -> Encoding {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ply::read_ply;
     const EPSILON: f64 = 0.0001;
 
     #[test]
@@ -844,7 +964,7 @@ mod tests {
     fn test_ply_to_ply() {
         let ply_ascii_path = PathBuf::from("./test_files/ply_ascii/longdress_vox10_1213_short.ply");
         let output_path = PathBuf::from("./test_files/ply_binary");
-        ply_to_ply(&output_path, PCDDataType::Binary, ply_ascii_path);
+        ply_to_ply(&output_path, PCDDataType::Binary, ply_ascii_path).unwrap();
         let output_path = output_path.join("longdress_vox10_1213_short.ply");
         let pc = read_file_to_point_cloud(&output_path).unwrap();
         assert_eq!(pc.number_of_points, 20);
@@ -890,7 +1010,7 @@ mod tests {
     fn test_ply_to_pcd() {
         let ply_ascii_path = PathBuf::from("./test_files/ply_ascii/longdress_vox10_1213_short.ply");
         let output_path = PathBuf::from("./test_files/pcd_binary");
-        ply_to_pcd(&output_path, PCDDataType::Binary, ply_ascii_path.clone());
+        ply_to_pcd(&output_path, PCDDataType::Binary, ply_ascii_path.clone()).unwrap();
         let output_path = output_path.join("longdress_vox10_1213_short.pcd");
         let pc = read_file_to_point_cloud(&output_path).unwrap();
         assert_eq!(pc.number_of_points, 20);
@@ -932,7 +1052,7 @@ mod tests {
         );
 
         let output_path = PathBuf::from("./test_files/pcd_ascii");
-        ply_to_pcd(&output_path, PCDDataType::Ascii, ply_ascii_path);
+        ply_to_pcd(&output_path, PCDDataType::Ascii, ply_ascii_path).unwrap();
         let output_path = output_path.join("longdress_vox10_1213_short.pcd");
         let pc = read_file_to_point_cloud(&output_path).unwrap();
         assert_eq!(pc.number_of_points, 20);
@@ -978,7 +1098,7 @@ mod tests {
     fn test_pcd_to_ply() {
         let pcd_ascii_path = PathBuf::from("./test_files/pcd_ascii/longdress_vox10_1213_short.pcd");
         let output_path = PathBuf::from("./test_files/ply_ascii/from_pcd");
-        pcd_to_ply(&output_path, PCDDataType::Ascii, pcd_ascii_path);
+        pcd_to_ply(&output_path, PCDDataType::Ascii, pcd_ascii_path, false).unwrap();
         let output_path = output_path.join("longdress_vox10_1213_short.ply");
         let pc = read_file_to_point_cloud(&output_path).unwrap();
         assert_eq!(pc.number_of_points, 20);
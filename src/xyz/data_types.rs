@@ -0,0 +1,270 @@
+use std::fmt::{Debug, Formatter};
+use std::str::FromStr;
+
+use crate::formats::{
+    pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud,
+};
+
+/// What a column of a text file holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    X,
+    Y,
+    Z,
+    R,
+    G,
+    B,
+    A,
+    Nx,
+    Ny,
+    Nz,
+    /// Shown as gray when there are no colors
+    Intensity,
+    /// Ignored
+    Skip,
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "x" => Ok(Column::X),
+            "y" => Ok(Column::Y),
+            "z" => Ok(Column::Z),
+            "r" | "red" => Ok(Column::R),
+            "g" | "green" => Ok(Column::G),
+            "b" | "blue" => Ok(Column::B),
+            "a" | "alpha" => Ok(Column::A),
+            "nx" | "normal_x" => Ok(Column::Nx),
+            "ny" | "normal_y" => Ok(Column::Ny),
+            "nz" | "normal_z" => Ok(Column::Nz),
+            "i" | "intensity" => Ok(Column::Intensity),
+            "_" | "skip" => Ok(Column::Skip),
+            other => Err(format!(
+                "unknown column {:?}, expected x, y, z, r, g, b, a, nx, ny, nz, i or _",
+                other
+            )),
+        }
+    }
+}
+
+impl Column {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::X => "x",
+            Column::Y => "y",
+            Column::Z => "z",
+            Column::R => "r",
+            Column::G => "g",
+            Column::B => "b",
+            Column::A => "a",
+            Column::Nx => "nx",
+            Column::Ny => "ny",
+            Column::Nz => "nz",
+            Column::Intensity => "i",
+            Column::Skip => "_",
+        }
+    }
+}
+
+/// Columns separated by commas, e.g. `x,y,z,r,g,b`
+pub fn parse_columns(s: &str) -> Result<Vec<Column>, String> {
+    let columns = s
+        .split(',')
+        .map(Column::from_str)
+        .collect::<Result<Vec<_>, _>>()?;
+    for required in [Column::X, Column::Y, Column::Z] {
+        if !columns.contains(&required) {
+            return Err(format!("missing column {}", required.name()));
+        }
+    }
+    for (i, column) in columns.iter().enumerate() {
+        if *column != Column::Skip && columns[..i].contains(column) {
+            return Err(format!("column {} is given twice", column.name()));
+        }
+    }
+    Ok(columns)
+}
+
+/// The columns used when the file does not name them, from the number of values of a line
+pub fn default_columns(values: usize) -> Option<Vec<Column>> {
+    use Column::*;
+
+    match values {
+        3 => Some(vec![X, Y, Z]),
+        4 => Some(vec![X, Y, Z, Intensity]),
+        6 => Some(vec![X, Y, Z, R, G, B]),
+        7 => Some(vec![X, Y, Z, Intensity, R, G, B]),
+        9 => Some(vec![X, Y, Z, R, G, B, Nx, Ny, Nz]),
+        _ => None,
+    }
+}
+
+/// Range of the colors of a text file
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScale {
+    /// From 0 to 1 if some color is fractional and none is larger than 1, from 0 to 255 otherwise
+    Auto,
+    /// From 0 to 1
+    Unit,
+    /// From 0 to 255
+    Byte,
+}
+
+/// How a text file is read or written
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextOptions {
+    /// Guessed from the file when `None`
+    pub columns: Option<Vec<Column>>,
+    /// Lines skipped at the start of the file
+    pub skip_lines: usize,
+    pub color_scale: ColorScale,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self {
+            columns: None,
+            skip_lines: 0,
+            color_scale: ColorScale::Auto,
+        }
+    }
+}
+
+/// This struct represents a single text file, with one row of values per point
+pub struct TextData {
+    pub(crate) columns: Vec<Column>,
+    /// The values of all the rows one after the other, one per column
+    pub(crate) values: Vec<f64>,
+    pub(crate) color_scale: ColorScale,
+}
+
+impl TextData {
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn number_of_points(&self) -> usize {
+        self.values.len() / self.columns.len().max(1)
+    }
+
+    pub fn has_normals(&self) -> bool {
+        [Column::Nx, Column::Ny, Column::Nz]
+            .iter()
+            .all(|c| self.columns.contains(c))
+    }
+
+    fn rows(&self) -> impl Iterator<Item = &[f64]> {
+        self.values.chunks_exact(self.columns.len().max(1))
+    }
+
+    fn index(&self, column: Column) -> Option<usize> {
+        self.columns.iter().position(|c| *c == column)
+    }
+
+    /// Factor from the values of `columns` to 0..255, `Auto` is resolved from the values.
+    /// A value must be fractional to take them from 0 to 1: a dark frame of 0s and 1s stays dark
+    fn scale_of(&self, columns: &[Column]) -> f64 {
+        let unit = match self.color_scale {
+            ColorScale::Unit => true,
+            ColorScale::Byte => false,
+            ColorScale::Auto => {
+                let indices = columns
+                    .iter()
+                    .filter_map(|c| self.index(*c))
+                    .collect::<Vec<_>>();
+                let values = || {
+                    self.rows()
+                        .flat_map(|row| indices.iter().map(move |&i| row[i]))
+                };
+                values().all(|v| v <= 1.0) && values().any(|v| v.fract() != 0.0)
+            }
+        };
+        if unit {
+            255.0
+        } else {
+            1.0
+        }
+    }
+
+    fn points(&self) -> Vec<PointXyzRgba> {
+        // !! intensities are often in another range than the colors, they are scaled on their own
+        let color_scale = self.scale_of(&[Column::R, Column::G, Column::B, Column::A]);
+        let intensity_scale = self.scale_of(&[Column::Intensity]);
+        let channel = |row: &[f64], i: Option<usize>, scale: f64, default: u8| -> u8 {
+            i.map_or(default, |i| {
+                (row[i] * scale).round().clamp(0.0, 255.0) as u8
+            })
+        };
+        let [x, y, z] = [Column::X, Column::Y, Column::Z].map(|c| self.index(c));
+        let [r, g, b, a] = [Column::R, Column::G, Column::B, Column::A].map(|c| self.index(c));
+        let intensity = self.index(Column::Intensity);
+        let position = |row: &[f64], i: Option<usize>| i.map_or(0.0, |i| row[i] as f32);
+        self.rows()
+            .map(|row| {
+                // !! without colors the points are gray as bright as their intensity, or white
+                let gray = channel(row, intensity, intensity_scale, 255);
+                PointXyzRgba {
+                    x: position(row, x),
+                    y: position(row, y),
+                    z: position(row, z),
+                    r: channel(row, r, color_scale, gray),
+                    g: channel(row, g, color_scale, gray),
+                    b: channel(row, b, color_scale, gray),
+                    a: channel(row, a, color_scale, 255),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Debug for TextData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "TextData: {} points with columns {:?}",
+            self.number_of_points(),
+            self.columns
+        )
+    }
+}
+
+impl From<TextData> for PointCloud<PointXyzRgba> {
+    fn from(text: TextData) -> Self {
+        let points = text.points();
+        Self {
+            number_of_points: points.len(),
+            points,
+        }
+    }
+}
+
+impl From<TextData> for PointCloud<PointXyzRgbaNormal> {
+    /// Normals without columns are zero
+    fn from(text: TextData) -> Self {
+        let [nx, ny, nz] = [Column::Nx, Column::Ny, Column::Nz].map(|c| text.index(c));
+        let normal = |row: &[f64], i: Option<usize>| i.map_or(0.0, |i| row[i] as f32);
+        let points = text
+            .points()
+            .into_iter()
+            .zip(text.rows())
+            .map(|(p, row)| PointXyzRgbaNormal {
+                x: p.x,
+                y: p.y,
+                z: p.z,
+                r: p.r,
+                g: p.g,
+                b: p.b,
+                a: p.a,
+                nx: normal(row, nx),
+                ny: normal(row, ny),
+                nz: normal(row, nz),
+            })
+            .collect::<Vec<_>>();
+        Self {
+            number_of_points: points.len(),
+            points,
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//! Plain text point clouds (XYZ, CSV and PTS)
+//!
+//! Every line holds the values of a point, separated by spaces, tabs, commas or semicolons.
+//! The meaning of each column is given by [`TextOptions`], by a header naming the columns
+//! or guessed from the number of values. Lines starting with `#` or `//` are comments.
+//! PTS files start with the number of points.
+//!
+//! # Examples
+//!
+//! ## Reading from a file
+//! ```no_run
+//! use vivotk::xyz::{parse_columns, read_text_file, TextOptions, TextReadError};
+//!
+//! fn main() -> Result<(), TextReadError> {
+//!     let options = TextOptions {
+//!         columns: Some(parse_columns("x,y,z,r,g,b").unwrap()),
+//!         ..TextOptions::default()
+//!     };
+//!     let text = read_text_file("example.xyz", &options)?;
+//!     println!("{}", text.number_of_points());
+//!     Ok(())
+//! }
+//! ```
+
+mod data_types;
+mod reader;
+mod writer;
+
+pub use data_types::*;
+pub use reader::{read_text, read_text_file, TextReadError};
+pub use writer::{is_text_file, write_text, write_text_file, TextFormat, TextPoint};
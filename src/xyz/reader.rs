@@ -0,0 +1,248 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::xyz::data_types::{default_columns, Column, TextData, TextOptions};
+
+type Result<T> = std::result::Result<T, TextReadError>;
+
+#[derive(Error, Debug)]
+pub enum TextReadError {
+    /// For ease of conversion from IO errors to TextReadError.
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+
+    /// Represents an error with a line of the file, counted from 1.
+    #[error("Invalid line {line}: {error_msg}")]
+    InvalidLine { line: usize, error_msg: String },
+
+    /// The columns cannot be matched with the values of the file.
+    #[error("Invalid columns: {0}")]
+    InvalidColumns(String),
+}
+
+/// Reads the text file from the reader. With `pts`, the lines holding a single number are
+/// point counts and are skipped.
+pub fn read_text<R: Read>(r: R, options: &TextOptions, pts: bool) -> Result<TextData> {
+    Parser::new(BufReader::new(r), options, pts).parse()
+}
+
+/// Reads the text file directly from a file given the path, `.pts` files start with a point count
+pub fn read_text_file<P: AsRef<Path>>(p: P, options: &TextOptions) -> Result<TextData> {
+    let pts = p.as_ref().extension().and_then(|ext| ext.to_str()) == Some("pts");
+    let file = File::open(p)?;
+    read_text(file, options, pts)
+}
+
+fn tokens(line: &str) -> Vec<&str> {
+    line.split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+struct Parser<'a, R: BufRead> {
+    reader: R,
+    options: &'a TextOptions,
+    pts: bool,
+}
+
+impl<'a, R: BufRead> Parser<'a, R> {
+    fn new(reader: R, options: &'a TextOptions, pts: bool) -> Self {
+        Self {
+            reader,
+            options,
+            pts,
+        }
+    }
+
+    fn parse(self) -> Result<TextData> {
+        let mut columns = self.options.columns.clone();
+        // !! the indices of the values kept, columns to skip are dropped
+        let mut kept = vec![];
+        let mut values = vec![];
+        let mut first_line = true;
+
+        for (i, line) in self
+            .reader
+            .lines()
+            .enumerate()
+            .skip(self.options.skip_lines)
+        {
+            let line = line?;
+            let line_number = i + 1;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("//") {
+                continue;
+            }
+            let tokens = tokens(trimmed);
+            if self.pts && tokens.len() == 1 && tokens[0].parse::<u64>().is_ok() {
+                continue;
+            }
+
+            let numbers = tokens
+                .iter()
+                .map(|token| token.parse::<f64>())
+                .collect::<std::result::Result<Vec<_>, _>>();
+            let numbers = match numbers {
+                Ok(numbers) => numbers,
+                // !! a header naming the columns, as written by spreadsheets and numpy
+                Err(_) if first_line => {
+                    first_line = false;
+                    if columns.is_none() {
+                        columns = tokens
+                            .iter()
+                            .map(|token| Column::from_str(token))
+                            .collect::<std::result::Result<Vec<_>, _>>()
+                            .ok();
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    return Err(TextReadError::InvalidLine {
+                        line: line_number,
+                        error_msg: e.to_string(),
+                    })
+                }
+            };
+            first_line = false;
+
+            if kept.is_empty() {
+                let found = match columns.take() {
+                    Some(columns) => columns,
+                    None => default_columns(numbers.len()).ok_or_else(|| {
+                        TextReadError::InvalidColumns(format!(
+                            "cannot guess the columns of {} values, give them with --columns",
+                            numbers.len()
+                        ))
+                    })?,
+                };
+                for required in [Column::X, Column::Y, Column::Z] {
+                    if !found.contains(&required) {
+                        return Err(TextReadError::InvalidColumns(format!(
+                            "missing column {}",
+                            required.name()
+                        )));
+                    }
+                }
+                kept = found
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, c)| *c != Column::Skip)
+                    .collect();
+            }
+
+            let expected = kept.last().map_or(0, |(i, _)| i + 1);
+            if numbers.len() < expected {
+                return Err(TextReadError::InvalidLine {
+                    line: line_number,
+                    error_msg: format!(
+                        "expected at least {} values, got {}",
+                        expected,
+                        numbers.len()
+                    ),
+                });
+            }
+            values.extend(kept.iter().map(|(i, _)| numbers[*i]));
+        }
+
+        Ok(TextData {
+            columns: kept.into_iter().map(|(_, c)| c).collect(),
+            values,
+            color_scale: self.options.color_scale,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::formats::{
+        pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud,
+    };
+    use crate::xyz::{parse_columns, ColorScale};
+
+    #[test]
+    fn test_read_pts() {
+        let pts = "2\n1 2 3 -100 255 128 0\n4.5 5 6 0 0 0 10\n";
+        let text = read_text(pts.as_bytes(), &TextOptions::default(), true).unwrap();
+        assert_eq!(text.number_of_points(), 2);
+        let pc: PointCloud<PointXyzRgba> = text.into();
+        assert_eq!(
+            pc.points[0],
+            PointXyzRgba {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+                r: 255,
+                g: 128,
+                b: 0,
+                a: 255
+            }
+        );
+        assert_eq!([pc.points[1].x, pc.points[1].b as f32], [4.5, 10.0]);
+    }
+
+    #[test]
+    fn test_read_csv_with_header_and_unit_colors() {
+        let csv = "# exported\nx,y,z,red,green,blue,nx,ny,nz\n0,0,0,1,0.5,0,0,0,1\n";
+        let text = read_text(csv.as_bytes(), &TextOptions::default(), false).unwrap();
+        assert!(text.has_normals());
+        let pc: PointCloud<PointXyzRgbaNormal> = text.into();
+        let p = pc.points[0];
+        assert_eq!([p.r, p.g, p.b], [255, 128, 0]);
+        assert_eq!([p.nx, p.ny, p.nz], [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_auto_color_scale() {
+        let read = |xyz: &str, columns: &str| -> Vec<[u8; 3]> {
+            let options = TextOptions {
+                columns: Some(parse_columns(columns).unwrap()),
+                ..TextOptions::default()
+            };
+            let pc: PointCloud<PointXyzRgba> =
+                read_text(xyz.as_bytes(), &options, false).unwrap().into();
+            pc.points.iter().map(|p| [p.r, p.g, p.b]).collect()
+        };
+        // !! a dark frame without fractional colors is not taken for a white one
+        let dark = "0 0 0 0 1 0\n0 0 1 1 1 1\n";
+        assert_eq!(read(dark, "x,y,z,r,g,b"), vec![[0, 1, 0], [1, 1, 1]]);
+        let unit = "0 0 0 0 0.5 0\n0 0 1 1 1 1\n";
+        assert_eq!(
+            read(unit, "x,y,z,r,g,b"),
+            vec![[0, 128, 0], [255, 255, 255]]
+        );
+        // !! intensities do not change the range of the colors, nor the other way round
+        let intensity = "0 0 0 1000 0 0.5 1\n";
+        assert_eq!(read(intensity, "x,y,z,i,r,g,b"), vec![[0, 128, 255]]);
+        assert_eq!(read("0 0 0 0.25\n", "x,y,z,i"), vec![[64, 64, 64]]);
+    }
+
+    #[test]
+    fn test_read_with_options() {
+        let options = TextOptions {
+            columns: Some(parse_columns("_,z,y,x,r,g,b").unwrap()),
+            skip_lines: 2,
+            color_scale: ColorScale::Byte,
+        };
+        let xyz = "some\nheader\n7 1 2 3 1 1 0\n";
+        let pc: PointCloud<PointXyzRgba> =
+            read_text(xyz.as_bytes(), &options, false).unwrap().into();
+        let p = pc.points[0];
+        assert_eq!([p.x, p.y, p.z], [3.0, 2.0, 1.0]);
+        assert_eq!([p.r, p.g, p.b], [1, 1, 0]);
+
+        assert!(matches!(
+            read_text("1 2\n".as_bytes(), &TextOptions::default(), false),
+            Err(TextReadError::InvalidColumns(_))
+        ));
+        assert!(matches!(
+            read_text("1 2 3\n1 2 z\n".as_bytes(), &TextOptions::default(), false),
+            Err(TextReadError::InvalidLine { line: 2, .. })
+        ));
+        assert!(parse_columns("x,y").is_err());
+        assert!(parse_columns("x,y,z,x").is_err());
+    }
+}
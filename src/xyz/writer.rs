@@ -0,0 +1,252 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::formats::{
+    pointxyzrgba::PointXyzRgba, pointxyzrgbanormal::PointXyzRgbaNormal, PointCloud,
+};
+use crate::xyz::data_types::{ColorScale, Column, TextOptions};
+
+type IOResult = Result<(), std::io::Error>;
+
+/// Layout of a text file, given by its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextFormat {
+    /// Values separated by spaces, also used for `.txt`
+    Xyz,
+    /// Values separated by commas, after a header naming the columns
+    Csv,
+    /// Values separated by spaces, after the number of points
+    Pts,
+}
+
+impl TextFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "xyz" | "txt" => Some(TextFormat::Xyz),
+            "csv" => Some(TextFormat::Csv),
+            "pts" => Some(TextFormat::Pts),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the file is read as a text file, from its extension
+pub fn is_text_file(file: &Path) -> bool {
+    file.extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(TextFormat::from_extension)
+        .is_some()
+}
+
+/// Points written as the rows of a text file
+pub trait TextPoint {
+    /// Columns written when none are given
+    fn default_columns() -> Vec<Column>;
+
+    /// The value of the column, `None` if the point does not have it
+    fn value(&self, column: Column) -> Option<f32>;
+}
+
+impl TextPoint for PointXyzRgba {
+    fn default_columns() -> Vec<Column> {
+        vec![
+            Column::X,
+            Column::Y,
+            Column::Z,
+            Column::R,
+            Column::G,
+            Column::B,
+        ]
+    }
+
+    fn value(&self, column: Column) -> Option<f32> {
+        match column {
+            Column::X => Some(self.x),
+            Column::Y => Some(self.y),
+            Column::Z => Some(self.z),
+            Column::R => Some(self.r as f32),
+            Column::G => Some(self.g as f32),
+            Column::B => Some(self.b as f32),
+            Column::A => Some(self.a as f32),
+            // !! the luma of the color
+            Column::Intensity => Some(
+                (0.299 * self.r as f32 + 0.587 * self.g as f32 + 0.114 * self.b as f32).round(),
+            ),
+            Column::Skip => Some(0.0),
+            Column::Nx | Column::Ny | Column::Nz => None,
+        }
+    }
+}
+
+impl TextPoint for PointXyzRgbaNormal {
+    fn default_columns() -> Vec<Column> {
+        let mut columns = PointXyzRgba::default_columns();
+        columns.extend([Column::Nx, Column::Ny, Column::Nz]);
+        columns
+    }
+
+    fn value(&self, column: Column) -> Option<f32> {
+        match column {
+            Column::Nx => Some(self.nx),
+            Column::Ny => Some(self.ny),
+            Column::Nz => Some(self.nz),
+            _ => PointXyzRgba {
+                x: self.x,
+                y: self.y,
+                z: self.z,
+                r: self.r,
+                g: self.g,
+                b: self.b,
+                a: self.a,
+            }
+            .value(column),
+        }
+    }
+}
+
+/// Writes the point cloud into the file, laid out as given by the extension of the path
+pub fn write_text_file<T: TextPoint, P: AsRef<Path>>(
+    pc: &PointCloud<T>,
+    options: &TextOptions,
+    p: P,
+) -> IOResult {
+    let format = p
+        .as_ref()
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(TextFormat::from_extension)
+        .unwrap_or(TextFormat::Xyz);
+    let file = File::create(p)?;
+    let mut writer = BufWriter::new(file);
+    write_text(pc, options, format, &mut writer)?;
+    writer.flush()
+}
+
+/// Writes the point cloud into the provided writer. Colors are written from 0 to 1 with
+/// [ColorScale::Unit], from 0 to 255 otherwise.
+pub fn write_text<T: TextPoint, W: Write>(
+    pc: &PointCloud<T>,
+    options: &TextOptions,
+    format: TextFormat,
+    writer: &mut W,
+) -> IOResult {
+    let columns = options.columns.clone().unwrap_or_else(T::default_columns);
+    let separator = match format {
+        TextFormat::Csv => ",",
+        TextFormat::Xyz | TextFormat::Pts => " ",
+    };
+    match format {
+        TextFormat::Csv => {
+            let names = columns.iter().map(Column::name).collect::<Vec<_>>();
+            writeln!(writer, "{}", names.join(separator))?;
+        }
+        TextFormat::Pts => writeln!(writer, "{}", pc.points.len())?,
+        TextFormat::Xyz => {}
+    }
+
+    let unit_colors = options.color_scale == ColorScale::Unit;
+    let mut line = String::new();
+    for p in &pc.points {
+        line.clear();
+        for (i, column) in columns.iter().enumerate() {
+            let value = p.value(*column).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("The points have no column {}", column.name()),
+                )
+            })?;
+            if i > 0 {
+                line.push_str(separator);
+            }
+            let is_color = matches!(
+                column,
+                Column::R | Column::G | Column::B | Column::A | Column::Intensity
+            );
+            if is_color && unit_colors {
+                line.push_str(&(value / 255.0).to_string());
+            } else {
+                line.push_str(&value.to_string());
+            }
+        }
+        writeln!(writer, "{}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::xyz::{parse_columns, read_text};
+
+    fn cloud() -> PointCloud<PointXyzRgbaNormal> {
+        let points = vec![PointXyzRgbaNormal {
+            x: 1.5,
+            y: -2.0,
+            z: 3.0,
+            r: 255,
+            g: 0,
+            b: 51,
+            a: 255,
+            nx: 0.0,
+            ny: 1.0,
+            nz: 0.0,
+        }];
+        PointCloud {
+            number_of_points: points.len(),
+            points,
+        }
+    }
+
+    #[test]
+    fn test_write_formats() {
+        let pc = cloud();
+        let mut bytes = vec![];
+        write_text(&pc, &TextOptions::default(), TextFormat::Csv, &mut bytes).unwrap();
+        assert_eq!(
+            String::from_utf8(bytes.clone()).unwrap(),
+            "x,y,z,r,g,b,nx,ny,nz\n1.5,-2,3,255,0,51,0,1,0\n"
+        );
+        let read: PointCloud<PointXyzRgbaNormal> =
+            read_text(&bytes[..], &TextOptions::default(), false)
+                .unwrap()
+                .into();
+        assert_eq!(read.points, pc.points);
+
+        let options = TextOptions {
+            columns: Some(parse_columns("x,y,z,r,g,b").unwrap()),
+            color_scale: ColorScale::Unit,
+            ..TextOptions::default()
+        };
+        let mut bytes = vec![];
+        write_text(&pc, &options, TextFormat::Pts, &mut bytes).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), "1\n1.5 -2 3 1 0 0.2\n");
+    }
+
+    #[test]
+    fn test_write_missing_column() {
+        let pc = cloud();
+        let points = pc
+            .points
+            .iter()
+            .map(|p| PointXyzRgba {
+                x: p.x,
+                y: p.y,
+                z: p.z,
+                r: p.r,
+                g: p.g,
+                b: p.b,
+                a: p.a,
+            })
+            .collect::<Vec<_>>();
+        let pc = PointCloud {
+            number_of_points: points.len(),
+            points,
+        };
+        let options = TextOptions {
+            columns: Some(parse_columns("x,y,z,nx").unwrap()),
+            ..TextOptions::default()
+        };
+        assert!(write_text(&pc, &options, TextFormat::Xyz, &mut vec![]).is_err());
+    }
+}